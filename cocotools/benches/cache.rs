@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use cocotools::coco::cache;
+use cocotools::coco::streaming;
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+const IMAGE_FOLDER: &str = "../data_samples/coco_25k/images";
+
+fn load_benchmark(c: &mut Criterion) {
+    let cache_path =
+        std::env::temp_dir().join(format!("cocotools-bench-{}.cocobin", std::process::id()));
+    let dataset = streaming::load(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+    cache::save(&dataset, &cache_path).unwrap();
+
+    let mut group = c.benchmark_group("load");
+    group.bench_function("json", |b| {
+        b.iter(|| streaming::load(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap())
+    });
+    group.bench_function("cache", |b| {
+        b.iter(|| cache::load(cache_path.as_path(), Path::new(IMAGE_FOLDER)).unwrap())
+    });
+    group.finish();
+    std::fs::remove_file(cache_path).unwrap();
+}
+
+criterion_group!(benches, load_benchmark);
+criterion_main!(benches);
@@ -1,21 +1,43 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
-use crate::mask::conversions::Segmentation;
+use cocotools::coco::filter::Pattern;
+use cocotools::mask::conversions::Segmentation;
 
 #[derive(Parser)]
 #[command(author, version, about)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    #[command(flatten)]
+    pub save: SaveArgs,
 }
 
+/// Formatting of the json files written by the commands.
+#[derive(Args)]
+pub struct SaveArgs {
+    /// Write the json output without any whitespace instead of pretty printing it.
+    #[arg(long, global = true)]
+    pub compact: bool,
+    /// Number of spaces per indentation level of the pretty printed json output.
+    #[arg(long, global = true, default_value_t = 2)]
+    pub indent: usize,
+    /// Round the polygons and bounding boxes coordinates to this number of decimals.
+    #[arg(long, global = true)]
+    pub float_precision: Option<u32>,
+    /// Also sort the licenses and the category ids lists of the images, so that the output only depends on the dataset content.
+    #[arg(long, global = true)]
+    pub canonical: bool,
+}
+
+// The commands are only parsed once, their size does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     /// Visualize COCO labels.
     Visualize {
-        /// Path to the COCO json annotation file.
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
         annotations_file: PathBuf,
         /// Path to the folder with the images.
         image_folder: PathBuf,
@@ -26,12 +48,122 @@ pub enum Commands {
 
     /// Convert the segmentation format of the labels in a COCO annotation file.
     ConvertSegmentation {
-        /// Path to the COCO json annotation file.
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
         annotations_path: PathBuf,
         target_segmentation: Segmentation,
-        /// Path to where the output will be saved (for example "output/annotation_rle.json"). If not given, the conversion is done in place.
+        /// Path to where the output will be saved (for example "output/annotation_rle.json", or "output/annotation_rle.json.gz" to compress it). If not given, the conversion is done in place.
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+        /// Tolerance (in pixels) of the Douglas–Peucker simplification applied when converting masks to polygons.
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+        /// Maximum number of vertices of each polygon when converting masks to polygons.
+        #[arg(long)]
+        max_vertices: Option<usize>,
+        /// Number of smoothing iterations applied when converting masks to polygons.
+        #[arg(long, default_value_t = 0)]
+        smoothing: u32,
+    },
+
+    /// Check a COCO annotation file for invalid or inconsistent data.
+    Validate {
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
+        annotations_path: PathBuf,
+        /// Path to the folder with the images. If given, the image files are checked against the annotation file.
+        #[arg(short, long)]
+        image_folder: Option<PathBuf>,
+        /// Output the problems found as json instead of human readable text.
+        #[arg(long)]
+        json: bool,
+        /// Maximum relative difference allowed between the area of an annotation and the area of its segmentation.
+        #[arg(long, default_value_t = 0.05)]
+        area_tolerance: f64,
+    },
+
+    /// Fix the common problems of a COCO annotation file (bounding boxes outside of the image, inconsistent areas, empty annotations, etc.).
+    Fix {
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
+        annotations_path: PathBuf,
+        /// Path to where the output will be saved. If not given, the fix is done in place.
         #[arg(short, long)]
         output_path: Option<PathBuf>,
+        /// Path to a json file where the list of changes will be saved.
+        #[arg(long)]
+        change_log: Option<PathBuf>,
+        /// Maximum relative difference allowed between the area of an annotation and the area of its segmentation.
+        #[arg(long, default_value_t = 0.05)]
+        area_tolerance: f64,
+    },
+
+    /// Print statistics about a COCO dataset.
+    Stats {
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
+        annotations_path: PathBuf,
+        /// Output the statistics as json instead of tables.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Keep only the annotations and images of a COCO dataset that satisfy the given conditions.
+    Filter {
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
+        annotations_path: PathBuf,
+        /// Path to where the filtered dataset will be saved.
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Ids of the categories to keep (for example "1,3").
+        #[arg(long, value_delimiter = ',')]
+        cat_ids: Option<Vec<u32>>,
+        /// Names of the categories to keep (for example "person,car"), in addition to the ones selected with --cat-ids.
+        #[arg(long, value_delimiter = ',')]
+        cat_names: Option<Vec<String>>,
+        /// Minimum area of the annotations.
+        #[arg(long)]
+        min_area: Option<f64>,
+        /// Maximum area of the annotations.
+        #[arg(long)]
+        max_area: Option<f64>,
+        /// Keep only the crowd annotations (true) or only the non-crowd annotations (false).
+        #[arg(long)]
+        iscrowd: Option<bool>,
+        /// Minimum width and height of the bounding boxes.
+        #[arg(long, num_args = 2, value_names = ["WIDTH", "HEIGHT"])]
+        min_bbox_size: Option<Vec<f64>>,
+        /// Maximum width and height of the bounding boxes.
+        #[arg(long, num_args = 2, value_names = ["WIDTH", "HEIGHT"])]
+        max_bbox_size: Option<Vec<f64>>,
+        /// Ids of the images to keep (for example "17627,174482").
+        #[arg(long, value_delimiter = ',')]
+        img_ids: Option<Vec<u64>>,
+        /// Glob pattern the file name of the images must match (for example "train/*.jpg").
+        #[arg(long)]
+        file_name_glob: Option<Pattern>,
+        /// Minimum number of annotations per image (after filtering the annotations).
+        #[arg(long)]
+        min_anns: Option<usize>,
+        /// Maximum number of annotations per image (after filtering the annotations).
+        #[arg(long)]
+        max_anns: Option<usize>,
+        /// Remove the images left without annotations.
+        #[arg(long)]
+        drop_empty_images: bool,
+    },
+
+    /// Merge, rename or drop the categories of a COCO dataset according to a json mapping file.
+    RemapCategories {
+        /// Path to the COCO json annotation file (optionally compressed, .json.gz or .json.zst).
+        annotations_path: PathBuf,
+        /// Path to the json mapping file (see the documentation of `CategoryRemapping` for its format).
+        mapping_path: PathBuf,
+        /// Path to where the remapped dataset will be saved.
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Drop the categories (and their annotations) that are not in the mapping file.
+        #[arg(long)]
+        drop_unmapped: bool,
+        /// Renumber the categories with contiguous ids starting from 1.
+        #[arg(long)]
+        renumber: bool,
     },
     // Split a COCO dataset in two.
     // Convert to/from PascalVOC, SOLO.
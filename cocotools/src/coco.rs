@@ -1,7 +1,21 @@
 //! Module containing the data annotation formats.
 
+pub mod backend;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod compression;
+pub mod filter;
+pub mod lvis;
 pub mod object_detection;
 #[cfg(feature = "pyo3")]
 pub mod pyo3;
+pub mod remap;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod streaming;
+#[cfg(feature = "parquet")]
+pub mod tables;
+pub mod validation;
 
 pub use crate::coco::object_detection::BTreemapDataset as COCO;
@@ -0,0 +1,524 @@
+//! Module containing the traits implemented by the different dataset storage backends.
+//!
+//! The visualization, conversion and evaluation functions are generic over these traits, so that they can be used with
+//! an in-memory [`BTreemapDataset`] as well as with a dataset stored elsewhere (a SQLite database for example) without first
+//! copying it into a [`BTreemapDataset`].
+//!
+//! Backends that store the elements in memory return them borrowed, the others return them owned, hence the [`Cow`]s.
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Category, ExtraFields, Image, Info, License, Segmentation,
+};
+use crate::errors::{CocoError, MissingIdError};
+
+/// Read operations on a COCO dataset, see [`BTreemapDataset`] for the semantics of each method.
+pub trait DatasetView {
+    /// Error returned when the dataset cannot be read, or when an id is not in the dataset.
+    type Error: std::error::Error + From<MissingIdError> + 'static;
+
+    /// Returns the folder containing the images of the dataset.
+    fn image_folder(&self) -> &Path;
+
+    /// Return the annotation corresponding to the given annotation id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry in the dataset corresponding to `ann_id`.
+    fn get_ann(&self, ann_id: u64) -> Result<Cow<'_, Annotation>, Self::Error>;
+
+    /// Returns all the annotations of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_anns(&self) -> Result<Vec<Cow<'_, Annotation>>, Self::Error>;
+
+    /// Return the category corresponding to the given category id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `cat_id`.
+    fn get_cat(&self, cat_id: u32) -> Result<Cow<'_, Category>, Self::Error>;
+
+    /// Returns all the categories of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_cats(&self) -> Result<Vec<Cow<'_, Category>>, Self::Error>;
+
+    /// Return the image entry corresponding to the given image id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `img_id`.
+    fn get_img(&self, img_id: u64) -> Result<Cow<'_, Image>, Self::Error>;
+
+    /// Returns all the images of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_imgs(&self) -> Result<Vec<Cow<'_, Image>>, Self::Error>;
+
+    /// Returns the information about the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_info(&self) -> Result<Cow<'_, Info>, Self::Error>;
+
+    /// Returns the licenses of the images of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_licenses(&self) -> Result<Cow<'_, [License]>, Self::Error>;
+
+    /// Returns the fields of the annotation file that are not part of the COCO format.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_extra_fields(&self) -> Result<Cow<'_, ExtraFields>, Self::Error>;
+
+    /// Return the annotations for the given image id, the order depends on the backend.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `img_id`.
+    fn get_img_anns(&self, img_id: u64) -> Result<Vec<Cow<'_, Annotation>>, Self::Error>;
+
+    /// Return the ids of the annotations satisfying all the given conditions, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_ann_ids(
+        &self,
+        img_ids: &[u64],
+        cat_ids: &[u32],
+        area_range: Option<(f64, f64)>,
+        iscrowd: Option<bool>,
+    ) -> Result<Vec<u64>, Self::Error>;
+
+    /// Return the ids of the categories satisfying all the given conditions, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_cat_ids(
+        &self,
+        cat_names: &[&str],
+        sup_names: &[&str],
+        cat_ids: &[u32],
+    ) -> Result<Vec<u32>, Self::Error>;
+
+    /// Return the ids of the images satisfying all the given conditions, sorted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn get_img_ids(&self, img_ids: &[u64], cat_ids: &[u32]) -> Result<Vec<u64>, Self::Error>;
+
+    /// Return an image id that is not used in the dataset (the largest image id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn next_img_id(&self) -> Result<u64, Self::Error>;
+
+    /// Return an annotation id that is not used in the dataset (the largest annotation id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn next_ann_id(&self) -> Result<u64, Self::Error>;
+
+    /// Return a category id that is not used in the dataset (the largest category id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the dataset cannot be read.
+    fn next_cat_id(&self) -> Result<u32, Self::Error>;
+}
+
+/// Edit operations on a COCO dataset, see [`BTreemapDataset`] for the semantics of each method.
+pub trait DatasetMut: DatasetView {
+    /// Add an image to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an image with the same id in the dataset.
+    fn add_img(&mut self, img: Image) -> Result<(), Self::Error>;
+
+    /// Replace the image entry with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    fn update_img(&mut self, img: Image) -> Result<Image, Self::Error>;
+
+    /// Remove the image and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    fn remove_img(&mut self, img_id: u64) -> Result<(Image, Vec<Annotation>), Self::Error>;
+
+    /// Add an annotation to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an annotation with the same id in the dataset,
+    /// or if the image or category of the annotation is not in the dataset.
+    fn add_ann(&mut self, ann: Annotation) -> Result<(), Self::Error>;
+
+    /// Replace the annotation with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset,
+    /// or if the image or category of the new annotation is not in the dataset.
+    fn update_ann(&mut self, ann: Annotation) -> Result<Annotation, Self::Error>;
+
+    /// Replace the annotations with the same ids, all at once: if one of them cannot be updated, none of them is.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset,
+    /// or if the image or category of one of the new annotations is not in the dataset.
+    fn update_anns(&mut self, anns: Vec<Annotation>) -> Result<(), Self::Error>;
+
+    /// Replace the segmentations of the annotations with the given ids, all at once: if one of them cannot be updated,
+    /// none of them is. The other fields of the annotations are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset.
+    fn update_segmentations(
+        &mut self,
+        segmentations: Vec<(u64, Segmentation)>,
+    ) -> Result<(), Self::Error>;
+
+    /// Remove the annotation from the dataset and return it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset.
+    fn remove_ann(&mut self, ann_id: u64) -> Result<Annotation, Self::Error>;
+
+    /// Add a category to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already a category with the same id in the dataset.
+    fn add_cat(&mut self, cat: Category) -> Result<(), Self::Error>;
+
+    /// Replace the category with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    fn update_cat(&mut self, cat: Category) -> Result<Category, Self::Error>;
+
+    /// Remove the category and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    fn remove_cat(&mut self, cat_id: u32) -> Result<(Category, Vec<Annotation>), Self::Error>;
+}
+
+impl DatasetView for BTreemapDataset {
+    type Error = CocoError;
+
+    fn image_folder(&self) -> &Path {
+        &self.image_folder
+    }
+
+    fn get_ann(&self, ann_id: u64) -> Result<Cow<'_, Annotation>, Self::Error> {
+        Ok(Cow::Borrowed(self.get_ann(ann_id)?))
+    }
+
+    fn get_anns(&self) -> Result<Vec<Cow<'_, Annotation>>, Self::Error> {
+        Ok(self.anns.values().map(Cow::Borrowed).collect())
+    }
+
+    fn get_cat(&self, cat_id: u32) -> Result<Cow<'_, Category>, Self::Error> {
+        Ok(Cow::Borrowed(self.get_cat(cat_id)?))
+    }
+
+    fn get_cats(&self) -> Result<Vec<Cow<'_, Category>>, Self::Error> {
+        Ok(self.cats.values().map(Cow::Borrowed).collect())
+    }
+
+    fn get_img(&self, img_id: u64) -> Result<Cow<'_, Image>, Self::Error> {
+        Ok(Cow::Borrowed(self.get_img(img_id)?))
+    }
+
+    fn get_imgs(&self) -> Result<Vec<Cow<'_, Image>>, Self::Error> {
+        Ok(self.imgs.values().map(Cow::Borrowed).collect())
+    }
+
+    fn get_info(&self) -> Result<Cow<'_, Info>, Self::Error> {
+        Ok(Cow::Borrowed(&self.info))
+    }
+
+    fn get_licenses(&self) -> Result<Cow<'_, [License]>, Self::Error> {
+        Ok(Cow::Borrowed(&self.licenses))
+    }
+
+    fn get_extra_fields(&self) -> Result<Cow<'_, ExtraFields>, Self::Error> {
+        Ok(Cow::Borrowed(&self.extra))
+    }
+
+    fn get_img_anns(&self, img_id: u64) -> Result<Vec<Cow<'_, Annotation>>, Self::Error> {
+        Ok(self
+            .get_img_anns(img_id)?
+            .into_iter()
+            .map(Cow::Borrowed)
+            .collect())
+    }
+
+    fn get_ann_ids(
+        &self,
+        img_ids: &[u64],
+        cat_ids: &[u32],
+        area_range: Option<(f64, f64)>,
+        iscrowd: Option<bool>,
+    ) -> Result<Vec<u64>, Self::Error> {
+        Ok(self.get_ann_ids(img_ids, cat_ids, area_range, iscrowd))
+    }
+
+    fn get_cat_ids(
+        &self,
+        cat_names: &[&str],
+        sup_names: &[&str],
+        cat_ids: &[u32],
+    ) -> Result<Vec<u32>, Self::Error> {
+        Ok(self.get_cat_ids(cat_names, sup_names, cat_ids))
+    }
+
+    fn get_img_ids(&self, img_ids: &[u64], cat_ids: &[u32]) -> Result<Vec<u64>, Self::Error> {
+        Ok(self.get_img_ids(img_ids, cat_ids))
+    }
+
+    fn next_img_id(&self) -> Result<u64, Self::Error> {
+        Ok(self.next_img_id())
+    }
+
+    fn next_ann_id(&self) -> Result<u64, Self::Error> {
+        Ok(self.next_ann_id())
+    }
+
+    fn next_cat_id(&self) -> Result<u32, Self::Error> {
+        Ok(self.next_cat_id())
+    }
+}
+
+impl DatasetMut for BTreemapDataset {
+    fn add_img(&mut self, img: Image) -> Result<(), Self::Error> {
+        Ok(self.add_img(img)?)
+    }
+
+    fn update_img(&mut self, img: Image) -> Result<Image, Self::Error> {
+        Ok(self.update_img(img)?)
+    }
+
+    fn remove_img(&mut self, img_id: u64) -> Result<(Image, Vec<Annotation>), Self::Error> {
+        Ok(self.remove_img(img_id)?)
+    }
+
+    fn add_ann(&mut self, ann: Annotation) -> Result<(), Self::Error> {
+        Ok(self.add_ann(ann)?)
+    }
+
+    fn update_ann(&mut self, ann: Annotation) -> Result<Annotation, Self::Error> {
+        Ok(self.update_ann(ann)?)
+    }
+
+    fn update_anns(&mut self, anns: Vec<Annotation>) -> Result<(), Self::Error> {
+        Ok(self.update_anns(anns)?)
+    }
+
+    fn update_segmentations(
+        &mut self,
+        segmentations: Vec<(u64, Segmentation)>,
+    ) -> Result<(), Self::Error> {
+        Ok(self.update_segmentations(segmentations)?)
+    }
+
+    fn remove_ann(&mut self, ann_id: u64) -> Result<Annotation, Self::Error> {
+        Ok(self.remove_ann(ann_id)?)
+    }
+
+    fn add_cat(&mut self, cat: Category) -> Result<(), Self::Error> {
+        Ok(self.add_cat(cat)?)
+    }
+
+    fn update_cat(&mut self, cat: Category) -> Result<Category, Self::Error> {
+        Ok(self.update_cat(cat)?)
+    }
+
+    fn remove_cat(&mut self, cat_id: u32) -> Result<(Category, Vec<Annotation>), Self::Error> {
+        Ok(self.remove_cat(cat_id)?)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::Segmentation as AnnSegmentation;
+    use crate::errors::MutationError;
+    use crate::mask::conversions::{convert_coco_segmentation, Segmentation};
+
+    const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+    const IMAGE_FOLDER: &str = "../data_samples/coco_25k/images";
+
+    /// Moves an image's annotations to a new category, only through the traits.
+    fn relabel<D: DatasetMut>(dataset: &mut D, img_id: u64) -> Result<u32, D::Error> {
+        let cat_id = dataset.next_cat_id()?;
+        dataset.add_cat(Category {
+            id: cat_id,
+            name: "relabeled".to_string(),
+            ..Default::default()
+        })?;
+        let anns: Vec<Annotation> = dataset
+            .get_img_anns(img_id)?
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect();
+        for mut ann in anns {
+            ann.category_id = cat_id;
+            dataset.update_ann(ann)?;
+        }
+        Ok(cat_id)
+    }
+
+    /// Updates all the annotations of an image, with the last one moved to a category that does not exist.
+    fn update_with_missing_cat<D: DatasetMut>(
+        dataset: &mut D,
+        img_id: u64,
+    ) -> Result<(), D::Error> {
+        let mut anns: Vec<Annotation> = dataset
+            .get_img_anns(img_id)?
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect();
+        for ann in &mut anns {
+            ann.area += 1.0;
+        }
+        if let Some(ann) = anns.last_mut() {
+            ann.category_id = dataset.next_cat_id()?;
+        }
+        dataset.update_anns(anns)
+    }
+
+    #[test]
+    fn btreemap_view() {
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let view: &dyn DatasetView<Error = CocoError> = &dataset;
+        assert_eq!(view.image_folder(), Path::new(IMAGE_FOLDER));
+        assert_eq!(view.get_anns().unwrap().len(), dataset.get_anns().len());
+        assert_eq!(
+            view.get_img(17627).unwrap().as_ref(),
+            dataset.get_img(17627).unwrap()
+        );
+        assert_eq!(
+            view.get_img_ids(&[], &[1]).unwrap(),
+            dataset.get_img_ids(&[], &[1])
+        );
+        assert!(matches!(
+            view.get_img_anns(0),
+            Err(CocoError::MissingId(MissingIdError::Image(0)))
+        ));
+    }
+
+    #[test]
+    fn btreemap_mut() {
+        let mut dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let nb_anns = dataset.get_img_anns(17627).unwrap().len();
+        let cat_id = relabel(&mut dataset, 17627).unwrap();
+        assert_eq!(
+            dataset.get_ann_ids(&[], &[cat_id], None, None).len(),
+            nb_anns
+        );
+        assert_eq!(dataset.get_img_ids(&[], &[cat_id]), vec![17627]);
+        assert!(matches!(
+            relabel(&mut dataset, 0),
+            Err(CocoError::MissingId(MissingIdError::Image(0)))
+        ));
+    }
+
+    #[test]
+    fn btreemap_failed_batch_update() {
+        let mut dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let original = dataset.clone();
+        assert!(matches!(
+            update_with_missing_cat(&mut dataset, 17627),
+            Err(CocoError::Mutation(MutationError::MissingId(
+                MissingIdError::Category(_)
+            )))
+        ));
+        assert_eq!(dataset, original);
+    }
+
+    #[test]
+    fn btreemap_failed_segmentation_update() {
+        let mut dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let original = dataset.clone();
+        let segmentations = dataset
+            .get_anns()
+            .iter()
+            .map(|ann| (ann.id, AnnSegmentation::Polygons(Vec::new())))
+            .chain([(0, AnnSegmentation::Polygons(Vec::new()))])
+            .collect();
+        assert!(matches!(
+            DatasetMut::update_segmentations(&mut dataset, segmentations),
+            Err(CocoError::MissingId(MissingIdError::Annotation(0)))
+        ));
+        assert_eq!(dataset, original);
+    }
+
+    #[test]
+    fn convert_segmentation() {
+        let mut dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let nb_anns = dataset.get_anns().len();
+        convert_coco_segmentation(&mut dataset, Segmentation::CocoRle).unwrap();
+        assert_eq!(dataset.get_anns().len(), nb_anns);
+        assert!(dataset
+            .get_anns()
+            .iter()
+            .all(|ann| matches!(ann.segmentation, AnnSegmentation::CocoRle(_))));
+
+        // The polygons are stored with the size of their image, as when loading them.
+        convert_coco_segmentation(&mut dataset, Segmentation::Polygons).unwrap();
+        assert!(dataset
+            .get_anns()
+            .iter()
+            .all(|ann| matches!(ann.segmentation, AnnSegmentation::PolygonsRS(_))));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_backend() {
+        use crate::coco::sqlite::SqliteDataset;
+
+        let mut dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let mut sqlite_dataset = SqliteDataset::open_in_memory(IMAGE_FOLDER).unwrap();
+        sqlite_dataset.import(&dataset).unwrap();
+
+        relabel(&mut dataset, 17627).unwrap();
+        relabel(&mut sqlite_dataset, 17627).unwrap();
+        convert_coco_segmentation(&mut dataset, Segmentation::Rle).unwrap();
+        convert_coco_segmentation(&mut sqlite_dataset, Segmentation::Rle).unwrap();
+        assert_eq!(sqlite_dataset.to_dataset().unwrap(), dataset);
+
+        assert!(update_with_missing_cat(&mut sqlite_dataset, 17627).is_err());
+        assert_eq!(sqlite_dataset.to_dataset().unwrap(), dataset);
+    }
+}
@@ -0,0 +1,556 @@
+//! Module containing a compact binary format for datasets, used to cache the parsed json annotation files.
+//!
+//! The files start with a magic number and the version of the format, followed by a header and the dataset, both encoded with
+//! [MessagePack](https://msgpack.org/).
+//!
+//! The images, annotations and categories are stored as arrays of their fields (without the field names), and the
+//! segmentations as they are in memory (polygons with their size), so that decoding the dataset is a single pass without
+//! any conversion.
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Bbox, Category, CocoRle, ExtraFields, Frequency, Image, Info,
+    License, Polygons, PolygonsRS, Rle, Segmentation,
+};
+use crate::coco::streaming::{self, Seq};
+use crate::errors::{CacheError, LoadingError};
+
+const MAGIC: &[u8; 8] = b"COCOBIN\0";
+/// Version of the binary format, to increment whenever the serialization of the dataset changes.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+struct Header {
+    /// Json file the dataset was loaded from, only set for the cache files.
+    source: Option<Source>,
+}
+
+/// Json file, with its size and modification time, used to detect when a cache file is outdated.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Source {
+    path: PathBuf,
+    len: u64,
+    /// Seconds and nanoseconds since the unix epoch.
+    modified: Option<(u64, u32)>,
+}
+
+impl Source {
+    fn new(path: &Path) -> io::Result<Self> {
+        let path = fs::canonicalize(path)?;
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| (modified.as_secs(), modified.subsec_nanos()));
+        Ok(Self {
+            path,
+            len: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Loads the dataset from the binary cache in `cache_dir` if it is up to date, otherwise loads the json annotation file and
+/// (re)builds the cache.
+///
+/// The cache is rebuilt when the size or the modification time of the json file changes.
+///
+/// # Errors
+///
+/// Will return `Err` if the json file cannot be loaded, or if the cache file cannot be written.
+/// A cache file that cannot be read is rebuilt.
+pub fn load_cached<P: AsRef<Path>>(
+    annotations_path: P,
+    image_folder: P,
+    cache_dir: P,
+) -> Result<BTreemapDataset, CacheError> {
+    let annotations_path = annotations_path.as_ref();
+    let source = Source::new(annotations_path)
+        .map_err(|err| LoadingError::Read(err, annotations_path.to_path_buf()))?;
+    let cache_path = cache_path(&source, cache_dir.as_ref());
+
+    if let Ok(file) = fs::File::open(&cache_path) {
+        let cached = read(BufReader::new(file), &image_folder, &cache_path, |header| {
+            header.source.as_ref() == Some(&source)
+        });
+        if let Ok(Some(dataset)) = cached {
+            return Ok(dataset);
+        }
+    }
+
+    let dataset = streaming::load(annotations_path, image_folder.as_ref())?;
+    fs::create_dir_all(cache_dir.as_ref())
+        .map_err(|err| CacheError::Write(err, cache_path.clone()))?;
+    // Write to a temporary file first to never leave a partially written cache.
+    let tmp_path = cache_path.with_extension("tmp");
+    save_with_header(
+        &dataset,
+        &Header {
+            source: Some(source),
+        },
+        &tmp_path,
+    )?;
+    fs::rename(&tmp_path, &cache_path).map_err(|err| CacheError::Write(err, cache_path))?;
+    Ok(dataset)
+}
+
+/// Saves the dataset to the given path in the binary format.
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be created or written.
+pub fn save<P: AsRef<Path>>(dataset: &BTreemapDataset, output_path: P) -> Result<(), CacheError> {
+    save_with_header(dataset, &Header::default(), output_path.as_ref())
+}
+
+/// Loads a dataset saved in the binary format.
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be read, is not in the binary format (or in another version of it) or cannot be decoded.
+pub fn load<P: AsRef<Path>>(path: P, image_folder: P) -> Result<BTreemapDataset, CacheError> {
+    let path = path.as_ref();
+    let file = fs::File::open(path).map_err(|err| CacheError::Read(err, path.to_path_buf()))?;
+    let dataset = read(BufReader::new(file), &image_folder, path, |_| true)?;
+    // The header is always accepted.
+    dataset.ok_or_else(|| CacheError::NotACache(path.to_path_buf()))
+}
+
+/// Path of the cache file for the json file, named after the json file and a hash of its path.
+///
+/// The hash must not change between runs or Rust releases, otherwise the previous cache file would never be found again.
+fn cache_path(source: &Source, cache_dir: &Path) -> PathBuf {
+    let file_name = source
+        .path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let hash = fnv1a(source.path.to_string_lossy().as_bytes());
+    cache_dir.join(format!("{file_name}-{hash:016x}.cocobin"))
+}
+
+/// 64 bits [FNV-1a](https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function) hash of the bytes.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn save_with_header(
+    dataset: &BTreemapDataset,
+    header: &Header,
+    path: &Path,
+) -> Result<(), CacheError> {
+    let file = fs::File::create(path).map_err(|err| CacheError::Write(err, path.to_path_buf()))?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(MAGIC)
+        .and_then(|()| writer.write_all(&FORMAT_VERSION.to_le_bytes()))
+        .map_err(|err| CacheError::Write(err, path.to_path_buf()))?;
+
+    let mut serializer = rmp_serde::Serializer::new(&mut writer);
+    header
+        .serialize(&mut serializer)
+        .and_then(|()| encode(dataset).serialize(&mut serializer))
+        .map_err(|err| CacheError::Encode(err, path.to_path_buf()))?;
+    writer
+        .flush()
+        .map_err(|err| CacheError::Write(err, path.to_path_buf()))
+}
+
+/// Reads a file in the binary format, the dataset is only decoded if `accept` returns true for the header.
+fn read<R: Read, P: AsRef<Path>>(
+    mut reader: R,
+    image_folder: P,
+    path: &Path,
+    accept: impl Fn(&Header) -> bool,
+) -> Result<Option<BTreemapDataset>, CacheError> {
+    let mut magic = [0; 8];
+    let mut version = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .and_then(|()| reader.read_exact(&mut version))
+        .map_err(|err| CacheError::Read(err, path.to_path_buf()))?;
+    if &magic != MAGIC {
+        return Err(CacheError::NotACache(path.to_path_buf()));
+    }
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(CacheError::Version(
+            path.to_path_buf(),
+            version,
+            FORMAT_VERSION,
+        ));
+    }
+
+    let mut deserializer = rmp_serde::Deserializer::new(reader);
+    let header = Header::deserialize(&mut deserializer)
+        .map_err(|err| CacheError::Decode(err, path.to_path_buf()))?;
+    if !accept(&header) {
+        return Ok(None);
+    }
+    let dataset = EncodedDataset::deserialize(&mut deserializer)
+        .map_err(|err| CacheError::Decode(err, path.to_path_buf()))?;
+    Ok(Some(decode(dataset, image_folder)))
+}
+
+/// Fields of an image, in the order they are stored.
+type EncodedImage = (
+    u64,
+    u32,
+    u32,
+    String,
+    Option<u32>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<Vec<u32>>,
+    Option<Vec<u32>>,
+    ExtraFields,
+);
+
+/// Fields of an annotation, in the order they are stored.
+type EncodedAnnotation = (
+    u64,
+    u64,
+    u32,
+    EncodedSegmentation,
+    f64,
+    Bbox,
+    u32,
+    ExtraFields,
+);
+
+/// Fields of a category, in the order they are stored.
+type EncodedCategory = (
+    u32,
+    String,
+    Option<String>,
+    Option<Frequency>,
+    Option<Vec<String>>,
+    ExtraFields,
+);
+
+type EncodedDataset = (
+    Info,
+    Vec<License>,
+    ExtraFields,
+    Vec<EncodedImage>,
+    Vec<EncodedAnnotation>,
+    Vec<EncodedCategory>,
+);
+
+/// Tagged version of [`Segmentation`], which is untagged in json and would have to be buffered to be decoded.
+#[derive(Deserialize)]
+enum EncodedSegmentation {
+    Rle(Rle),
+    CocoRle(CocoRle),
+    Polygons(Polygons),
+    PolygonsRS(PolygonsRS),
+}
+
+/// Same as [`EncodedSegmentation`], without copying the segmentation.
+#[derive(Serialize)]
+enum SegmentationRef<'a> {
+    Rle(&'a Rle),
+    CocoRle(&'a CocoRle),
+    Polygons(&'a Polygons),
+    PolygonsRS(&'a PolygonsRS),
+}
+
+/// Serializable view of the dataset with the layout of [`EncodedDataset`].
+fn encode(dataset: &BTreemapDataset) -> impl Serialize + '_ {
+    let imgs = || {
+        dataset.imgs.values().map(|img| {
+            (
+                img.id,
+                img.width,
+                img.height,
+                &img.file_name,
+                img.license,
+                &img.flickr_url,
+                &img.coco_url,
+                &img.date_captured,
+                &img.neg_category_ids,
+                &img.not_exhaustive_category_ids,
+                &img.extra,
+            )
+        })
+    };
+    let anns = || {
+        dataset.anns.values().map(|ann| {
+            let segmentation = match &ann.segmentation {
+                Segmentation::Rle(rle) => SegmentationRef::Rle(rle),
+                Segmentation::CocoRle(rle) => SegmentationRef::CocoRle(rle),
+                Segmentation::Polygons(poly) => SegmentationRef::Polygons(poly),
+                Segmentation::PolygonsRS(poly) => SegmentationRef::PolygonsRS(poly),
+            };
+            (
+                ann.id,
+                ann.image_id,
+                ann.category_id,
+                segmentation,
+                ann.area,
+                &ann.bbox,
+                ann.iscrowd,
+                &ann.extra,
+            )
+        })
+    };
+    let cats = || {
+        dataset.cats.values().map(|cat| {
+            (
+                cat.id,
+                &cat.name,
+                &cat.supercategory,
+                cat.frequency,
+                &cat.synonyms,
+                &cat.extra,
+            )
+        })
+    };
+    (
+        &dataset.info,
+        &dataset.licenses,
+        &dataset.extra,
+        Seq(imgs),
+        Seq(anns),
+        Seq(cats),
+    )
+}
+
+/// Builds the dataset and its indexes from the decoded fields.
+fn decode<P: AsRef<Path>>(dataset: EncodedDataset, image_folder: P) -> BTreemapDataset {
+    let (info, licenses, extra, imgs, anns, cats) = dataset;
+    let imgs: BTreeMap<u64, Image> = imgs
+        .into_iter()
+        .map(|img| {
+            let (
+                id,
+                width,
+                height,
+                file_name,
+                license,
+                flickr_url,
+                coco_url,
+                date_captured,
+                neg_category_ids,
+                not_exhaustive_category_ids,
+                extra,
+            ) = img;
+            let img = Image {
+                id,
+                width,
+                height,
+                file_name,
+                license,
+                flickr_url,
+                coco_url,
+                date_captured,
+                neg_category_ids,
+                not_exhaustive_category_ids,
+                extra,
+            };
+            (id, img)
+        })
+        .collect();
+    let cats: BTreeMap<u32, Category> = cats
+        .into_iter()
+        .map(|(id, name, supercategory, frequency, synonyms, extra)| {
+            let cat = Category {
+                id,
+                name,
+                supercategory,
+                frequency,
+                synonyms,
+                extra,
+            };
+            (id, cat)
+        })
+        .collect();
+
+    // Same indexes as when loading the json file, with an entry for every image and category.
+    let mut img_to_anns: BTreeMap<u64, HashSet<u64>> = imgs
+        .keys()
+        .map(|img_id| (*img_id, HashSet::new()))
+        .collect();
+    let mut cat_to_anns: BTreeMap<u32, HashSet<u64>> = cats
+        .keys()
+        .map(|cat_id| (*cat_id, HashSet::new()))
+        .collect();
+    let mut cat_to_imgs = cat_to_anns.clone();
+    let anns: BTreeMap<u64, Annotation> = anns
+        .into_iter()
+        .map(|ann| {
+            let (id, image_id, category_id, segmentation, area, bbox, iscrowd, extra) = ann;
+            img_to_anns.entry(image_id).or_default().insert(id);
+            cat_to_anns.entry(category_id).or_default().insert(id);
+            cat_to_imgs.entry(category_id).or_default().insert(image_id);
+            let segmentation = match segmentation {
+                EncodedSegmentation::Rle(rle) => Segmentation::Rle(rle),
+                EncodedSegmentation::CocoRle(rle) => Segmentation::CocoRle(rle),
+                EncodedSegmentation::Polygons(poly) => Segmentation::Polygons(poly),
+                EncodedSegmentation::PolygonsRS(poly) => Segmentation::PolygonsRS(poly),
+            };
+            let ann = Annotation {
+                id,
+                image_id,
+                category_id,
+                segmentation,
+                area,
+                bbox,
+                iscrowd,
+                extra,
+            };
+            (id, ann)
+        })
+        .collect();
+
+    BTreemapDataset {
+        anns,
+        cats,
+        imgs,
+        img_to_anns,
+        cat_to_anns,
+        cat_to_imgs,
+        info,
+        licenses,
+        extra,
+        image_folder: image_folder.as_ref().to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_dir;
+
+    const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+    const IMAGE_FOLDER: &str = "../data_samples/coco_25k/images";
+
+    #[test]
+    fn cache_file_name_is_stable() {
+        let source = Source {
+            path: PathBuf::from("/data/annotations.json"),
+            len: 0,
+            modified: None,
+        };
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(
+            cache_path(&source, Path::new("cache")),
+            Path::new("cache").join(format!(
+                "annotations-{:016x}.cocobin",
+                fnv1a(b"/data/annotations.json")
+            ))
+        );
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let dir = test_dir("binary-roundtrip");
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let path = dir.join("annotations.cocobin");
+        save(&dataset, &path).unwrap();
+
+        assert_eq!(
+            load(path.as_path(), Path::new(IMAGE_FOLDER)).unwrap(),
+            dataset
+        );
+        assert!(matches!(
+            load(Path::new(ANNOTATIONS_PATH), Path::new(IMAGE_FOLDER)),
+            Err(CacheError::NotACache(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn binary_roundtrip_keeps_every_field() {
+        let dir = test_dir("binary-every-field");
+        let json = serde_json::json!({
+            "info": {"year": 2020, "source": "lvis"},
+            "licenses": [{"id": 1, "name": "CC BY 4.0", "url": ""}],
+            "images": [
+                {"id": 1, "width": 20, "height": 10, "file_name": "1.jpg", "license": 1, "coco_url": "1.jpg", "extra": {"a": [1, 2.5]}},
+                {"id": 2, "width": 20, "height": 10, "neg_category_ids": [2], "not_exhaustive_category_ids": []}
+            ],
+            "annotations": [
+                {"id": 1, "image_id": 1, "category_id": 1, "segmentation": [[1.0, 1.0, 5.0, 1.0, 5.0, 5.0]],
+                 "area": 8.0, "bbox": [1.0, 1.0, 4.0, 4.0], "iscrowd": 0, "attributes": {"occluded": true}},
+                {"id": 2, "image_id": 1, "category_id": 2, "segmentation": {"size": [10, 20], "counts": [10, 5, 185]},
+                 "area": 5.0, "bbox": [1.0, 0.0, 1.0, 5.0], "iscrowd": 1},
+                {"id": 3, "image_id": 2, "category_id": 2, "segmentation": {"size": [10, 20], "counts": "<5o5"},
+                 "area": 5.0, "bbox": [1.0, 0.0, 1.0, 5.0], "iscrowd": 1}
+            ],
+            "categories": [
+                {"id": 1, "name": "person"},
+                {"id": 2, "name": "dog", "supercategory": "animal", "frequency": "r", "synonyms": ["puppy"], "color": "red"}
+            ],
+            "split": "train"
+        });
+        let annotations_path = dir.join("annotations.json");
+        fs::write(&annotations_path, json.to_string()).unwrap();
+        let dataset = BTreemapDataset::new(&annotations_path, &dir).unwrap();
+        let path = dir.join("annotations.cocobin");
+        save(&dataset, &path).unwrap();
+
+        assert_eq!(load(&path, &dir).unwrap(), dataset);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cache_is_used_and_rebuilt() {
+        let dir = test_dir("load-cached");
+        let annotations_path = dir.join("annotations.json");
+        let cache_dir = dir.join("cache");
+        fs::copy(ANNOTATIONS_PATH, &annotations_path).unwrap();
+        let load = || {
+            load_cached(
+                annotations_path.as_path(),
+                Path::new(IMAGE_FOLDER),
+                &cache_dir,
+            )
+            .unwrap()
+        };
+
+        let dataset = load();
+        let cache_path = cache_path(&Source::new(&annotations_path).unwrap(), &cache_dir);
+        assert!(cache_path.is_file());
+
+        // An up to date cache is used instead of the json file.
+        let mut cached_dataset = dataset.clone();
+        let ann_id = cached_dataset.get_anns()[0].id;
+        cached_dataset.remove_ann(ann_id).unwrap();
+        let header = Header {
+            source: Some(Source::new(&annotations_path).unwrap()),
+        };
+        save_with_header(&cached_dataset, &header, &cache_path).unwrap();
+        assert_eq!(load(), cached_dataset);
+
+        // The cache is rebuilt when the json file changes, or when it cannot be read.
+        let mut content = fs::read_to_string(&annotations_path).unwrap();
+        content.push('\n');
+        fs::write(&annotations_path, content).unwrap();
+        assert_eq!(load(), dataset);
+        fs::write(&cache_path, b"COCOBIN\0garbage").unwrap();
+        assert_eq!(load(), dataset);
+        assert_eq!(
+            read(
+                BufReader::new(fs::File::open(&cache_path).unwrap()),
+                IMAGE_FOLDER,
+                &cache_path,
+                |_| true
+            )
+            .unwrap(),
+            Some(dataset)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+//! Module handling the (de)compression of the annotation files.
+//!
+//! The compression is detected from the magic bytes when reading and from the extension (`.gz` or `.zst`) when writing.
+//! Each compression format requires its cargo feature (`gzip` or `zstd`).
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::errors::LoadingError;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression format of an annotation file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression format matching the extension of the path.
+    #[must_use]
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Compression format matching the first bytes of a file.
+    #[must_use]
+    pub fn from_magic(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Self::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Opens the file for reading, decompressing it if needed.
+///
+/// # Errors
+///
+/// Will return `Err` if the file cannot be opened, or if it is compressed with a format whose feature is not enabled.
+pub(crate) fn open(path: &Path) -> Result<Box<dyn Read>, LoadingError> {
+    let file = fs::File::open(path).map_err(|err| LoadingError::Read(err, path.to_path_buf()))?;
+    let mut reader = BufReader::new(file);
+    let compression = Compression::from_magic(
+        reader
+            .fill_buf()
+            .map_err(|err| LoadingError::Read(err, path.to_path_buf()))?,
+    );
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(BufReader::new(
+            zstd::Decoder::with_buffer(reader)
+                .map_err(|err| LoadingError::Read(err, path.to_path_buf()))?,
+        ))),
+        #[allow(unreachable_patterns)]
+        compression => Err(LoadingError::UnsupportedCompression(
+            compression,
+            path.to_path_buf(),
+        )),
+    }
+}
+
+/// Writer compressing the data depending on the extension of the file, [`Writer::finish`] has to be called once done.
+pub(crate) enum Writer {
+    Plain(BufWriter<fs::File>),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<BufWriter<fs::File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<fs::File>>),
+}
+
+impl Writer {
+    /// Creates the file, compressed depending on its extension.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be created, or if the extension requires a compression whose feature is not enabled.
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        let compression = Compression::from_extension(path);
+        let unsupported = || {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("saving to {path:?} requires the `{compression}` feature of cocotools"),
+            )
+        };
+        match compression {
+            Compression::None => Ok(Self::Plain(BufWriter::new(fs::File::create(path)?))),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Ok(Self::Gzip(flate2::write::GzEncoder::new(
+                BufWriter::new(fs::File::create(path)?),
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(Self::Zstd(zstd::Encoder::new(
+                BufWriter::new(fs::File::create(path)?),
+                0,
+            )?)),
+            #[allow(unreachable_patterns)]
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// Writes the end of the compressed stream and flushes the file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the data cannot be written.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Self::Plain(file) => file,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::{load_json, BTreemapDataset};
+    use crate::test_utils::test_dir;
+    use rstest::rstest;
+
+    const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+
+    #[rstest]
+    #[case::json("annotations.json", Compression::None)]
+    #[case::gzip("annotations.json.gz", Compression::Gzip)]
+    #[case::zstd("annotations.json.zst", Compression::Zstd)]
+    fn compression_from_extension(#[case] path: &str, #[case] expected: Compression) {
+        assert_eq!(Compression::from_extension(Path::new(path)), expected);
+    }
+
+    #[rstest]
+    #[case::json(b"{\"images\"", Compression::None)]
+    #[case::gzip(&[0x1f, 0x8b, 0x08, 0x00], Compression::Gzip)]
+    #[case::zstd(&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd)]
+    #[case::empty(b"", Compression::None)]
+    fn compression_from_magic(#[case] bytes: &[u8], #[case] expected: Compression) {
+        assert_eq!(Compression::from_magic(bytes), expected);
+    }
+
+    #[rstest]
+    #[cfg_attr(feature = "gzip", case::gzip("annotations.json.gz"))]
+    #[cfg_attr(feature = "zstd", case::zstd("annotations.json.zst"))]
+    #[case::json("annotations.json")]
+    fn compressed_roundtrip(#[case] file_name: &str) {
+        let dir = test_dir(&format!("compression-{file_name}"));
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, "images").unwrap();
+        let path = dir.join(file_name);
+        dataset.save_to(&path).unwrap();
+
+        let mut magic = [0; 4];
+        fs::File::open(&path)
+            .unwrap()
+            .read_exact(&mut magic)
+            .unwrap();
+        assert_eq!(
+            Compression::from_magic(&magic),
+            Compression::from_extension(&path)
+        );
+        assert_eq!(
+            BTreemapDataset::new(path.as_path(), Path::new("images")).unwrap(),
+            dataset
+        );
+        let plain_path = dir.join("plain.json");
+        dataset.save_to(&plain_path).unwrap();
+        assert_eq!(load_json(&path).unwrap(), load_json(&plain_path).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn gzip_requires_feature() {
+        let dir = test_dir("compression-no-gzip");
+        let path = dir.join("annotations.json.gz");
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, "images").unwrap();
+        assert!(dataset.save_to(&path).is_err());
+
+        fs::write(&path, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+        assert!(matches!(
+            BTreemapDataset::new(path.as_path(), Path::new("images")),
+            Err(LoadingError::UnsupportedCompression(Compression::Gzip, _))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+//! Module containing the filters used to select a subset of a dataset.
+use std::collections::{BTreeMap, HashSet};
+
+pub use glob::Pattern;
+
+use crate::coco::object_detection::{Annotation, BTreemapDataset, Category, Image};
+
+/// Filter selecting the annotations and images to keep from a dataset.
+///
+/// All the conditions must be satisfied for an annotation (or image) to be kept, conditions left to `None` are ignored.
+/// The exception is the categories: when both `cat_ids` and `cat_names` are set, the categories matching either are selected.
+///
+/// ```
+/// # use cocotools::coco::filter::Filter;
+/// // Only people and cars, non-crowd, with an area above 32².
+/// let filter = Filter::new()
+///     .cat_names(["person", "car"])
+///     .iscrowd(Some(false))
+///     .min_area(Some(32.0 * 32.0))
+///     .keep_empty_images(false);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub cat_ids: Option<HashSet<u32>>,
+    /// Names of the categories to keep, names that are not in the dataset are ignored.
+    pub cat_names: Option<HashSet<String>>,
+    pub min_area: Option<f64>,
+    pub max_area: Option<f64>,
+    pub iscrowd: Option<bool>,
+    /// Minimum width and height of the bounding boxes.
+    pub min_bbox_size: Option<(f64, f64)>,
+    /// Maximum width and height of the bounding boxes.
+    pub max_bbox_size: Option<(f64, f64)>,
+    pub img_ids: Option<HashSet<u64>>,
+    pub file_name_glob: Option<Pattern>,
+    /// Minimum number of (kept) annotations for an image to be kept.
+    pub min_anns_per_image: Option<usize>,
+    /// Maximum number of (kept) annotations for an image to be kept.
+    pub max_anns_per_image: Option<usize>,
+    /// Whether to keep the images that are left without annotations after filtering.
+    pub keep_empty_images: bool,
+}
+
+impl Filter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn cat_ids(mut self, cat_ids: impl IntoIterator<Item = u32>) -> Self {
+        self.cat_ids = Some(cat_ids.into_iter().collect());
+        self
+    }
+
+    #[must_use]
+    pub fn cat_names(mut self, cat_names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cat_names = Some(cat_names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    #[must_use]
+    pub const fn min_area(mut self, min_area: Option<f64>) -> Self {
+        self.min_area = min_area;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_area(mut self, max_area: Option<f64>) -> Self {
+        self.max_area = max_area;
+        self
+    }
+
+    #[must_use]
+    pub const fn iscrowd(mut self, iscrowd: Option<bool>) -> Self {
+        self.iscrowd = iscrowd;
+        self
+    }
+
+    #[must_use]
+    pub const fn min_bbox_size(mut self, min_bbox_size: Option<(f64, f64)>) -> Self {
+        self.min_bbox_size = min_bbox_size;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_bbox_size(mut self, max_bbox_size: Option<(f64, f64)>) -> Self {
+        self.max_bbox_size = max_bbox_size;
+        self
+    }
+
+    #[must_use]
+    pub fn img_ids(mut self, img_ids: impl IntoIterator<Item = u64>) -> Self {
+        self.img_ids = Some(img_ids.into_iter().collect());
+        self
+    }
+
+    #[must_use]
+    pub fn file_name_glob(mut self, file_name_glob: Option<Pattern>) -> Self {
+        self.file_name_glob = file_name_glob;
+        self
+    }
+
+    #[must_use]
+    pub const fn min_anns_per_image(mut self, min_anns_per_image: Option<usize>) -> Self {
+        self.min_anns_per_image = min_anns_per_image;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_anns_per_image(mut self, max_anns_per_image: Option<usize>) -> Self {
+        self.max_anns_per_image = max_anns_per_image;
+        self
+    }
+
+    #[must_use]
+    pub const fn keep_empty_images(mut self, keep_empty_images: bool) -> Self {
+        self.keep_empty_images = keep_empty_images;
+        self
+    }
+
+    /// Returns a new dataset with only the images, annotations and categories selected by the filter.
+    ///
+    /// When filtering on categories, only the selected categories are kept in the new dataset.
+    #[must_use]
+    pub fn apply(&self, dataset: &BTreemapDataset) -> BTreemapDataset {
+        let cat_ids = self.selected_cat_ids(dataset);
+        let cats: BTreeMap<u32, Category> = dataset
+            .cats
+            .iter()
+            .filter(|(cat_id, _)| {
+                cat_ids
+                    .as_ref()
+                    .map_or(true, |cat_ids| cat_ids.contains(cat_id))
+            })
+            .map(|(cat_id, cat)| (*cat_id, cat.clone()))
+            .collect();
+
+        let cat_to_anns: BTreeMap<u32, HashSet<u64>> = cats
+            .keys()
+            .map(|cat_id| (*cat_id, HashSet::new()))
+            .collect();
+        let mut filtered_dataset = BTreemapDataset {
+            anns: BTreeMap::new(),
+            cats,
+            imgs: BTreeMap::new(),
+            img_to_anns: BTreeMap::new(),
+            cat_to_imgs: cat_to_anns.clone(),
+            cat_to_anns,
+            info: dataset.info.clone(),
+            licenses: dataset.licenses.clone(),
+            extra: dataset.extra.clone(),
+            image_folder: dataset.image_folder.clone(),
+        };
+        for img in dataset.imgs.values().filter(|img| self.keeps_img(img)) {
+            let img_anns: Vec<&Annotation> = dataset
+                .img_to_anns
+                .get(&img.id)
+                .into_iter()
+                .flatten()
+                .filter_map(|ann_id| dataset.anns.get(ann_id))
+                .filter(|ann| {
+                    cat_ids
+                        .as_ref()
+                        .map_or(true, |cat_ids| cat_ids.contains(&ann.category_id))
+                        && self.keeps_ann(ann)
+                })
+                .collect();
+
+            if self
+                .min_anns_per_image
+                .map_or(false, |min| img_anns.len() < min)
+                || self
+                    .max_anns_per_image
+                    .map_or(false, |max| img_anns.len() > max)
+                || (img_anns.is_empty() && !self.keep_empty_images)
+            {
+                continue;
+            }
+            filtered_dataset.imgs.insert(img.id, img.clone());
+            filtered_dataset.img_to_anns.insert(img.id, HashSet::new());
+            for ann in img_anns {
+                filtered_dataset.insert_ann(ann.clone());
+            }
+        }
+        filtered_dataset
+    }
+
+    /// Ids of the categories selected by id or by name, `None` if there is no filter on the categories.
+    fn selected_cat_ids(&self, dataset: &BTreemapDataset) -> Option<HashSet<u32>> {
+        if self.cat_ids.is_none() && self.cat_names.is_none() {
+            return None;
+        }
+        Some(
+            dataset
+                .cats
+                .values()
+                .filter(|cat| {
+                    self.cat_ids
+                        .as_ref()
+                        .map_or(false, |cat_ids| cat_ids.contains(&cat.id))
+                        || self
+                            .cat_names
+                            .as_ref()
+                            .map_or(false, |cat_names| cat_names.contains(&cat.name))
+                })
+                .map(|cat| cat.id)
+                .collect(),
+        )
+    }
+
+    fn keeps_img(&self, img: &Image) -> bool {
+        self.img_ids
+            .as_ref()
+            .map_or(true, |img_ids| img_ids.contains(&img.id))
+            && self
+                .file_name_glob
+                .as_ref()
+                .map_or(true, |pattern| pattern.matches(&img.file_name))
+    }
+
+    fn keeps_ann(&self, ann: &Annotation) -> bool {
+        self.min_area.map_or(true, |min_area| ann.area >= min_area)
+            && self.max_area.map_or(true, |max_area| ann.area <= max_area)
+            && self
+                .iscrowd
+                .map_or(true, |iscrowd| (ann.iscrowd != 0) == iscrowd)
+            && self.min_bbox_size.map_or(true, |(min_width, min_height)| {
+                ann.bbox.width >= min_width && ann.bbox.height >= min_height
+            })
+            && self.max_bbox_size.map_or(true, |(max_width, max_height)| {
+                ann.bbox.width <= max_width && ann.bbox.height <= max_height
+            })
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            cat_ids: None,
+            cat_names: None,
+            min_area: None,
+            max_area: None,
+            iscrowd: None,
+            min_bbox_size: None,
+            max_bbox_size: None,
+            img_ids: None,
+            file_name_glob: None,
+            min_anns_per_image: None,
+            max_anns_per_image: None,
+            keep_empty_images: true,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_utils::DatasetBuilder;
+    use rstest::rstest;
+
+    fn dataset() -> BTreemapDataset {
+        DatasetBuilder::new()
+            .named_img(1, 640, 480, "train/1.jpg")
+            .named_img(2, 640, 480, "train/2.jpg")
+            .named_img(3, 640, 480, "val/3.jpg")
+            .named_img(4, 640, 480, "val/4.png")
+            .ann(1, 1, 1, 10.0, 10.0)
+            .ann(2, 1, 1, 50.0, 50.0)
+            .crowd_ann(3, 1, 2, 100.0, 100.0)
+            .ann(4, 2, 3, 40.0, 40.0)
+            .ann(5, 3, 2, 40.0, 40.0)
+            .cat(1, "person", None)
+            .cat(2, "car", None)
+            .cat(3, "dog", None)
+            .build()
+    }
+
+    #[rstest]
+    #[case::no_filter(Filter::new(), vec![1, 2, 3, 4, 5], vec![1, 2, 3, 4])]
+    #[case::person_and_car(
+        Filter::new().cat_names(["person", "car"]).iscrowd(Some(false)).min_area(Some(32.0 * 32.0)).keep_empty_images(false),
+        vec![2, 5],
+        vec![1, 3])]
+    #[case::cat_ids_or_names(Filter::new().cat_ids([1]).cat_names(["car"]), vec![1, 2, 3, 5], vec![1, 2, 3, 4])]
+    #[case::cat_ids_and_names_overlap(Filter::new().cat_ids([1, 3]).cat_names(["dog"]), vec![1, 2, 4], vec![1, 2, 3, 4])]
+    #[case::bbox_size(
+        Filter::new().min_bbox_size(Some((20.0, 20.0))).max_bbox_size(Some((60.0, 60.0))).keep_empty_images(false),
+        vec![2, 4, 5],
+        vec![1, 2, 3])]
+    #[case::img_ids(Filter::new().img_ids([2, 4]), vec![4], vec![2, 4])]
+    #[case::file_name_glob(Filter::new().file_name_glob(Some(Pattern::new("val/*.jpg").unwrap())), vec![5], vec![3])]
+    #[case::anns_per_image(Filter::new().min_anns_per_image(Some(1)).max_anns_per_image(Some(2)), vec![4, 5], vec![2, 3])]
+    fn filter_dataset(
+        #[case] filter: Filter,
+        #[case] expected_ann_ids: Vec<u64>,
+        #[case] expected_img_ids: Vec<u64>,
+    ) {
+        let filtered_dataset = dataset().filter(&filter);
+        let ann_ids: Vec<u64> = filtered_dataset
+            .get_anns()
+            .iter()
+            .map(|ann| ann.id)
+            .collect();
+        let img_ids: Vec<u64> = filtered_dataset
+            .get_imgs()
+            .iter()
+            .map(|img| img.id)
+            .collect();
+        assert_eq!(ann_ids, expected_ann_ids);
+        assert_eq!(img_ids, expected_img_ids);
+        for img_id in img_ids {
+            for ann in filtered_dataset.get_img_anns(img_id).unwrap() {
+                assert_eq!(ann.image_id, img_id);
+                assert!(filtered_dataset.get_cat(ann.category_id).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,678 @@
+//! Module containing the [LVIS](https://www.lvisdataset.org/) evaluation, following the official [lvis-api](https://github.com/lvis-dataset/lvis-api).
+//!
+//! LVIS is federated: each image is only exhaustively annotated for some categories. Detections are only evaluated on an image
+//! for the categories present in it and for its `neg_category_ids`, and unmatched detections of the image's
+//! `not_exhaustive_category_ids` are ignored.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use ndarray::{s, Array3, Array4, ArrayViewD, ArrayViewMut1, ArrayViewMut2, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::coco::backend::DatasetView;
+use crate::coco::compression;
+use crate::coco::object_detection::{
+    Annotation, Bbox, ExtraFields, Frequency, Image, Segmentation,
+};
+use crate::coco::stats::{MEDIUM_AREA, SMALL_AREA};
+use crate::errors::{LoadingError, MaskError};
+use crate::mask::utils::{compute_ious, rle_from_segmentation, Area, IouType};
+
+/// IoU thresholds used to compute the AP, from 0.5 to 0.95 with a step of 0.05.
+const IOU_THRESHOLDS: [f64; 10] = [0.5, 0.55, 0.6, 0.65, 0.7, 0.75, 0.8, 0.85, 0.9, 0.95];
+/// Number of recall thresholds (from 0 to 1 with a step of 0.01) at which the precision is interpolated.
+const NB_RECALL_THRESHOLDS: usize = 101;
+/// Area ranges: all, small, medium and large.
+const AREA_RANGES: [(f64, f64); 4] = [
+    (0.0, 1e10),
+    (0.0, SMALL_AREA),
+    (SMALL_AREA, MEDIUM_AREA),
+    (MEDIUM_AREA, 1e10),
+];
+
+/// Detection (prediction) in the LVIS/COCO results format.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Detection {
+    pub image_id: u64,
+    pub category_id: u32,
+    pub bbox: Bbox,
+    /// Required for the segmentation evaluation, the bounding box is used if missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segmentation: Option<Segmentation>,
+    pub score: f64,
+}
+
+/// Loads detections from a json results file, which can be compressed (see [`compression`]).
+///
+/// # Errors
+///
+/// Will return `Err` if the json file does not exist/cannot be read or if an error happens when deserializing it.
+pub fn load_detections<P: AsRef<Path>>(results_path: P) -> Result<Vec<Detection>, LoadingError> {
+    let results_path = results_path.as_ref().to_path_buf();
+    let mut content = String::new();
+    compression::open(&results_path)?
+        .read_to_string(&mut content)
+        .map_err(|err| LoadingError::Read(err, results_path.clone()))?;
+    serde_json::from_str(&content).map_err(|err| LoadingError::Deserialize(err, results_path))
+}
+
+/// Options of the LVIS evaluation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LvisEvalOption {
+    pub iou_type: IouType,
+    /// Maximum number of detections per image (across all categories), the ones with the highest scores are kept.
+    pub max_dets: usize,
+}
+
+impl LvisEvalOption {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn iou_type(mut self, iou_type: IouType) -> Self {
+        self.iou_type = iou_type;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_dets(mut self, max_dets: usize) -> Self {
+        self.max_dets = max_dets;
+        self
+    }
+}
+
+impl Default for LvisEvalOption {
+    fn default() -> Self {
+        Self {
+            iou_type: IouType::Segm,
+            max_dets: 300,
+        }
+    }
+}
+
+/// Result of the LVIS evaluation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LvisEvaluation {
+    /// Ids of the evaluated categories, in the order used by `precision` and `recall`.
+    pub cat_ids: Vec<u32>,
+    /// Interpolated precision, indexed by IoU threshold, recall threshold, category and area range (all, small, medium, large).
+    /// It is -1 when there is no ground truth.
+    pub precision: Array4<f64>,
+    /// Recall, indexed by IoU threshold, category and area range. It is -1 when there is no ground truth.
+    pub recall: Array3<f64>,
+    pub metrics: LvisMetrics,
+}
+
+/// Summary metrics of the LVIS evaluation, -1 when there is no ground truth to compute them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct LvisMetrics {
+    /// AP averaged over the IoU thresholds from 0.5 to 0.95.
+    pub ap: f64,
+    pub ap50: f64,
+    pub ap75: f64,
+    pub aps: f64,
+    pub apm: f64,
+    pub apl: f64,
+    /// AP of the rare categories.
+    pub apr: f64,
+    /// AP of the common categories.
+    pub apc: f64,
+    /// AP of the frequent categories.
+    pub apf: f64,
+    pub ar: f64,
+    pub ars: f64,
+    pub arm: f64,
+    pub arl: f64,
+    #[serde(skip)]
+    pub max_dets: usize,
+}
+
+/// Matching of the detections and ground truths of one image and category, for one area range.
+struct ImageEvaluation {
+    dt_scores: Vec<f64>,
+    /// Whether each detection is matched, for each IoU threshold.
+    dt_matched: Vec<Vec<bool>>,
+    /// Whether each detection is ignored, for each IoU threshold.
+    dt_ignored: Vec<Vec<bool>>,
+    nb_gts: usize,
+}
+
+/// Evaluates the detections against the (LVIS) ground truth dataset.
+///
+/// # Errors
+///
+/// Will return `Err` if a detection is for an image not in the dataset, or if a segmentation cannot be decoded.
+pub fn evaluate<D: DatasetView>(
+    dataset: &D,
+    dts: &[Detection],
+    option: LvisEvalOption,
+) -> Result<LvisEvaluation, D::Error>
+where
+    D::Error: From<MaskError>,
+{
+    let cats = dataset.get_cats()?;
+    let cat_ids: Vec<u32> = cats.iter().map(|cat| cat.id).collect();
+    let img_ids: Vec<u64> = dataset.get_imgs()?.iter().map(|img| img.id).collect();
+
+    // The ground truths of each image and category, sorted by id, and the images to evaluate for each category.
+    let mut gts_per_img_cat: HashMap<(u64, u32), Vec<Cow<'_, Annotation>>> = HashMap::new();
+    let mut cat_img_ids: HashMap<u32, BTreeSet<u64>> = HashMap::new();
+    for img_id in &img_ids {
+        for ann in dataset.get_img_anns(*img_id)? {
+            cat_img_ids
+                .entry(ann.category_id)
+                .or_default()
+                .insert(*img_id);
+            gts_per_img_cat
+                .entry((*img_id, ann.category_id))
+                .or_default()
+                .push(ann);
+        }
+    }
+    for gts in gts_per_img_cat.values_mut() {
+        gts.sort_by_key(|ann| ann.id);
+    }
+
+    let mut img_dts: BTreeMap<u64, Vec<&Detection>> = BTreeMap::new();
+    for dt in dts {
+        dataset.get_img(dt.image_id)?;
+        img_dts.entry(dt.image_id).or_default().push(dt);
+    }
+    // Only keep the detections of the categories verified for the image (present or negative).
+    // They are stored with their score, by decreasing score.
+    let mut dts_per_img_cat: HashMap<(u64, u32), Vec<(Annotation, f64)>> = HashMap::new();
+    for (img_id, mut dts) in img_dts {
+        dts.sort_by(|dt1, dt2| dt2.score.total_cmp(&dt1.score));
+        dts.truncate(option.max_dets);
+        let img = dataset.get_img(img_id)?;
+        let verified_cat_ids: HashSet<u32> = dataset
+            .get_img_anns(img_id)?
+            .iter()
+            .map(|ann| ann.category_id)
+            .chain(img.neg_category_ids.iter().flatten().copied())
+            .collect();
+        for (idx, dt) in dts.into_iter().enumerate() {
+            if verified_cat_ids.contains(&dt.category_id) {
+                cat_img_ids
+                    .entry(dt.category_id)
+                    .or_default()
+                    .insert(img_id);
+                dts_per_img_cat
+                    .entry((img_id, dt.category_id))
+                    .or_default()
+                    .push((detection_to_ann(dt, idx, &img, option.iou_type)?, dt.score));
+            }
+        }
+    }
+
+    let mut precision = Array4::from_elem(
+        (
+            IOU_THRESHOLDS.len(),
+            NB_RECALL_THRESHOLDS,
+            cat_ids.len(),
+            AREA_RANGES.len(),
+        ),
+        -1.0,
+    );
+    let mut recall = Array3::from_elem(
+        (IOU_THRESHOLDS.len(), cat_ids.len(), AREA_RANGES.len()),
+        -1.0,
+    );
+    for (cat_idx, cat_id) in cat_ids.iter().enumerate() {
+        let mut evaluations: Vec<Vec<ImageEvaluation>> =
+            AREA_RANGES.iter().map(|_| Vec::new()).collect();
+        for img_id in cat_img_ids.get(cat_id).into_iter().flatten() {
+            let gts: Vec<&Annotation> = gts_per_img_cat
+                .get(&(*img_id, *cat_id))
+                .into_iter()
+                .flatten()
+                .map(AsRef::as_ref)
+                .collect();
+            let dts: &[(Annotation, f64)] = dts_per_img_cat
+                .get(&(*img_id, *cat_id))
+                .map_or(&[], Vec::as_slice);
+            let img = dataset.get_img(*img_id)?;
+            let not_exhaustive = img
+                .not_exhaustive_category_ids
+                .as_ref()
+                .map_or(false, |cat_ids| cat_ids.contains(cat_id));
+            for (area_idx, area_range) in AREA_RANGES.iter().enumerate() {
+                evaluations[area_idx].push(evaluate_img(
+                    dts,
+                    &gts,
+                    &img,
+                    *area_range,
+                    not_exhaustive,
+                    option.iou_type,
+                )?);
+            }
+        }
+
+        for (area_idx, area_evaluations) in evaluations.iter().enumerate() {
+            accumulate(
+                area_evaluations,
+                precision.slice_mut(s![.., .., cat_idx, area_idx]),
+                recall.slice_mut(s![.., cat_idx, area_idx]),
+            );
+        }
+    }
+
+    let frequencies: Vec<Option<Frequency>> = cats.iter().map(|cat| cat.frequency).collect();
+    let metrics = summarize(&precision, &recall, &frequencies, option.max_dets);
+    Ok(LvisEvaluation {
+        cat_ids,
+        precision,
+        recall,
+        metrics,
+    })
+}
+
+/// Converts a detection to an annotation, with the area used by LVIS (the bounding box area for the bbox evaluation).
+fn detection_to_ann(
+    dt: &Detection,
+    idx: usize,
+    img: &Image,
+    iou_type: IouType,
+) -> Result<Annotation, MaskError> {
+    let segmentation = dt.segmentation.clone().unwrap_or_else(|| {
+        let Bbox {
+            left,
+            top,
+            width,
+            height,
+        } = dt.bbox;
+        let (right, bottom) = (left + width, top + height);
+        Segmentation::Polygons(vec![vec![
+            left, top, left, bottom, right, bottom, right, top,
+        ]])
+    });
+    let area = match iou_type {
+        IouType::Bbox => dt.bbox.width * dt.bbox.height,
+        IouType::Segm | IouType::Boundary(_) => {
+            f64::from(rle_from_segmentation(&segmentation, img.width, img.height)?.area())
+        }
+    };
+    Ok(Annotation {
+        id: idx as u64,
+        image_id: dt.image_id,
+        category_id: dt.category_id,
+        segmentation,
+        area,
+        bbox: dt.bbox.clone(),
+        iscrowd: 0,
+        extra: ExtraFields::default(),
+    })
+}
+
+/// Greedily matches the detections (given with their score, by decreasing score) with the ground truths, for each IoU threshold.
+///
+/// Ground truths outside of the area range (or crowd annotations) are ignored, as well as the unmatched detections
+/// outside of the area range or of a category not exhaustively annotated in the image.
+fn evaluate_img(
+    dts: &[(Annotation, f64)],
+    gts: &[&Annotation],
+    img: &Image,
+    (min_area, max_area): (f64, f64),
+    not_exhaustive: bool,
+    iou_type: IouType,
+) -> Result<ImageEvaluation, MaskError> {
+    let is_outside = |ann: &Annotation| ann.area < min_area || ann.area > max_area;
+    // Non ignored ground truths first.
+    let mut gts: Vec<(&Annotation, bool)> = gts
+        .iter()
+        .map(|gt| (*gt, gt.iscrowd != 0 || is_outside(gt)))
+        .collect();
+    gts.sort_by_key(|(_, ignored)| *ignored);
+
+    let ious = compute_ious(
+        &dts.iter().map(|(dt, _)| dt).collect::<Vec<_>>(),
+        &gts.iter().map(|(gt, _)| *gt).collect::<Vec<_>>(),
+        img,
+        iou_type,
+    )?;
+    let mut dt_matched = vec![vec![false; dts.len()]; IOU_THRESHOLDS.len()];
+    let mut dt_ignored = vec![vec![false; dts.len()]; IOU_THRESHOLDS.len()];
+    for (thr_idx, iou_threshold) in IOU_THRESHOLDS.iter().enumerate() {
+        let mut gt_matched = vec![false; gts.len()];
+        for dt_idx in 0..dts.len() {
+            let mut best_iou = iou_threshold.min(1.0 - 1e-10);
+            let mut best_gt: Option<usize> = None;
+            for (gt_idx, (_, gt_ignored)) in gts.iter().enumerate() {
+                if gt_matched[gt_idx] {
+                    continue;
+                }
+                // Stop once a non ignored ground truth is matched and only ignored ones are left.
+                if best_gt.map_or(false, |best_gt| !gts[best_gt].1) && *gt_ignored {
+                    break;
+                }
+                if ious[[dt_idx, gt_idx]] < best_iou {
+                    continue;
+                }
+                best_iou = ious[[dt_idx, gt_idx]];
+                best_gt = Some(gt_idx);
+            }
+            if let Some(gt_idx) = best_gt {
+                gt_matched[gt_idx] = true;
+                dt_matched[thr_idx][dt_idx] = true;
+                dt_ignored[thr_idx][dt_idx] = gts[gt_idx].1;
+            } else {
+                dt_ignored[thr_idx][dt_idx] = is_outside(&dts[dt_idx].0) || not_exhaustive;
+            }
+        }
+    }
+
+    Ok(ImageEvaluation {
+        dt_scores: dts.iter().map(|(_, score)| *score).collect(),
+        dt_matched,
+        dt_ignored,
+        nb_gts: gts.iter().filter(|(_, ignored)| !ignored).count(),
+    })
+}
+
+/// Computes the interpolated precision and recall of a category for an area range, from the evaluations of all the images.
+#[allow(clippy::cast_precision_loss)]
+fn accumulate(
+    evaluations: &[ImageEvaluation],
+    mut precision: ArrayViewMut2<f64>,
+    mut recall: ArrayViewMut1<f64>,
+) {
+    let nb_gts: usize = evaluations.iter().map(|evaluation| evaluation.nb_gts).sum();
+    if nb_gts == 0 {
+        return;
+    }
+    // (image index, detection index), by decreasing score (stable to match lvis-api).
+    let mut order: Vec<(usize, usize)> = evaluations
+        .iter()
+        .enumerate()
+        .flat_map(|(img_idx, evaluation)| {
+            (0..evaluation.dt_scores.len()).map(move |dt_idx| (img_idx, dt_idx))
+        })
+        .collect();
+    order.sort_by(|(img1, dt1), (img2, dt2)| {
+        evaluations[*img2].dt_scores[*dt2].total_cmp(&evaluations[*img1].dt_scores[*dt1])
+    });
+
+    for thr_idx in 0..IOU_THRESHOLDS.len() {
+        let (mut tp, mut fp) = (0_usize, 0_usize);
+        let mut recalls = Vec::with_capacity(order.len());
+        let mut precisions = Vec::with_capacity(order.len());
+        for (img_idx, dt_idx) in &order {
+            let evaluation = &evaluations[*img_idx];
+            if !evaluation.dt_ignored[thr_idx][*dt_idx] {
+                if evaluation.dt_matched[thr_idx][*dt_idx] {
+                    tp += 1;
+                } else {
+                    fp += 1;
+                }
+            }
+            recalls.push(tp as f64 / nb_gts as f64);
+            precisions.push(tp as f64 / ((tp + fp) as f64 + f64::EPSILON));
+        }
+        recall[thr_idx] = recalls.last().copied().unwrap_or(0.0);
+
+        // Precision envelope, then precision at each recall threshold (0 if the recall is never reached).
+        for idx in (1..precisions.len()).rev() {
+            if precisions[idx] > precisions[idx - 1] {
+                precisions[idx - 1] = precisions[idx];
+            }
+        }
+        for rec_idx in 0..NB_RECALL_THRESHOLDS {
+            let recall_threshold = rec_idx as f64 / (NB_RECALL_THRESHOLDS - 1) as f64;
+            let idx = recalls.partition_point(|recall| *recall < recall_threshold);
+            precision[[thr_idx, rec_idx]] = precisions.get(idx).copied().unwrap_or(0.0);
+        }
+    }
+}
+
+fn summarize(
+    precision: &Array4<f64>,
+    recall: &Array3<f64>,
+    frequencies: &[Option<Frequency>],
+    max_dets: usize,
+) -> LvisMetrics {
+    let mean = |values: ArrayViewD<f64>| -> f64 {
+        let (sum, count) = values
+            .iter()
+            .filter(|value| **value > -1.0)
+            .fold((0.0, 0_usize), |(sum, count), value| {
+                (sum + value, count + 1)
+            });
+        #[allow(clippy::cast_precision_loss)]
+        if count == 0 {
+            -1.0
+        } else {
+            sum / count as f64
+        }
+    };
+    let ap = |thr_idx: Option<usize>, area_idx: usize, frequency: Option<Frequency>| -> f64 {
+        let cat_idxs: Vec<usize> = (0..frequencies.len())
+            .filter(|cat_idx| {
+                frequency.map_or(true, |frequency| frequencies[*cat_idx] == Some(frequency))
+            })
+            .collect();
+        let precision = precision.select(Axis(2), &cat_idxs);
+        match thr_idx {
+            Some(thr_idx) => mean(precision.slice(s![thr_idx, .., .., area_idx]).into_dyn()),
+            None => mean(precision.slice(s![.., .., .., area_idx]).into_dyn()),
+        }
+    };
+    let ar = |area_idx: usize| mean(recall.slice(s![.., .., area_idx]).into_dyn());
+    let thr_idx = |threshold: f64| {
+        IOU_THRESHOLDS
+            .iter()
+            .position(|iou_threshold| (iou_threshold - threshold).abs() < 1e-9)
+    };
+
+    LvisMetrics {
+        ap: ap(None, 0, None),
+        ap50: ap(thr_idx(0.5), 0, None),
+        ap75: ap(thr_idx(0.75), 0, None),
+        aps: ap(None, 1, None),
+        apm: ap(None, 2, None),
+        apl: ap(None, 3, None),
+        apr: ap(None, 0, Some(Frequency::Rare)),
+        apc: ap(None, 0, Some(Frequency::Common)),
+        apf: ap(None, 0, Some(Frequency::Frequent)),
+        ar: ar(0),
+        ars: ar(1),
+        arm: ar(2),
+        arl: ar(3),
+        max_dets,
+    }
+}
+
+impl fmt::Display for LvisMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = [
+            (
+                "Average Precision",
+                "AP",
+                "0.50:0.95",
+                "all",
+                "all",
+                self.ap,
+            ),
+            (
+                "Average Precision",
+                "AP",
+                "0.50     ",
+                "all",
+                "all",
+                self.ap50,
+            ),
+            (
+                "Average Precision",
+                "AP",
+                "0.75     ",
+                "all",
+                "all",
+                self.ap75,
+            ),
+            ("Average Precision", "AP", "0.50:0.95", "s", "all", self.aps),
+            ("Average Precision", "AP", "0.50:0.95", "m", "all", self.apm),
+            ("Average Precision", "AP", "0.50:0.95", "l", "all", self.apl),
+            ("Average Precision", "AP", "0.50:0.95", "all", "r", self.apr),
+            ("Average Precision", "AP", "0.50:0.95", "all", "c", self.apc),
+            ("Average Precision", "AP", "0.50:0.95", "all", "f", self.apf),
+            ("Average Recall", "AR", "0.50:0.95", "all", "all", self.ar),
+            ("Average Recall", "AR", "0.50:0.95", "s", "all", self.ars),
+            ("Average Recall", "AR", "0.50:0.95", "m", "all", self.arm),
+            ("Average Recall", "AR", "0.50:0.95", "l", "all", self.arl),
+        ];
+        for (name, short_name, iou, area, frequency, value) in lines {
+            writeln!(
+                f,
+                " {name:<18} ({short_name}) @[ IoU={iou} | area={area:>3} | maxDets={} catIds={frequency:>3}] = {value:.3}",
+                self.max_dets
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::{BTreemapDataset, Dataset};
+    use crate::errors::CocoError;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn lvis_json() -> serde_json::Value {
+        let ann = |id, image_id, category_id, left: f64, top: f64| {
+            json!({
+                "id": id,
+                "image_id": image_id,
+                "category_id": category_id,
+                "segmentation": [[left, top, left, top + 10.0, left + 10.0, top + 10.0, left + 10.0, top]],
+                "area": 100.0,
+                "bbox": [left, top, 10.0, 10.0]
+            })
+        };
+        json!({
+            "images": [
+                {"id": 1, "width": 100, "height": 100, "coco_url": "http://images.cocodataset.org/val2017/1.jpg",
+                 "neg_category_ids": [2], "not_exhaustive_category_ids": []},
+                {"id": 2, "width": 100, "height": 100, "coco_url": "http://images.cocodataset.org/val2017/2.jpg",
+                 "neg_category_ids": [], "not_exhaustive_category_ids": [1]},
+                {"id": 3, "width": 100, "height": 100, "coco_url": "http://images.cocodataset.org/val2017/3.jpg",
+                 "neg_category_ids": [], "not_exhaustive_category_ids": []}
+            ],
+            "annotations": [
+                ann(1, 1, 1, 0.0, 0.0),
+                ann(2, 2, 1, 0.0, 0.0),
+                ann(3, 2, 3, 20.0, 20.0),
+                ann(4, 3, 2, 0.0, 0.0)
+            ],
+            "categories": [
+                {"id": 1, "name": "person", "synonyms": ["person", "human"], "frequency": "f"},
+                {"id": 2, "name": "aardvark", "synonyms": ["aardvark"], "frequency": "r"},
+                {"id": 3, "name": "bus", "synonyms": ["bus"], "frequency": "c"}
+            ]
+        })
+    }
+
+    fn detections() -> Vec<Detection> {
+        let dt = |image_id, category_id, left, top, score| Detection {
+            image_id,
+            category_id,
+            bbox: Bbox {
+                left,
+                top,
+                width: 10.0,
+                height: 10.0,
+            },
+            segmentation: None,
+            score,
+        };
+        vec![
+            dt(1, 1, 0.0, 0.0, 0.9),
+            // False positive, the category is verified to not be in the image.
+            dt(1, 2, 50.0, 50.0, 0.8),
+            // Dropped, the category has not been verified for the image.
+            dt(1, 3, 0.0, 0.0, 0.95),
+            // Ignored, the category is not exhaustively annotated in the image.
+            dt(2, 1, 60.0, 60.0, 0.99),
+            dt(2, 1, 0.0, 0.0, 0.7),
+            dt(2, 3, 20.0, 20.0, 0.6),
+            dt(3, 2, 0.0, 0.0, 0.5),
+        ]
+    }
+
+    #[test]
+    fn load_lvis() {
+        let dataset: Dataset = serde_json::from_value(lvis_json()).unwrap();
+        assert_eq!(dataset.images[1].not_exhaustive_category_ids, Some(vec![1]));
+        assert_eq!(dataset.categories[1].frequency, Some(Frequency::Rare));
+        assert_eq!(dataset.annotations[0].iscrowd, 0);
+        let saved = serde_json::to_value(&dataset).unwrap();
+        assert_eq!(
+            saved["images"][1]["not_exhaustive_category_ids"],
+            json!([1])
+        );
+        assert!(saved["images"][1].get("file_name").is_none());
+        assert_eq!(saved["categories"][0]["frequency"], json!("f"));
+        assert_eq!(
+            saved["categories"][0]["synonyms"],
+            json!(["person", "human"])
+        );
+    }
+
+    #[rstest]
+    #[case::bbox(IouType::Bbox)]
+    #[case::segm(IouType::Segm)]
+    fn federated_evaluation(#[case] iou_type: IouType) {
+        let dataset: Dataset = serde_json::from_value(lvis_json()).unwrap();
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        let option = LvisEvalOption::new().iou_type(iou_type);
+        let evaluation = evaluate(&dataset, &detections(), option).unwrap();
+
+        let metrics = evaluation.metrics;
+        let assert_close = |value: f64, expected: f64| {
+            assert!((value - expected).abs() < 1e-6, "{value} != {expected}")
+        };
+        assert_close(metrics.ap, 2.5 / 3.0);
+        assert_close(metrics.ap50, 2.5 / 3.0);
+        assert_close(metrics.aps, 2.5 / 3.0);
+        assert_close(metrics.apr, 0.5);
+        assert_close(metrics.apc, 1.0);
+        assert_close(metrics.apf, 1.0);
+        assert_close(metrics.ar, 1.0);
+        assert_close(metrics.apm, -1.0);
+        assert_close(metrics.arl, -1.0);
+    }
+
+    #[test]
+    fn max_dets_per_image() {
+        let dataset: Dataset = serde_json::from_value(lvis_json()).unwrap();
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        let option = LvisEvalOption::new().iou_type(IouType::Bbox).max_dets(1);
+        let evaluation = evaluate(&dataset, &detections(), option).unwrap();
+
+        // Only the highest scoring detection of each image is kept, which leaves a single true positive.
+        assert_eq!(evaluation.cat_ids, vec![1, 2, 3]);
+        assert!((evaluation.metrics.ap - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(
+            evaluation.recall.slice(s![0, .., 0]).to_vec(),
+            vec![0.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn unknown_image() {
+        let dataset: Dataset = serde_json::from_value(lvis_json()).unwrap();
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        let mut dts = detections();
+        dts[0].image_id = 42;
+        assert!(matches!(
+            evaluate(&dataset, &dts, LvisEvalOption::new()),
+            Err(CocoError::MissingId(_))
+        ));
+    }
+}
@@ -2,47 +2,83 @@
 use core::fmt;
 use std::collections::HashSet;
 use std::error::Error;
-use std::fs;
+use std::io::Read;
 use std::path::Path;
 use std::{collections::BTreeMap, path::PathBuf};
 
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
-use serde::de::{self, SeqAccess, Visitor};
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
 use serde::ser::SerializeTuple;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::errors::{self, LoadingError, MissingIdError};
-use crate::utils::load_img;
+#[cfg(feature = "cache")]
+use crate::coco::cache;
+use crate::coco::compression;
+use crate::coco::filter::Filter;
+use crate::coco::remap::{CategoryIdMap, CategoryRemapping};
+use crate::coco::streaming::{self, SaveOption};
+#[cfg(feature = "parquet")]
+use crate::coco::tables;
+#[cfg(feature = "cache")]
+use crate::errors::CacheError;
+#[cfg(feature = "parquet")]
+use crate::errors::TableError;
+use crate::errors::{self, LoadingError, MissingIdError, MutationError, RemapError};
+use crate::utils::{load_img, try_par_map};
 use crate::visualize::draw::{self, DrawOption};
 
 /// COCO dataset as-is, without additionnal functionalities.
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
 pub struct Dataset {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_default")]
     pub info: Info,
     pub images: Vec<Image>,
     pub annotations: Vec<Annotation>,
     pub categories: Vec<Category>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub licenses: Vec<License>,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+/// Information about the dataset, the fields missing from the annotation file stay missing when saving it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
 pub struct Info {
-    pub year: u32,
-    pub version: String,
-    pub description: String,
-    pub contributor: String,
-    pub url: String,
-    pub date_created: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contributor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_created: Option<String>,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
 pub struct License {
     pub id: u32,
     pub name: String,
     pub url: String,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
+}
+
+/// Fields that are not part of the COCO format (for example LVIS' `neg_category_ids`, or custom attributes).
+///
+/// They are kept as-is so that they are not lost when saving the dataset.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ExtraFields(pub serde_json::Map<String, serde_json::Value>);
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    value == &T::default()
 }
 
 /// Stores information relating to one image.
@@ -50,20 +86,30 @@ pub struct License {
     feature = "pyo3",
     pyclass(get_all, set_all, module = "rpycocotools.anns")
 )]
-#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
 pub struct Image {
     pub id: u64,
     pub width: u32,
     pub height: u32,
+    /// LVIS does not have file names, only `coco_url`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub file_name: String,
-    #[serde(default)]
-    pub license: u32,
-    #[serde(default)]
-    pub flickr_url: String,
-    #[serde(default)]
-    pub coco_url: String,
-    #[serde(default)]
-    pub date_captured: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flickr_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coco_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_captured: Option<String>,
+    /// LVIS: categories verified to not be present in the image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub neg_category_ids: Option<Vec<u32>>,
+    /// LVIS: categories present in the image, but not all of their instances have been annotated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_exhaustive_category_ids: Option<Vec<u32>>,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
 }
 
 /// Object instance annotation for object detection.\
@@ -76,7 +122,7 @@ pub struct Image {
     feature = "pyo3",
     pyclass(subclass, get_all, set_all, module = "rpycocotools.anns")
 )]
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Annotation {
     pub id: u64,
     pub image_id: u64,
@@ -91,8 +137,11 @@ pub struct Annotation {
     /// The COCO bounding box format is `[top left x position, top left y position, width, height]`.\
     /// Example: "bbox": `[473.07, 395.93, 38.65, 28.67]`
     pub bbox: Bbox,
-    /// Either 1 or 0
+    /// Either 1 or 0 (LVIS annotations do not have this field, they are all 0)
+    #[serde(default)]
     pub iscrowd: u32,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
 }
 
 // #[cfg_attr(feature = "pyo3", pyclass)]
@@ -188,11 +237,51 @@ pub struct Bbox {
     feature = "pyo3",
     pyclass(get_all, set_all, module = "rpycocotools.anns")
 )]
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct Category {
     pub id: u32,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub supercategory: Option<String>,
+    /// LVIS: frequency group of the category.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<Frequency>,
+    /// LVIS: synonyms of the category name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synonyms: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: ExtraFields,
+}
+
+/// Frequency group of a LVIS category, depending on the number of training images it appears in.
+#[cfg_attr(feature = "pyo3", pyclass(module = "rpycocotools.anns"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Frequency {
+    /// In 1 to 10 images.
+    #[serde(rename = "r")]
+    Rare,
+    /// In 11 to 100 images.
+    #[serde(rename = "c")]
+    Common,
+    /// In more than 100 images.
+    #[serde(rename = "f")]
+    Frequent,
+}
+
+/// Loads a COCO dataset as-is from the annotation file, which can be compressed (see [`compression`]).
+///
+/// # Errors
+///
+/// Will return `Err` if the json file does not exist/cannot be read or if an error happens when deserializing it.
+pub fn load_json<P: AsRef<Path>>(annotations_path: P) -> Result<Dataset, LoadingError> {
+    let annotations_path = annotations_path.as_ref().to_path_buf();
+    let mut annotations_file_content = String::new();
+    compression::open(&annotations_path)?
+        .read_to_string(&mut annotations_file_content)
+        .map_err(|err| LoadingError::Read(err, annotations_path.clone()))?;
+
+    serde_json::from_str(&annotations_file_content)
+        .map_err(|err| LoadingError::Deserialize(err, annotations_path))
 }
 
 /// COCO dataset represented as a hashmap where the hashmap's keys are the ids.
@@ -201,29 +290,60 @@ pub struct Category {
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct BTreemapDataset {
     pub(crate) anns: BTreeMap<u64, Annotation>,
-    cats: BTreeMap<u32, Category>,
-    imgs: BTreeMap<u64, Image>,
+    pub(crate) cats: BTreeMap<u32, Category>,
+    pub(crate) imgs: BTreeMap<u64, Image>,
     /// Hashmap that links an image id to the image's annotations
     // Use Rc to reference the annotations directly ?
-    img_to_anns: BTreeMap<u64, HashSet<u64>>,
+    pub(crate) img_to_anns: BTreeMap<u64, HashSet<u64>>,
+    /// Hashmap that links a category id to the annotations of this category
+    pub(crate) cat_to_anns: BTreeMap<u32, HashSet<u64>>,
+    /// Hashmap that links a category id to the images with at least one annotation of this category
+    pub(crate) cat_to_imgs: BTreeMap<u32, HashSet<u64>>,
+    pub(crate) info: Info,
+    pub(crate) licenses: Vec<License>,
+    /// Fields of the annotation file that are not part of the COCO format.
+    pub(crate) extra: ExtraFields,
     pub image_folder: PathBuf,
 }
 
 impl BTreemapDataset {
     /// Loads a COCO dataset from the annotation file and the image folder.
     ///
+    /// The annotation file can be compressed with gzip or zstd if the corresponding feature is enabled.
+    ///
     /// # Errors
     ///
     /// Will return `Err` if the json file does not exist/cannot be read or if an error happens when deserializing and parsing it.
     /// Will return `Err` if there is an annotation with an image id X, but no image entry has this id.
     pub fn new<P: AsRef<Path>>(annotations_path: P, image_folder: P) -> Result<Self, LoadingError> {
-        let annotations_path = annotations_path.as_ref().to_path_buf();
-        let annotations_file_content = fs::read_to_string(&annotations_path)
-            .map_err(|err| LoadingError::Read(err, annotations_path.clone()))?;
+        streaming::load(annotations_path, image_folder)
+    }
 
-        let dataset: Dataset = serde_json::from_str(&annotations_file_content)
-            .map_err(|err| LoadingError::Deserialize(err, annotations_path.clone()))?;
-        Self::from_dataset(dataset, image_folder)
+    /// Loads a COCO dataset from a binary cache of the annotation file, kept in `cache_dir`.
+    ///
+    /// The cache is (re)built from the annotation file when it does not exist yet or when the annotation file has changed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the json file cannot be loaded or if the cache file cannot be written.
+    #[cfg(feature = "cache")]
+    pub fn load_cached<P: AsRef<Path>>(
+        annotations_path: P,
+        image_folder: P,
+        cache_dir: P,
+    ) -> Result<Self, CacheError> {
+        cache::load_cached(annotations_path, image_folder, cache_dir)
+    }
+
+    /// Loads a COCO dataset from the Parquet files saved by [`BTreemapDataset::save_parquet`] in `input_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the files cannot be read or do not have the expected columns, or if an annotation references an image
+    /// that does not exist.
+    #[cfg(feature = "parquet")]
+    pub fn load_parquet<P: AsRef<Path>>(input_dir: P, image_folder: P) -> Result<Self, TableError> {
+        tables::load_parquet(input_dir, image_folder)
     }
 
     /// Construct a hashmap COCO dataset from a "simple" dataset and the image folder.
@@ -235,7 +355,26 @@ impl BTreemapDataset {
         dataset: Dataset,
         image_folder: P,
     ) -> Result<Self, LoadingError> {
-        let cats = dataset
+        Self::index(dataset, image_folder, true)
+    }
+
+    /// Same as [`BTreemapDataset::from_dataset`], but the annotations whose image is not in the dataset are kept,
+    /// with their polygons left in the COCO format (without the image size).
+    ///
+    /// This is meant for datasets that are going to be checked with [`crate::coco::validation::validate`] or [`crate::coco::validation::fix`],
+    /// which report these annotations.
+    #[must_use]
+    pub fn from_dataset_unchecked<P: AsRef<Path>>(dataset: Dataset, image_folder: P) -> Self {
+        Self::index(dataset, image_folder, false)
+            .unwrap_or_else(|_| unreachable!("only the strict indexing can fail"))
+    }
+
+    fn index<P: AsRef<Path>>(
+        dataset: Dataset,
+        image_folder: P,
+        strict: bool,
+    ) -> Result<Self, LoadingError> {
+        let cats: BTreeMap<u32, Category> = dataset
             .categories
             .into_iter()
             .map(|category| (category.id, category))
@@ -243,47 +382,99 @@ impl BTreemapDataset {
 
         let imgs: BTreeMap<u64, Image> = dataset
             .images
-            .clone()
             .into_iter()
             .map(|image| (image.id, image))
             .collect();
 
-        let mut anns: BTreeMap<u64, Annotation> = BTreeMap::new();
         // Have (at least) an empty set for each image to avoid getting an error in the case where an image does not have any annotation.
-        let mut img_to_anns: BTreeMap<u64, HashSet<u64>> = dataset
-            .images
-            .into_iter()
-            .map(|image| (image.id, HashSet::new()))
+        let img_to_anns: BTreeMap<u64, HashSet<u64>> = imgs
+            .keys()
+            .map(|img_id| (*img_id, HashSet::new()))
             .collect();
+        let cat_to_anns: BTreeMap<u32, HashSet<u64>> = cats
+            .keys()
+            .map(|cat_id| (*cat_id, HashSet::new()))
+            .collect();
+
+        let mut coco_dataset = Self {
+            anns: BTreeMap::new(),
+            cats,
+            imgs: BTreeMap::new(),
+            img_to_anns,
+            cat_to_imgs: cat_to_anns.clone(),
+            cat_to_anns,
+            info: dataset.info,
+            licenses: dataset.licenses,
+            extra: dataset.extra,
+            image_folder: image_folder.as_ref().to_path_buf(),
+        };
 
-        for mut annotation in dataset.annotations {
-            let ann_id = annotation.id;
+        let annotations = try_par_map(dataset.annotations, |mut annotation| {
             let img_id = annotation.image_id;
 
             // The polygon format from COCO is annoying to deal with as it does not contain the size of the image,
             // it is therefore transformed into a more complete format.
             if let Segmentation::Polygons(counts) = annotation.segmentation {
-                annotation.segmentation = Segmentation::PolygonsRS(PolygonsRS {
-                    size: if let Some(img) = imgs.get(&img_id) {
-                        vec![img.height, img.width]
-                    } else {
-                        return Err(LoadingError::Parsing(MissingIdError::Image(img_id)));
-                    },
-                    counts,
-                });
+                annotation.segmentation = match imgs.get(&img_id) {
+                    Some(img) => Segmentation::PolygonsRS(PolygonsRS {
+                        size: vec![img.height, img.width],
+                        counts,
+                    }),
+                    // Kept as is, for the validation to report the missing image.
+                    None if !strict => Segmentation::Polygons(counts),
+                    None => return Err(LoadingError::Parsing(MissingIdError::Image(img_id))),
+                };
             };
-
-            anns.insert(annotation.id, annotation);
-            img_to_anns.entry(img_id).or_default().insert(ann_id);
+            Ok(annotation)
+        })?;
+        for annotation in annotations {
+            coco_dataset.insert_ann(annotation);
         }
+        coco_dataset.imgs = imgs;
 
-        Ok(Self {
-            anns,
-            cats,
-            imgs,
-            img_to_anns,
-            image_folder: image_folder.as_ref().to_path_buf(),
-        })
+        Ok(coco_dataset)
+    }
+
+    /// Insert the annotation in the dataset and in the indexes, replacing the annotation with the same id if there is one.
+    pub(crate) fn insert_ann(&mut self, ann: Annotation) {
+        self.take_ann(ann.id);
+        self.img_to_anns
+            .entry(ann.image_id)
+            .or_default()
+            .insert(ann.id);
+        self.cat_to_anns
+            .entry(ann.category_id)
+            .or_default()
+            .insert(ann.id);
+        self.cat_to_imgs
+            .entry(ann.category_id)
+            .or_default()
+            .insert(ann.image_id);
+        self.anns.insert(ann.id, ann);
+    }
+
+    /// Remove the annotation from the dataset and from the indexes.
+    fn take_ann(&mut self, ann_id: u64) -> Option<Annotation> {
+        let ann = self.anns.remove(&ann_id)?;
+        if let Some(img_anns) = self.img_to_anns.get_mut(&ann.image_id) {
+            img_anns.remove(&ann_id);
+        }
+        if let Some(cat_anns) = self.cat_to_anns.get_mut(&ann.category_id) {
+            cat_anns.remove(&ann_id);
+        }
+        let img_has_cat = self
+            .img_to_anns
+            .get(&ann.image_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|other_id| self.anns.get(other_id))
+            .any(|other| other.category_id == ann.category_id);
+        if !img_has_cat {
+            if let Some(cat_imgs) = self.cat_to_imgs.get_mut(&ann.category_id) {
+                cat_imgs.remove(&ann.image_id);
+            }
+        }
+        Some(ann)
     }
 
     /// Return the annotation corresponding to the given annotation id.
@@ -335,6 +526,46 @@ impl BTreemapDataset {
         self.imgs.values().collect()
     }
 
+    /// Returns the information about the dataset.
+    #[must_use]
+    pub const fn get_info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Returns the licenses of the images of the dataset.
+    #[must_use]
+    pub fn get_licenses(&self) -> &[License] {
+        &self.licenses
+    }
+
+    /// Returns the fields of the annotation file that are not part of the COCO format.
+    #[must_use]
+    pub const fn get_extra_fields(&self) -> &ExtraFields {
+        &self.extra
+    }
+
+    /// Return a new dataset with only the images, annotations and categories selected by the filter.
+    ///
+    /// See [`Filter`] for the available conditions.
+    #[must_use]
+    pub fn filter(&self, filter: &Filter) -> Self {
+        filter.apply(self)
+    }
+
+    /// Remap the categories of the dataset and return the new id of each of the old categories (`None` if dropped).
+    ///
+    /// See [`CategoryRemapping`] for how the categories are merged, renamed and dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` (without modifying the dataset) if the remapping is not valid for this dataset.
+    pub fn remap_categories(
+        &mut self,
+        remapping: &CategoryRemapping,
+    ) -> Result<CategoryIdMap, RemapError> {
+        remapping.apply(self)
+    }
+
     /// Return the annotations for the given image id.
     ///
     /// # Errors
@@ -348,6 +579,363 @@ impl BTreemapDataset {
             })
     }
 
+    /// Return the ids of the annotations satisfying all the given conditions, sorted.
+    ///
+    /// Like pycocotools' `getAnnIds`, an empty `img_ids` or `cat_ids` is not used as a condition,
+    /// and the area must be strictly within `area_range`. Image ids that are not in the dataset are ignored.
+    #[must_use]
+    pub fn get_ann_ids(
+        &self,
+        img_ids: &[u64],
+        cat_ids: &[u32],
+        area_range: Option<(f64, f64)>,
+        iscrowd: Option<bool>,
+    ) -> Vec<u64> {
+        let mut ann_ids: Vec<u64> = if img_ids.is_empty() && cat_ids.is_empty() {
+            self.anns.keys().copied().collect()
+        } else if img_ids.is_empty() {
+            cat_ids
+                .iter()
+                .filter_map(|cat_id| self.cat_to_anns.get(cat_id))
+                .flatten()
+                .copied()
+                .collect()
+        } else {
+            img_ids
+                .iter()
+                .filter_map(|img_id| self.img_to_anns.get(img_id))
+                .flatten()
+                .copied()
+                .filter(|ann_id| {
+                    cat_ids.is_empty()
+                        || self
+                            .anns
+                            .get(ann_id)
+                            .map_or(false, |ann| cat_ids.contains(&ann.category_id))
+                })
+                .collect()
+        };
+        ann_ids.retain(|ann_id| {
+            self.anns.get(ann_id).map_or(false, |ann| {
+                area_range.map_or(true, |(min_area, max_area)| {
+                    ann.area > min_area && ann.area < max_area
+                }) && iscrowd.map_or(true, |iscrowd| (ann.iscrowd != 0) == iscrowd)
+            })
+        });
+        ann_ids.sort_unstable();
+        ann_ids.dedup();
+        ann_ids
+    }
+
+    /// Return the ids of the categories satisfying all the given conditions, sorted.
+    ///
+    /// Like pycocotools' `getCatIds`, empty slices are not used as conditions.
+    #[must_use]
+    pub fn get_cat_ids(&self, cat_names: &[&str], sup_names: &[&str], cat_ids: &[u32]) -> Vec<u32> {
+        self.cats
+            .values()
+            .filter(|cat| {
+                (cat_names.is_empty() || cat_names.contains(&cat.name.as_str()))
+                    && (sup_names.is_empty()
+                        || cat
+                            .supercategory
+                            .as_ref()
+                            .map_or(false, |sup_name| sup_names.contains(&sup_name.as_str())))
+                    && (cat_ids.is_empty() || cat_ids.contains(&cat.id))
+            })
+            .map(|cat| cat.id)
+            .collect()
+    }
+
+    /// Return the ids of the images satisfying all the given conditions, sorted.
+    ///
+    /// Like pycocotools' `getImgIds`, the images must contain at least one annotation of *each* of the given categories,
+    /// and empty slices are not used as conditions.
+    #[must_use]
+    pub fn get_img_ids(&self, img_ids: &[u64], cat_ids: &[u32]) -> Vec<u64> {
+        let mut selected_ids: Vec<u64> = if img_ids.is_empty() {
+            match cat_ids.split_first() {
+                None => return self.imgs.keys().copied().collect(),
+                Some((first_cat_id, _)) => self
+                    .cat_to_imgs
+                    .get(first_cat_id)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .collect(),
+            }
+        } else {
+            img_ids.to_vec()
+        };
+        selected_ids.retain(|img_id| {
+            cat_ids.iter().all(|cat_id| {
+                self.cat_to_imgs
+                    .get(cat_id)
+                    .map_or(false, |cat_imgs| cat_imgs.contains(img_id))
+            })
+        });
+        selected_ids.sort_unstable();
+        selected_ids.dedup();
+        selected_ids
+    }
+
+    /// Return an image id that is not used in the dataset (the largest image id plus one).
+    #[must_use]
+    pub fn next_img_id(&self) -> u64 {
+        self.imgs.keys().next_back().map_or(1, |img_id| img_id + 1)
+    }
+
+    /// Return an annotation id that is not used in the dataset (the largest annotation id plus one).
+    #[must_use]
+    pub fn next_ann_id(&self) -> u64 {
+        self.anns.keys().next_back().map_or(1, |ann_id| ann_id + 1)
+    }
+
+    /// Return a category id that is not used in the dataset (the largest category id plus one).
+    #[must_use]
+    pub fn next_cat_id(&self) -> u32 {
+        self.cats.keys().next_back().map_or(1, |cat_id| cat_id + 1)
+    }
+
+    /// Add an image to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an image with the same id in the dataset.
+    pub fn add_img(&mut self, img: Image) -> Result<(), MutationError> {
+        if self.imgs.contains_key(&img.id) {
+            return Err(MutationError::DuplicateImage(img.id));
+        }
+        self.img_to_anns.entry(img.id).or_default();
+        self.imgs.insert(img.id, img);
+        Ok(())
+    }
+
+    /// Replace the image entry with the same id and return the previous one.
+    ///
+    /// The size stored in the polygon annotations of the image is updated to the new image size.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    pub fn update_img(&mut self, img: Image) -> Result<Image, MissingIdError> {
+        let old_img = self
+            .imgs
+            .get_mut(&img.id)
+            .ok_or(MissingIdError::Image(img.id))?;
+        for ann_id in self.img_to_anns.get(&img.id).into_iter().flatten() {
+            if let Some(Annotation {
+                segmentation: Segmentation::PolygonsRS(polygons),
+                ..
+            }) = self.anns.get_mut(ann_id)
+            {
+                polygons.size = vec![img.height, img.width];
+            }
+        }
+        Ok(std::mem::replace(old_img, img))
+    }
+
+    /// Remove the image and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    pub fn remove_img(&mut self, img_id: u64) -> Result<(Image, Vec<Annotation>), MissingIdError> {
+        let img = self
+            .imgs
+            .remove(&img_id)
+            .ok_or(MissingIdError::Image(img_id))?;
+        let mut ann_ids: Vec<u64> = self
+            .img_to_anns
+            .remove(&img_id)
+            .into_iter()
+            .flatten()
+            .collect();
+        ann_ids.sort_unstable();
+        let anns = ann_ids
+            .into_iter()
+            .filter_map(|ann_id| self.take_ann(ann_id))
+            .collect();
+        Ok((img, anns))
+    }
+
+    /// Add an annotation to the dataset.
+    ///
+    /// Polygons are converted to the internal format, which contains the size of the image.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an annotation with the same id in the dataset,
+    /// or if the image or category of the annotation is not in the dataset.
+    pub fn add_ann(&mut self, ann: Annotation) -> Result<(), MutationError> {
+        if self.anns.contains_key(&ann.id) {
+            return Err(MutationError::DuplicateAnnotation(ann.id));
+        }
+        let ann = self.checked_ann(ann)?;
+        self.insert_ann(ann);
+        Ok(())
+    }
+
+    /// Replace the annotation with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset,
+    /// or if the image or category of the new annotation is not in the dataset.
+    pub fn update_ann(&mut self, ann: Annotation) -> Result<Annotation, MutationError> {
+        if !self.anns.contains_key(&ann.id) {
+            return Err(MissingIdError::Annotation(ann.id).into());
+        }
+        let ann = self.checked_ann(ann)?;
+        let old_ann = self.remove_ann(ann.id)?;
+        self.insert_ann(ann);
+        Ok(old_ann)
+    }
+
+    /// Replace the annotations with the same ids, all at once: if one of them cannot be updated, none of them is.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset,
+    /// or if the image or category of one of the new annotations is not in the dataset.
+    pub fn update_anns(&mut self, anns: Vec<Annotation>) -> Result<(), MutationError> {
+        let anns = anns
+            .into_iter()
+            .map(|ann| {
+                if !self.anns.contains_key(&ann.id) {
+                    return Err(MissingIdError::Annotation(ann.id));
+                }
+                self.checked_ann(ann)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for ann in anns {
+            match self.anns.get_mut(&ann.id) {
+                // The indexes only need to be updated if the image or category changes.
+                Some(old_ann)
+                    if old_ann.image_id == ann.image_id
+                        && old_ann.category_id == ann.category_id =>
+                {
+                    *old_ann = ann;
+                }
+                _ => self.insert_ann(ann),
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the segmentations of the annotations with the given ids, all at once: if one of them cannot be updated,
+    /// none of them is. The annotations are modified in place.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset.
+    pub fn update_segmentations(
+        &mut self,
+        segmentations: Vec<(u64, Segmentation)>,
+    ) -> Result<(), MissingIdError> {
+        if let Some((ann_id, _)) = segmentations
+            .iter()
+            .find(|(ann_id, _)| !self.anns.contains_key(ann_id))
+        {
+            return Err(MissingIdError::Annotation(*ann_id));
+        }
+        for (ann_id, segmentation) in segmentations {
+            if let Some(ann) = self.anns.get_mut(&ann_id) {
+                ann.segmentation = match (segmentation, self.imgs.get(&ann.image_id)) {
+                    (Segmentation::Polygons(counts), Some(img)) => {
+                        Segmentation::PolygonsRS(PolygonsRS {
+                            size: vec![img.height, img.width],
+                            counts,
+                        })
+                    }
+                    (segmentation, _) => segmentation,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the annotation from the dataset and return it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset.
+    pub fn remove_ann(&mut self, ann_id: u64) -> Result<Annotation, MissingIdError> {
+        self.take_ann(ann_id)
+            .ok_or(MissingIdError::Annotation(ann_id))
+    }
+
+    /// Check that the image and category of the annotation exist, and convert its polygons to the internal format.
+    fn checked_ann(&self, mut ann: Annotation) -> Result<Annotation, MissingIdError> {
+        self.get_cat(ann.category_id)?;
+        let img = self.get_img(ann.image_id)?;
+        if let Segmentation::Polygons(counts) = ann.segmentation {
+            ann.segmentation = Segmentation::PolygonsRS(PolygonsRS {
+                size: vec![img.height, img.width],
+                counts,
+            });
+        }
+        Ok(ann)
+    }
+
+    /// Add a category to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already a category with the same id in the dataset.
+    pub fn add_cat(&mut self, cat: Category) -> Result<(), MutationError> {
+        if self.cats.contains_key(&cat.id) {
+            return Err(MutationError::DuplicateCategory(cat.id));
+        }
+        self.cat_to_anns.entry(cat.id).or_default();
+        self.cat_to_imgs.entry(cat.id).or_default();
+        self.cats.insert(cat.id, cat);
+        Ok(())
+    }
+
+    /// Replace the category with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    pub fn update_cat(&mut self, cat: Category) -> Result<Category, MissingIdError> {
+        let old_cat = self
+            .cats
+            .get_mut(&cat.id)
+            .ok_or(MissingIdError::Category(cat.id))?;
+        Ok(std::mem::replace(old_cat, cat))
+    }
+
+    /// Remove the category and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    pub fn remove_cat(
+        &mut self,
+        cat_id: u32,
+    ) -> Result<(Category, Vec<Annotation>), MissingIdError> {
+        let cat = self
+            .cats
+            .remove(&cat_id)
+            .ok_or(MissingIdError::Category(cat_id))?;
+        let mut ann_ids: Vec<u64> = self
+            .cat_to_anns
+            .get(&cat_id)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        ann_ids.sort_unstable();
+        let anns = ann_ids
+            .into_iter()
+            .filter_map(|ann_id| self.take_ann(ann_id))
+            .collect();
+        self.cat_to_anns.remove(&cat_id);
+        self.cat_to_imgs.remove(&cat_id);
+        Ok((cat, anns))
+    }
+
     /// Draw the annotations for the given image id on the image and return it.
     ///
     /// # Errors
@@ -382,21 +970,55 @@ impl BTreemapDataset {
         Ok(())
     }
 
-    /// Save the dataset to the given path.
+    /// Save the dataset to the given path as pretty printed json, compressed if the path ends with `.gz` or `.zst` (see [`compression`]).
     ///
     /// # Errors
     ///
     /// Will return `Err` if:
     ///   - The file cannot be created (if the full directory path does not exist for example).
+    ///   - The extension requires a compression whose feature is not enabled.
     ///   - The implementation of `Serialize` fails or the dataset contains non-string keys.
     pub fn save_to<P: AsRef<Path>>(&self, output_path: P) -> Result<(), Box<dyn Error>> {
-        let dataset = Dataset::from(self);
-        let f = fs::File::create(output_path)?;
-        serde_json::to_writer_pretty(&f, &dataset)?;
+        self.save_to_with_option(output_path, SaveOption::default())
+    }
+
+    /// Save the dataset to the given path, formatted according to the option (see [`SaveOption`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` in the same cases as [`BTreemapDataset::save_to`].
+    pub fn save_to_with_option<P: AsRef<Path>>(
+        &self,
+        output_path: P,
+        option: SaveOption,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut writer = compression::Writer::create(output_path.as_ref())?;
+        streaming::write(self, &mut writer, option)?;
+        writer.finish()?;
 
         Ok(())
     }
 
+    /// Return the images, annotations and categories as Arrow tables (see [`tables`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a segmentation cannot be converted to COCO RLE.
+    #[cfg(feature = "parquet")]
+    pub fn to_arrow(&self) -> Result<tables::Tables, TableError> {
+        tables::to_arrow(self)
+    }
+
+    /// Save the images, annotations and categories as Parquet files in `output_dir` (see [`tables`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a segmentation cannot be converted to COCO RLE, or if the files cannot be written.
+    #[cfg(feature = "parquet")]
+    pub fn save_parquet<P: AsRef<Path>>(&self, output_dir: P) -> Result<(), TableError> {
+        tables::save_parquet(self, output_dir)
+    }
+
     /// Return the dataset as a json string.
     ///
     /// # Errors
@@ -412,9 +1034,22 @@ impl From<&BTreemapDataset> for Dataset {
     fn from(dataset: &BTreemapDataset) -> Self {
         Self {
             images: dataset.get_imgs().into_iter().cloned().collect(),
-            annotations: dataset.get_anns().into_iter().cloned().collect(),
+            annotations: dataset
+                .get_anns()
+                .into_iter()
+                .map(|ann| {
+                    let mut ann = ann.clone();
+                    // The size of the polygons is not part of the COCO format.
+                    if let Segmentation::PolygonsRS(poly) = ann.segmentation {
+                        ann.segmentation = Segmentation::Polygons(poly.counts);
+                    }
+                    ann
+                })
+                .collect(),
             categories: dataset.get_cats().into_iter().cloned().collect(),
-            ..Default::default()
+            info: dataset.info.clone(),
+            licenses: dataset.licenses.clone(),
+            extra: dataset.extra.clone(),
         }
     }
 }
@@ -512,13 +1147,416 @@ impl<'de> Deserialize<'de> for Bbox {
     }
 }
 
+/// Key of a JSON object: one of the fields of its struct, or an unknown field kept in its `extra` fields.
+enum Key<F> {
+    Field(F),
+    Unknown(String),
+}
+
+/// Deserializes a key, without allocating a string for the known fields.
+struct KeySeed<F: 'static>(&'static [(&'static str, F)]);
+
+impl<'de, F: Copy> DeserializeSeed<'de> for KeySeed<F> {
+    type Value = Key<F>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de, F: Copy> Visitor<'de> for KeySeed<F> {
+    type Value = Key<F>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a field name")
+    }
+
+    fn visit_str<E>(self, key: &str) -> Result<Key<F>, E>
+    where
+        E: de::Error,
+    {
+        Ok(self.0.iter().find(|(name, _)| *name == key).map_or_else(
+            || Key::Unknown(key.to_string()),
+            |(_, field)| Key::Field(*field),
+        ))
+    }
+}
+
+/// Deserializes the value of a field, which must not have been set already.
+fn next_field<'de, A, T>(
+    map: &mut A,
+    value: &mut Option<T>,
+    name: &'static str,
+) -> Result<(), A::Error>
+where
+    A: MapAccess<'de>,
+    T: Deserialize<'de>,
+{
+    if value.is_some() {
+        return Err(de::Error::duplicate_field(name));
+    }
+    *value = Some(map.next_value()?);
+    Ok(())
+}
+
+/// Implements `Deserialize` for a struct whose unknown fields are kept in its `extra` field, the optional fields taking
+/// their default value when missing.
+///
+/// This replaces `#[serde(flatten)]`, which buffers the whole object before deserializing it and makes loading a
+/// dataset about twice slower.
+macro_rules! deserialize_with_extra_fields {
+    ($name:ident, $expecting:literal, required: [$($required:ident),*], optional: [$($optional:ident),*]) => {
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                #[allow(non_camel_case_types)]
+                #[derive(Clone, Copy)]
+                enum Field {
+                    $($required,)*
+                    $($optional,)*
+                }
+
+                const FIELDS: &[(&str, Field)] = &[
+                    $((stringify!($required), Field::$required),)*
+                    $((stringify!($optional), Field::$optional),)*
+                ];
+
+                struct ObjectVisitor;
+
+                impl<'de> Visitor<'de> for ObjectVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                        formatter.write_str($expecting)
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> Result<$name, A::Error>
+                    where
+                        A: MapAccess<'de>,
+                    {
+                        $(let mut $required = None;)*
+                        $(let mut $optional = None;)*
+                        let mut extra = serde_json::Map::new();
+                        while let Some(key) = map.next_key_seed(KeySeed(FIELDS))? {
+                            match key {
+                                $(Key::Field(Field::$required) => {
+                                    next_field(&mut map, &mut $required, stringify!($required))?;
+                                })*
+                                $(Key::Field(Field::$optional) => {
+                                    next_field(&mut map, &mut $optional, stringify!($optional))?;
+                                })*
+                                Key::Unknown(key) => {
+                                    extra.insert(key, map.next_value()?);
+                                }
+                            }
+                        }
+                        Ok($name {
+                            $($required: $required
+                                .ok_or_else(|| de::Error::missing_field(stringify!($required)))?,)*
+                            $($optional: $optional.unwrap_or_default(),)*
+                            extra: ExtraFields(extra),
+                        })
+                    }
+                }
+
+                deserializer.deserialize_map(ObjectVisitor)
+            }
+        }
+    };
+}
+
+deserialize_with_extra_fields!(
+    Dataset,
+    "a COCO dataset",
+    required: [images, annotations, categories],
+    optional: [info, licenses]
+);
+deserialize_with_extra_fields!(
+    Info,
+    "an info object",
+    required: [],
+    optional: [year, version, description, contributor, url, date_created]
+);
+deserialize_with_extra_fields!(
+    License,
+    "a license object",
+    required: [id, name, url],
+    optional: []
+);
+deserialize_with_extra_fields!(
+    Image,
+    "an image object",
+    required: [id, width, height],
+    optional: [
+        file_name,
+        license,
+        flickr_url,
+        coco_url,
+        date_captured,
+        neg_category_ids,
+        not_exhaustive_category_ids
+    ]
+);
+deserialize_with_extra_fields!(
+    Annotation,
+    "an annotation object",
+    required: [id, image_id, category_id, segmentation, area, bbox],
+    optional: [iscrowd]
+);
+deserialize_with_extra_fields!(
+    Category,
+    "a category object",
+    required: [id, name],
+    optional: [supercategory, frequency, synonyms]
+);
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
-    use super::PolygonsRS;
+    use super::{
+        BTreemapDataset, Category, Dataset, Image, MissingIdError, MutationError, PolygonsRS,
+        Segmentation,
+    };
+    use crate::test_utils::DatasetBuilder;
     use rstest::rstest;
 
+    fn dataset() -> BTreemapDataset {
+        DatasetBuilder::new()
+            .img(1, 100, 100)
+            .img(2, 100, 100)
+            .img(3, 100, 100)
+            .ann(1, 1, 1, 10.0, 10.0)
+            .ann(2, 1, 2, 20.0, 100.0)
+            .crowd_ann(3, 2, 1, 50.0, 100.0)
+            .ann(4, 2, 3, 5.0, 10.0)
+            .ann(5, 1, 1, 20.0, 20.0)
+            .cat(1, "person", Some("person"))
+            .cat(2, "car", Some("vehicle"))
+            .cat(3, "bus", Some("vehicle"))
+            .build()
+    }
+
+    #[rstest]
+    #[case::all(&[], &[], None, None, vec![1, 2, 3, 4, 5])]
+    #[case::img_ids(&[2, 3, 42], &[], None, None, vec![3, 4])]
+    #[case::cat_ids(&[], &[1], None, None, vec![1, 3, 5])]
+    #[case::img_and_cat_ids(&[1], &[1, 3], None, None, vec![1, 5])]
+    #[case::area_range(&[], &[], Some((100.0, 5000.0)), None, vec![2, 5])]
+    #[case::iscrowd(&[], &[1], None, Some(false), vec![1, 5])]
+    fn get_ann_ids(
+        #[case] img_ids: &[u64],
+        #[case] cat_ids: &[u32],
+        #[case] area_range: Option<(f64, f64)>,
+        #[case] iscrowd: Option<bool>,
+        #[case] expected: Vec<u64>,
+    ) {
+        assert_eq!(
+            dataset().get_ann_ids(img_ids, cat_ids, area_range, iscrowd),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::all(&[], &[], &[], vec![1, 2, 3])]
+    #[case::names(&["car", "bus", "truck"], &[], &[], vec![2, 3])]
+    #[case::supercategories(&[], &["vehicle"], &[], vec![2, 3])]
+    #[case::all_conditions(&["person", "car"], &["vehicle"], &[2, 3], vec![2])]
+    fn get_cat_ids(
+        #[case] cat_names: &[&str],
+        #[case] sup_names: &[&str],
+        #[case] cat_ids: &[u32],
+        #[case] expected: Vec<u32>,
+    ) {
+        assert_eq!(
+            dataset().get_cat_ids(cat_names, sup_names, cat_ids),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::all(&[], &[], vec![1, 2, 3])]
+    #[case::img_ids(&[3, 1], &[], vec![1, 3])]
+    #[case::one_cat(&[], &[1], vec![1, 2])]
+    #[case::every_cat(&[], &[1, 2], vec![1])]
+    #[case::img_ids_and_cat(&[2, 3], &[1], vec![2])]
+    fn get_img_ids(#[case] img_ids: &[u64], #[case] cat_ids: &[u32], #[case] expected: Vec<u64>) {
+        assert_eq!(dataset().get_img_ids(img_ids, cat_ids), expected);
+    }
+
+    #[test]
+    fn indexes_follow_annotation_changes() {
+        let mut dataset = dataset();
+        dataset.remove_ann(1).unwrap();
+        assert_eq!(dataset.get_img_ids(&[], &[1]), vec![1, 2]);
+        dataset.remove_ann(5).unwrap();
+        assert_eq!(dataset.get_img_ids(&[], &[1]), vec![2]);
+        assert_eq!(dataset.get_ann_ids(&[1], &[], None, None), vec![2]);
+
+        let mut ann = dataset.get_ann(4).unwrap().clone();
+        ann.image_id = 3;
+        ann.category_id = 2;
+        dataset.insert_ann(ann);
+        assert_eq!(dataset.get_img_ids(&[], &[3]), Vec::<u64>::new());
+        assert_eq!(dataset.get_img_ids(&[], &[2]), vec![1, 3]);
+        assert_eq!(dataset.get_ann_ids(&[], &[2], None, None), vec![2, 4]);
+    }
+
+    #[test]
+    fn save_is_lossless() {
+        let json = serde_json::json!({
+            "info": {"year": 2020, "source": "lvis"},
+            "licenses": [{"id": 1, "name": "CC BY 4.0", "url": "https://creativecommons.org/licenses/by/4.0/"}],
+            "images": [
+                {"id": 1, "width": 20, "height": 10, "file_name": "1.jpg"},
+                {"id": 2, "width": 20, "height": 10, "file_name": "2.jpg", "license": 1, "coco_url": "", "neg_category_ids": [2], "not_exhaustive_category_ids": []}
+            ],
+            "annotations": [{
+                "id": 1, "image_id": 1, "category_id": 1, "segmentation": [[1.0, 1.0, 5.0, 1.0, 5.0, 5.0]],
+                "area": 8.0, "bbox": [1.0, 1.0, 4.0, 4.0], "iscrowd": 0, "attributes": {"occluded": true, "color": "red"}
+            }],
+            "categories": [
+                {"id": 1, "name": "person"},
+                {"id": 2, "name": "dog", "supercategory": "animal", "frequency": "f", "synonyms": ["dog", "puppy"]}
+            ],
+            "split": "train"
+        });
+        let dataset: Dataset = serde_json::from_value(json.clone()).unwrap();
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        assert_eq!(dataset.get_info().extra.0["source"], "lvis");
+        assert_eq!(dataset.get_licenses().len(), 1);
+        assert_eq!(dataset.get_extra_fields().0["split"], "train");
+
+        let saved: serde_json::Value = serde_json::from_str(&dataset.json().unwrap()).unwrap();
+        assert_eq!(saved, json);
+    }
+
+    #[test]
+    fn save_without_info_and_licenses() {
+        let json = serde_json::json!({"images": [], "annotations": [], "categories": []});
+        let dataset: Dataset = serde_json::from_value(json.clone()).unwrap();
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&dataset.json().unwrap()).unwrap();
+        assert_eq!(saved, json);
+    }
+
+    #[rstest]
+    #[case::missing_field(r#"{"id": 1, "width": 20}"#, "missing field `height`")]
+    #[case::duplicate_field(
+        r#"{"id": 1, "width": 20, "height": 10, "width": 30}"#,
+        "duplicate field `width`"
+    )]
+    #[case::invalid_type(
+        r#"{"id": 1, "width": 20, "height": 10, "license": "MIT"}"#,
+        "invalid type"
+    )]
+    fn invalid_image(#[case] json: &str, #[case] expected_error: &str) {
+        let error = serde_json::from_str::<Image>(json).unwrap_err();
+        assert!(error.to_string().contains(expected_error), "{error}");
+    }
+
+    #[test]
+    fn null_optional_field() {
+        let img: Image =
+            serde_json::from_str(r#"{"id": 1, "width": 20, "height": 10, "license": null}"#)
+                .unwrap();
+        assert_eq!(
+            img,
+            Image {
+                id: 1,
+                width: 20,
+                height: 10,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn mutations_keep_the_dataset_consistent() {
+        let mut dataset = dataset();
+        assert_eq!(
+            (
+                dataset.next_img_id(),
+                dataset.next_ann_id(),
+                dataset.next_cat_id()
+            ),
+            (4, 6, 4)
+        );
+
+        let img = Image {
+            id: dataset.next_img_id(),
+            width: 50,
+            height: 40,
+            file_name: "4.jpg".to_string(),
+            ..Default::default()
+        };
+        dataset.add_img(img.clone()).unwrap();
+        assert!(matches!(
+            dataset.add_img(img),
+            Err(MutationError::DuplicateImage(4))
+        ));
+        dataset
+            .add_cat(Category {
+                id: 4,
+                name: "dog".to_string(),
+                supercategory: None,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut ann = dataset.get_ann(1).unwrap().clone();
+        ann.id = dataset.next_ann_id();
+        ann.image_id = 4;
+        ann.category_id = 4;
+        ann.segmentation = Segmentation::Polygons(vec![vec![0.0, 0.0, 10.0, 0.0, 10.0, 10.0]]);
+        dataset.add_ann(ann.clone()).unwrap();
+        assert!(matches!(
+            dataset.add_ann(ann.clone()),
+            Err(MutationError::DuplicateAnnotation(6))
+        ));
+        assert!(matches!(
+            dataset.get_ann(6).unwrap().segmentation,
+            Segmentation::PolygonsRS(PolygonsRS { ref size, .. }) if size == &vec![40, 50]
+        ));
+        assert_eq!(dataset.get_img_ids(&[], &[4]), vec![4]);
+
+        ann.category_id = 42;
+        assert!(matches!(
+            dataset.update_ann(ann.clone()),
+            Err(MutationError::MissingId(MissingIdError::Category(42)))
+        ));
+        ann.category_id = 2;
+        assert_eq!(dataset.update_ann(ann).unwrap().category_id, 4);
+        assert_eq!(dataset.get_img_ids(&[], &[4]), Vec::<u64>::new());
+        assert_eq!(dataset.get_img_ids(&[], &[2]), vec![1, 4]);
+
+        let mut img = dataset.get_img(4).unwrap().clone();
+        img.width = 60;
+        assert_eq!(dataset.update_img(img).unwrap().width, 50);
+        assert!(matches!(
+            dataset.get_ann(6).unwrap().segmentation,
+            Segmentation::PolygonsRS(PolygonsRS { ref size, .. }) if size == &vec![40, 60]
+        ));
+
+        let (_, removed_anns) = dataset.remove_img(1).unwrap();
+        let removed_ids: Vec<u64> = removed_anns.iter().map(|ann| ann.id).collect();
+        assert_eq!(removed_ids, vec![1, 2, 5]);
+        assert_eq!(dataset.get_ann_ids(&[], &[], None, None), vec![3, 4, 6]);
+        assert_eq!(dataset.get_img_ids(&[], &[2]), vec![4]);
+        assert!(dataset.get_img_anns(1).is_err());
+
+        let (cat, removed_anns) = dataset.remove_cat(1).unwrap();
+        assert_eq!(cat.name, "person");
+        assert_eq!(removed_anns.len(), 1);
+        assert_eq!(dataset.get_ann_ids(&[2], &[], None, None), vec![4]);
+        assert!(dataset.get_cat(1).is_err());
+        assert!(dataset.remove_ann(3).is_err());
+    }
+
     #[rstest]
     #[case::single_polygon(
         &PolygonsRS {size: vec![20, 20], counts: vec![vec![1.1, 2.1, 3.2, 4.2, 5.3, 6.3]] },
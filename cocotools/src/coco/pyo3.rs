@@ -1,5 +1,10 @@
+use std::collections::BTreeMap;
+
 use pyo3::class::basic::CompareOp;
+use pyo3::exceptions::PyTypeError;
 use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+use serde_json::Value;
 
 use crate::coco::object_detection::*;
 
@@ -23,6 +28,7 @@ impl Annotation {
             area,
             bbox,
             iscrowd,
+            extra: ExtraFields::default(),
         }
     }
 
@@ -59,18 +65,24 @@ impl Annotation {
 #[pymethods]
 impl Category {
     #[new]
-    fn new(id: u32, name: String, supercategory: String) -> Self {
+    #[pyo3(signature = (id, name, supercategory=None))]
+    fn new(id: u32, name: String, supercategory: Option<String>) -> Self {
         Self {
             id,
             name,
             supercategory,
+            ..Default::default()
         }
     }
 
     fn __repr__(&self) -> String {
+        let supercategory = self.supercategory.as_ref().map_or_else(
+            || "None".to_string(),
+            |supercategory| format!("'{supercategory}'"),
+        );
         format!(
-            "Category(id={}, name='{}', supercategory='{}')",
-            self.id, self.name, self.supercategory
+            "Category(id={}, name='{}', supercategory={})",
+            self.id, self.name, supercategory
         )
     }
 
@@ -126,6 +138,85 @@ impl Image {
     }
 }
 
+/// The extra fields are exposed to python as a dict.
+impl IntoPy<PyObject> for ExtraFields {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.0
+            .into_iter()
+            .map(|(key, value)| (key, json_into_py(value, py)))
+            .collect::<BTreeMap<String, PyObject>>()
+            .into_py(py)
+    }
+}
+
+impl<'source> FromPyObject<'source> for ExtraFields {
+    fn extract(obj: &'source PyAny) -> PyResult<Self> {
+        match json_from_py(obj.downcast::<PyDict>()?)? {
+            Value::Object(fields) => Ok(Self(fields)),
+            _ => unreachable!("a python dict is always converted to a json object"),
+        }
+    }
+}
+
+fn json_into_py(value: Value, py: Python<'_>) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(value) => value.into_py(py),
+        Value::Number(number) => {
+            if let Some(number) = number.as_u64() {
+                number.into_py(py)
+            } else if let Some(number) = number.as_i64() {
+                number.into_py(py)
+            } else {
+                number.as_f64().unwrap_or(f64::NAN).into_py(py)
+            }
+        }
+        Value::String(value) => value.into_py(py),
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| json_into_py(value, py))
+            .collect::<Vec<PyObject>>()
+            .into_py(py),
+        Value::Object(fields) => fields
+            .into_iter()
+            .map(|(key, value)| (key, json_into_py(value, py)))
+            .collect::<BTreeMap<String, PyObject>>()
+            .into_py(py),
+    }
+}
+
+fn json_from_py(obj: &PyAny) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(value) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(value.is_true()))
+    } else if obj.is_instance_of::<PyLong>()? {
+        match obj.extract::<u64>() {
+            Ok(value) => Ok(Value::from(value)),
+            Err(_) => Ok(Value::from(obj.extract::<i64>()?)),
+        }
+    } else if obj.is_instance_of::<PyFloat>()? {
+        Ok(Value::from(obj.extract::<f64>()?))
+    } else if let Ok(value) = obj.downcast::<PyString>() {
+        Ok(Value::String(value.to_str()?.to_string()))
+    } else if let Ok(values) = obj.downcast::<PyList>() {
+        values.iter().map(json_from_py).collect()
+    } else if let Ok(values) = obj.downcast::<PyTuple>() {
+        values.iter().map(json_from_py).collect()
+    } else if let Ok(fields) = obj.downcast::<PyDict>() {
+        fields
+            .iter()
+            .map(|(key, value)| Ok((key.extract::<String>()?, json_from_py(value)?)))
+            .collect::<PyResult<serde_json::Map<String, Value>>>()
+            .map(Value::Object)
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "Cannot convert {} to json",
+            obj.get_type().name()?
+        )))
+    }
+}
+
 #[pyclass]
 struct BboxIter {
     inner: std::vec::IntoIter<f64>,
@@ -0,0 +1,477 @@
+//! Module containing the remapping of the categories of a dataset (merging, renaming and dropping categories).
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coco::object_detection::{BTreemapDataset, Category};
+use crate::errors::RemapError;
+
+/// New id of each of the old categories of a remapped dataset, `None` for the dropped categories.
+pub type CategoryIdMap = BTreeMap<u32, Option<u32>>;
+
+/// Remapping of the categories of a dataset.
+///
+/// Each mapping sends a category of the dataset to a new category, or drops it (along with its annotations).
+/// Categories mapped to the same name are merged. Categories without a mapping are kept as-is, unless `drop_unmapped` is set.
+///
+/// The mapping file is a json with the same structure, for example:
+/// ```json
+/// {
+///   "mappings": [
+///     {"from": "van", "to": {"name": "vehicle", "supercategory": "transport"}},
+///     {"from": "truck", "to": "vehicle"},
+///     {"from": 7, "to": null}
+///   ],
+///   "renumber": true
+/// }
+/// ```
+///
+/// ```
+/// # use cocotools::coco::remap::{CategoryRemapping, NewCategory};
+/// let remapping = CategoryRemapping::new()
+///     .map("van", NewCategory::from("vehicle").supercategory("transport"))
+///     .map("truck", "vehicle")
+///     .drop(7)
+///     .renumber(true);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CategoryRemapping {
+    pub mappings: Vec<CategoryMapping>,
+    /// Whether to drop the categories (and their annotations) that are not in the mappings.
+    #[serde(default)]
+    pub drop_unmapped: bool,
+    /// Whether to renumber the categories with contiguous ids starting from 1 (keeping the order of the ids).
+    #[serde(default)]
+    pub renumber: bool,
+}
+
+/// Mapping of one category of the dataset.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CategoryMapping {
+    pub from: CategoryRef,
+    /// New category, `None` to drop the category and its annotations.
+    pub to: Option<NewCategory>,
+}
+
+/// Reference to a category of the dataset, by id or by name.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CategoryRef {
+    Id(u32),
+    Name(String),
+}
+
+/// Category a category is mapped to.
+///
+/// Fields left to `None` are taken from the category with the same name if there is one,
+/// and otherwise from the remapped category.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "NewCategoryRepr")]
+pub struct NewCategory {
+    pub id: Option<u32>,
+    pub name: Option<String>,
+    pub supercategory: Option<String>,
+}
+
+/// In the mapping file, a new category can be given as just its name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NewCategoryRepr {
+    Name(String),
+    Full {
+        id: Option<u32>,
+        name: Option<String>,
+        supercategory: Option<String>,
+    },
+}
+
+impl From<NewCategoryRepr> for NewCategory {
+    fn from(repr: NewCategoryRepr) -> Self {
+        match repr {
+            NewCategoryRepr::Name(name) => Self::from(name),
+            NewCategoryRepr::Full {
+                id,
+                name,
+                supercategory,
+            } => Self {
+                id,
+                name,
+                supercategory,
+            },
+        }
+    }
+}
+
+impl NewCategory {
+    #[must_use]
+    pub const fn id(mut self, id: u32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    #[must_use]
+    pub fn supercategory(mut self, supercategory: impl Into<String>) -> Self {
+        self.supercategory = Some(supercategory.into());
+        self
+    }
+}
+
+impl From<String> for NewCategory {
+    fn from(name: String) -> Self {
+        Self {
+            name: Some(name),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for NewCategory {
+    fn from(name: &str) -> Self {
+        Self::from(name.to_string())
+    }
+}
+
+impl From<u32> for CategoryRef {
+    fn from(id: u32) -> Self {
+        Self::Id(id)
+    }
+}
+
+impl From<&str> for CategoryRef {
+    fn from(name: &str) -> Self {
+        Self::Name(name.to_string())
+    }
+}
+
+impl fmt::Display for CategoryRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "with id {id}"),
+            Self::Name(name) => write!(f, "named {name:?}"),
+        }
+    }
+}
+
+impl CategoryRemapping {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the remapping from a json mapping file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be read or deserialized.
+    pub fn from_file<P: AsRef<Path>>(mapping_path: P) -> Result<Self, RemapError> {
+        let mapping_path = mapping_path.as_ref().to_path_buf();
+        let content = fs::read_to_string(&mapping_path)
+            .map_err(|err| RemapError::Read(err, mapping_path.clone()))?;
+        serde_json::from_str(&content).map_err(|err| RemapError::Deserialize(err, mapping_path))
+    }
+
+    #[must_use]
+    pub fn map(mut self, from: impl Into<CategoryRef>, to: impl Into<NewCategory>) -> Self {
+        self.mappings.push(CategoryMapping {
+            from: from.into(),
+            to: Some(to.into()),
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn drop(mut self, from: impl Into<CategoryRef>) -> Self {
+        self.mappings.push(CategoryMapping {
+            from: from.into(),
+            to: None,
+        });
+        self
+    }
+
+    #[must_use]
+    pub const fn drop_unmapped(mut self, drop_unmapped: bool) -> Self {
+        self.drop_unmapped = drop_unmapped;
+        self
+    }
+
+    #[must_use]
+    pub const fn renumber(mut self, renumber: bool) -> Self {
+        self.renumber = renumber;
+        self
+    }
+
+    /// Remaps the categories of the dataset, and returns the new id of each of the old categories (`None` if dropped).
+    ///
+    /// Annotations of dropped categories are removed, the others get the id of their new category.
+    /// Annotations whose category is not in the dataset are left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` (without modifying the dataset) if a mapping refers to a category not in the dataset,
+    /// if a category is mapped more than once, or if two different categories would end up with the same id.
+    pub fn apply(&self, dataset: &mut BTreemapDataset) -> Result<CategoryIdMap, RemapError> {
+        let (cats, id_map) = self.remapped_cats(dataset)?;
+
+        let anns = std::mem::take(&mut dataset.anns);
+        dataset.cat_to_anns = cats
+            .keys()
+            .map(|cat_id| (*cat_id, HashSet::new()))
+            .collect();
+        dataset.cat_to_imgs = dataset.cat_to_anns.clone();
+        dataset.img_to_anns.values_mut().for_each(HashSet::clear);
+        dataset.cats = cats;
+        for mut ann in anns.into_values() {
+            match id_map.get(&ann.category_id) {
+                Some(None) => continue,
+                Some(Some(new_id)) => ann.category_id = *new_id,
+                None => {}
+            }
+            dataset.insert_ann(ann);
+        }
+        Ok(id_map)
+    }
+
+    /// Computes the new categories and the new id of each of the old categories.
+    fn remapped_cats(
+        &self,
+        dataset: &BTreemapDataset,
+    ) -> Result<(BTreeMap<u32, Category>, CategoryIdMap), RemapError> {
+        let mut mappings: BTreeMap<u32, Option<&NewCategory>> = BTreeMap::new();
+        for mapping in &self.mappings {
+            let cat_id = match &mapping.from {
+                CategoryRef::Id(cat_id) => dataset.cats.get(cat_id).map(|cat| cat.id),
+                CategoryRef::Name(name) => dataset
+                    .cats
+                    .values()
+                    .find(|cat| &cat.name == name)
+                    .map(|cat| cat.id),
+            }
+            .ok_or_else(|| RemapError::UnknownCategory(mapping.from.clone()))?;
+            if mappings.insert(cat_id, mapping.to.as_ref()).is_some() {
+                return Err(RemapError::DuplicateMapping(cat_id));
+            }
+        }
+
+        // New categories are identified by their name. The unmapped categories are handled first so that
+        // merging a category into one of them preserves its id.
+        let mut new_cats: Vec<Category> = Vec::new();
+        let mut name_to_idx: HashMap<String, usize> = HashMap::new();
+        let mut old_to_idx: BTreeMap<u32, Option<usize>> = BTreeMap::new();
+        let unmapped = dataset
+            .cats
+            .values()
+            .filter(|cat| !mappings.contains_key(&cat.id));
+        for cat in unmapped {
+            if self.drop_unmapped {
+                old_to_idx.insert(cat.id, None);
+                continue;
+            }
+            new_cats.push(cat.clone());
+            name_to_idx
+                .entry(cat.name.clone())
+                .or_insert(new_cats.len() - 1);
+            old_to_idx.insert(cat.id, Some(new_cats.len() - 1));
+        }
+        for (cat_id, new_cat) in &mappings {
+            let new_cat = match new_cat {
+                Some(new_cat) => new_cat,
+                None => {
+                    old_to_idx.insert(*cat_id, None);
+                    continue;
+                }
+            };
+            let cat = &dataset.cats[cat_id];
+            let name = new_cat.name.clone().unwrap_or_else(|| cat.name.clone());
+            let idx = *name_to_idx.entry(name.clone()).or_insert_with(|| {
+                new_cats.push(Category {
+                    id: cat.id,
+                    name,
+                    ..cat.clone()
+                });
+                new_cats.len() - 1
+            });
+            if let Some(id) = new_cat.id {
+                new_cats[idx].id = id;
+            }
+            if let Some(supercategory) = &new_cat.supercategory {
+                new_cats[idx].supercategory = Some(supercategory.clone());
+            }
+            old_to_idx.insert(*cat_id, Some(idx));
+        }
+
+        let mut cats: BTreeMap<u32, Category> = BTreeMap::new();
+        for cat in &new_cats {
+            if let Some(other_cat) = cats.insert(cat.id, cat.clone()) {
+                return Err(RemapError::ConflictingIds(
+                    cat.id,
+                    other_cat.name,
+                    cat.name.clone(),
+                ));
+            }
+        }
+        if self.renumber {
+            let new_ids: HashMap<u32, u32> = cats.keys().copied().zip(1..).collect();
+            for cat in &mut new_cats {
+                cat.id = new_ids[&cat.id];
+            }
+            cats = new_cats.iter().map(|cat| (cat.id, cat.clone())).collect();
+        }
+
+        let id_map = old_to_idx
+            .into_iter()
+            .map(|(old_id, idx)| (old_id, idx.map(|idx| new_cats[idx].id)))
+            .collect();
+        Ok((cats, id_map))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_utils::DatasetBuilder;
+    use rstest::rstest;
+
+    fn dataset() -> BTreemapDataset {
+        DatasetBuilder::new()
+            .img(1, 10, 10)
+            .ann(1, 1, 1, 10.0, 10.0)
+            .ann(2, 1, 3, 10.0, 10.0)
+            .ann(3, 1, 5, 10.0, 10.0)
+            .ann(4, 1, 7, 10.0, 10.0)
+            .cat(1, "person", Some("person"))
+            .cat(3, "van", Some("car"))
+            .cat(5, "truck", Some("car"))
+            .cat(7, "vehicle", None)
+            .build()
+    }
+
+    fn cat_names(dataset: &BTreemapDataset) -> Vec<(u32, String, Option<String>)> {
+        dataset
+            .get_cats()
+            .into_iter()
+            .map(|cat| (cat.id, cat.name.clone(), cat.supercategory.clone()))
+            .collect()
+    }
+
+    fn ann_cat_ids(dataset: &BTreemapDataset) -> Vec<(u64, u32)> {
+        dataset
+            .get_anns()
+            .into_iter()
+            .map(|ann| (ann.id, ann.category_id))
+            .collect()
+    }
+
+    #[test]
+    fn merge_into_existing_category() {
+        let mut dataset = dataset();
+        let id_map = dataset
+            .remap_categories(
+                &CategoryRemapping::new()
+                    .map(
+                        "van",
+                        NewCategory::from("vehicle").supercategory("transport"),
+                    )
+                    .map(5, "vehicle"),
+            )
+            .unwrap();
+        assert_eq!(
+            id_map,
+            BTreeMap::from([(1, Some(1)), (3, Some(7)), (5, Some(7)), (7, Some(7))])
+        );
+        assert_eq!(
+            cat_names(&dataset),
+            vec![
+                (1, "person".to_string(), Some("person".to_string())),
+                (7, "vehicle".to_string(), Some("transport".to_string()))
+            ]
+        );
+        assert_eq!(ann_cat_ids(&dataset), vec![(1, 1), (2, 7), (3, 7), (4, 7)]);
+        assert_eq!(dataset.get_ann_ids(&[], &[7], None, None), vec![2, 3, 4]);
+        assert_eq!(dataset.get_img_ids(&[], &[3]), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn rename_drop_and_renumber() {
+        let mut dataset = dataset();
+        let id_map = dataset
+            .remap_categories(
+                &CategoryRemapping::new()
+                    .map("person", "pedestrian")
+                    .map("van", "car")
+                    .map("truck", "car")
+                    .drop(7)
+                    .renumber(true),
+            )
+            .unwrap();
+        assert_eq!(
+            id_map,
+            BTreeMap::from([(1, Some(1)), (3, Some(2)), (5, Some(2)), (7, None)])
+        );
+        assert_eq!(
+            cat_names(&dataset),
+            vec![
+                (1, "pedestrian".to_string(), Some("person".to_string())),
+                (2, "car".to_string(), Some("car".to_string()))
+            ]
+        );
+        assert_eq!(ann_cat_ids(&dataset), vec![(1, 1), (2, 2), (3, 2)]);
+        assert_eq!(dataset.get_img_anns(1).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn drop_unmapped_categories() {
+        let mut dataset = dataset();
+        dataset
+            .remap_categories(
+                &CategoryRemapping::new()
+                    .map("truck", NewCategory::default().id(2))
+                    .drop_unmapped(true),
+            )
+            .unwrap();
+        assert_eq!(
+            cat_names(&dataset),
+            vec![(2, "truck".to_string(), Some("car".to_string()))]
+        );
+        assert_eq!(ann_cat_ids(&dataset), vec![(3, 2)]);
+    }
+
+    #[rstest]
+    #[case::unknown_category(CategoryRemapping::new().drop("bus"))]
+    #[case::duplicate_mapping(CategoryRemapping::new().drop(3).map("van", "car"))]
+    #[case::conflicting_ids(CategoryRemapping::new().map("van", NewCategory::from("car").id(1)))]
+    fn invalid_remapping(#[case] remapping: CategoryRemapping) {
+        let mut dataset = dataset();
+        assert!(dataset.remap_categories(&remapping).is_err());
+        assert_eq!(dataset, self::dataset());
+    }
+
+    #[test]
+    fn deserialize_mapping_file() {
+        let remapping: CategoryRemapping = serde_json::from_str(
+            r#"{
+                "mappings": [
+                    {"from": "van", "to": {"name": "vehicle", "supercategory": "transport"}},
+                    {"from": "truck", "to": "vehicle"},
+                    {"from": 7, "to": null}
+                ],
+                "renumber": true
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            remapping,
+            CategoryRemapping::new()
+                .map(
+                    "van",
+                    NewCategory::from("vehicle").supercategory("transport")
+                )
+                .map("truck", "vehicle")
+                .drop(7)
+                .renumber(true)
+        );
+    }
+}
@@ -0,0 +1,1110 @@
+//! Module containing a dataset stored in a [SQLite](https://www.sqlite.org/) database instead of in memory.
+//!
+//! Each image, annotation and category is stored as a row with its json representation, along with the columns needed to
+//! query it (the annotations are indexed on their image and category ids). The info, licenses and unknown top-level fields
+//! are stored in a `metadata` table.
+//!
+//! The database is opened in WAL mode, so several [`SqliteDataset`] (from different threads or processes) can read and edit
+//! the same database file concurrently. Each edit is done in its own transaction.
+use std::borrow::Cow;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, Params, TransactionBehavior,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::coco::backend::{DatasetMut, DatasetView};
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Category, Dataset, ExtraFields, Image, Info, License, PolygonsRS,
+    Segmentation,
+};
+use crate::coco::streaming::{AnnotationRef, SaveOption};
+use crate::errors::{MissingIdError, MutationError, SqliteError};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS images (
+    id INTEGER PRIMARY KEY,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS annotations (
+    id INTEGER PRIMARY KEY,
+    image_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    area REAL NOT NULL,
+    iscrowd INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS annotations_image_id ON annotations (image_id);
+CREATE INDEX IF NOT EXISTS annotations_category_id ON annotations (category_id);
+CREATE TABLE IF NOT EXISTS metadata (
+    key TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+";
+
+/// The annotations are joined with their image to get the size of their polygons.
+const ANNS_QUERY: &str = "SELECT annotations.data, images.width, images.height FROM annotations
+    JOIN images ON images.id = annotations.image_id";
+
+/// How long to wait for another connection to finish writing before returning an error.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// COCO dataset stored in a SQLite database, with the same query and edit methods as [`BTreemapDataset`].
+///
+/// The elements are returned by value since they are read from the database.
+#[derive(Debug)]
+pub struct SqliteDataset {
+    conn: Connection,
+    pub image_folder: PathBuf,
+}
+
+impl SqliteDataset {
+    /// Opens the database at the given path, creating it (and its tables) if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be opened or created.
+    pub fn open<P: AsRef<Path>>(db_path: P, image_folder: P) -> Result<Self, SqliteError> {
+        Self::init(Connection::open(db_path)?, image_folder)
+    }
+
+    /// Creates an empty dataset in a database that only lives in memory.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be created.
+    pub fn open_in_memory<P: AsRef<Path>>(image_folder: P) -> Result<Self, SqliteError> {
+        Self::init(Connection::open_in_memory()?, image_folder)
+    }
+
+    fn init<P: AsRef<Path>>(conn: Connection, image_folder: P) -> Result<Self, SqliteError> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn,
+            image_folder: image_folder.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Adds all the images, annotations and categories of the dataset, and replaces the info, licenses and unknown fields.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` (without modifying the database) if an element has the same id as one already in the database.
+    pub fn import(&mut self, dataset: &BTreemapDataset) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for img in dataset.get_imgs() {
+            if contains(&tx, "images", img.id)? {
+                return Err(MutationError::DuplicateImage(img.id).into());
+            }
+            insert_img(&tx, img)?;
+        }
+        for cat in dataset.get_cats() {
+            if contains(&tx, "categories", u64::from(cat.id))? {
+                return Err(MutationError::DuplicateCategory(cat.id).into());
+            }
+            insert_cat(&tx, cat)?;
+        }
+        for ann in dataset.get_anns() {
+            if contains(&tx, "annotations", ann.id)? {
+                return Err(MutationError::DuplicateAnnotation(ann.id).into());
+            }
+            insert_ann(&tx, ann)?;
+        }
+        set_metadata(&tx, "info", dataset.get_info())?;
+        set_metadata(&tx, "licenses", dataset.get_licenses())?;
+        set_metadata(&tx, "extra", dataset.get_extra_fields())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Loads the COCO json annotation file (see [`BTreemapDataset::new`]) and imports it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the json file cannot be loaded, or if it cannot be imported (see [`SqliteDataset::import`]).
+    pub fn import_json<P: AsRef<Path>>(&mut self, annotations_path: P) -> Result<(), SqliteError> {
+        let dataset = BTreemapDataset::new(annotations_path.as_ref(), &self.image_folder)?;
+        self.import(&dataset)
+    }
+
+    /// Loads the whole dataset in memory.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn to_dataset(&self) -> Result<BTreemapDataset, SqliteError> {
+        let dataset = Dataset {
+            info: self.get_info()?,
+            images: query_all(&self.conn, "SELECT data FROM images ORDER BY id", [])?,
+            annotations: query_all(&self.conn, "SELECT data FROM annotations ORDER BY id", [])?,
+            categories: query_all(&self.conn, "SELECT data FROM categories ORDER BY id", [])?,
+            licenses: self.get_licenses()?,
+            extra: self.get_extra_fields()?,
+        };
+        Ok(BTreemapDataset::from_dataset(dataset, &self.image_folder)?)
+    }
+
+    /// Save the dataset as a COCO json file (see [`BTreemapDataset::save_to`]).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read or if the file cannot be written.
+    pub fn save_to<P: AsRef<Path>>(&self, output_path: P) -> Result<(), Box<dyn Error>> {
+        self.to_dataset()?.save_to(output_path)
+    }
+
+    /// Return the annotation corresponding to the given annotation id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry in the dataset corresponding to `ann_id`.
+    pub fn get_ann(&self, ann_id: u64) -> Result<Annotation, SqliteError> {
+        query_anns(&self.conn, "WHERE annotations.id = ?1", [ann_id])?
+            .pop()
+            .ok_or_else(|| MissingIdError::Annotation(ann_id).into())
+    }
+
+    /// Returns all the annotations of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_anns(&self) -> Result<Vec<Annotation>, SqliteError> {
+        query_anns(&self.conn, "ORDER BY annotations.id", [])
+    }
+
+    /// Return the category corresponding to the given category id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `cat_id`.
+    pub fn get_cat(&self, cat_id: u32) -> Result<Category, SqliteError> {
+        get_cat(&self.conn, cat_id)?.ok_or_else(|| MissingIdError::Category(cat_id).into())
+    }
+
+    /// Returns all the categories of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_cats(&self) -> Result<Vec<Category>, SqliteError> {
+        query_all(&self.conn, "SELECT data FROM categories ORDER BY id", [])
+    }
+
+    /// Return the image entry corresponding to the given image id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `img_id`.
+    pub fn get_img(&self, img_id: u64) -> Result<Image, SqliteError> {
+        get_img(&self.conn, img_id)?.ok_or_else(|| MissingIdError::Image(img_id).into())
+    }
+
+    /// Returns all the images of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_imgs(&self) -> Result<Vec<Image>, SqliteError> {
+        query_all(&self.conn, "SELECT data FROM images ORDER BY id", [])
+    }
+
+    /// Returns the information about the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_info(&self) -> Result<Info, SqliteError> {
+        get_metadata(&self.conn, "info")
+    }
+
+    /// Returns the licenses of the images of the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_licenses(&self) -> Result<Vec<License>, SqliteError> {
+        get_metadata(&self.conn, "licenses")
+    }
+
+    /// Returns the fields of the annotation file that are not part of the COCO format.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_extra_fields(&self) -> Result<ExtraFields, SqliteError> {
+        get_metadata(&self.conn, "extra")
+    }
+
+    /// Return the annotations for the given image id, sorted by id.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no entry corresponding to `img_id`.
+    pub fn get_img_anns(&self, img_id: u64) -> Result<Vec<Annotation>, SqliteError> {
+        if !contains(&self.conn, "images", img_id)? {
+            return Err(MissingIdError::Image(img_id).into());
+        }
+        query_anns(
+            &self.conn,
+            "WHERE annotations.image_id = ?1 ORDER BY annotations.id",
+            [img_id],
+        )
+    }
+
+    /// Return the ids of the annotations satisfying all the given conditions, sorted.
+    ///
+    /// See [`BTreemapDataset::get_ann_ids`] for the conditions.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_ann_ids(
+        &self,
+        img_ids: &[u64],
+        cat_ids: &[u32],
+        area_range: Option<(f64, f64)>,
+        iscrowd: Option<bool>,
+    ) -> Result<Vec<u64>, SqliteError> {
+        let mut conditions = Vec::new();
+        if !img_ids.is_empty() {
+            conditions.push(format!("image_id IN ({})", join_ids(img_ids)));
+        }
+        if !cat_ids.is_empty() {
+            conditions.push(format!("category_id IN ({})", join_ids(cat_ids)));
+        }
+        if area_range.is_some() {
+            conditions.push("area > ?1 AND area < ?2".to_string());
+        }
+        if let Some(iscrowd) = iscrowd {
+            conditions.push(format!("(iscrowd != 0) = {}", u8::from(iscrowd)));
+        }
+        let mut query = "SELECT id FROM annotations".to_string();
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(" ORDER BY id");
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let area_params =
+            area_range.map_or_else(Vec::new, |(min_area, max_area)| vec![min_area, max_area]);
+        let ann_ids = stmt.query_map(params_from_iter(area_params), |row| row.get(0))?;
+        Ok(ann_ids.collect::<Result<_, _>>()?)
+    }
+
+    /// Return the ids of the categories satisfying all the given conditions, sorted.
+    ///
+    /// See [`BTreemapDataset::get_cat_ids`] for the conditions.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_cat_ids(
+        &self,
+        cat_names: &[&str],
+        sup_names: &[&str],
+        cat_ids: &[u32],
+    ) -> Result<Vec<u32>, SqliteError> {
+        Ok(self
+            .get_cats()?
+            .into_iter()
+            .filter(|cat| {
+                (cat_names.is_empty() || cat_names.contains(&cat.name.as_str()))
+                    && (sup_names.is_empty()
+                        || cat
+                            .supercategory
+                            .as_ref()
+                            .map_or(false, |sup_name| sup_names.contains(&sup_name.as_str())))
+                    && (cat_ids.is_empty() || cat_ids.contains(&cat.id))
+            })
+            .map(|cat| cat.id)
+            .collect())
+    }
+
+    /// Return the ids of the images satisfying all the given conditions, sorted.
+    ///
+    /// See [`BTreemapDataset::get_img_ids`] for the conditions.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn get_img_ids(&self, img_ids: &[u64], cat_ids: &[u32]) -> Result<Vec<u64>, SqliteError> {
+        let cat_img_ids = |cat_id: &u32| {
+            query_ids(
+                &self.conn,
+                &format!("SELECT DISTINCT image_id FROM annotations WHERE category_id = {cat_id} ORDER BY image_id"),
+            )
+        };
+        let (mut selected_ids, cat_ids): (Vec<u64>, _) = if img_ids.is_empty() {
+            match cat_ids.split_first() {
+                None => return query_ids(&self.conn, "SELECT id FROM images ORDER BY id"),
+                Some((first_cat_id, other_cat_ids)) => (cat_img_ids(first_cat_id)?, other_cat_ids),
+            }
+        } else {
+            (img_ids.to_vec(), cat_ids)
+        };
+        for cat_id in cat_ids {
+            let cat_img_ids = cat_img_ids(cat_id)?;
+            selected_ids.retain(|img_id| cat_img_ids.binary_search(img_id).is_ok());
+        }
+        selected_ids.sort_unstable();
+        selected_ids.dedup();
+        Ok(selected_ids)
+    }
+
+    /// Return an image id that is not used in the dataset (the largest image id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn next_img_id(&self) -> Result<u64, SqliteError> {
+        Ok(max_id::<u64>(&self.conn, "images")?.map_or(1, |img_id| img_id + 1))
+    }
+
+    /// Return an annotation id that is not used in the dataset (the largest annotation id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn next_ann_id(&self) -> Result<u64, SqliteError> {
+        Ok(max_id::<u64>(&self.conn, "annotations")?.map_or(1, |ann_id| ann_id + 1))
+    }
+
+    /// Return a category id that is not used in the dataset (the largest category id plus one).
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database cannot be read.
+    pub fn next_cat_id(&self) -> Result<u32, SqliteError> {
+        Ok(max_id::<u32>(&self.conn, "categories")?.map_or(1, |cat_id| cat_id + 1))
+    }
+
+    /// Add an image to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an image with the same id in the dataset.
+    pub fn add_img(&mut self, img: &Image) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if contains(&tx, "images", img.id)? {
+            return Err(MutationError::DuplicateImage(img.id).into());
+        }
+        insert_img(&tx, img)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the image entry with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    pub fn update_img(&mut self, img: &Image) -> Result<Image, SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let old_img = get_img(&tx, img.id)?.ok_or(MissingIdError::Image(img.id))?;
+        insert_img(&tx, img)?;
+        tx.commit()?;
+        Ok(old_img)
+    }
+
+    /// Remove the image and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    pub fn remove_img(&mut self, img_id: u64) -> Result<(Image, Vec<Annotation>), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let img = get_img(&tx, img_id)?.ok_or(MissingIdError::Image(img_id))?;
+        let anns = query_anns(
+            &tx,
+            "WHERE annotations.image_id = ?1 ORDER BY annotations.id",
+            [img_id],
+        )?;
+        tx.execute("DELETE FROM annotations WHERE image_id = ?1", [img_id])?;
+        tx.execute("DELETE FROM images WHERE id = ?1", [img_id])?;
+        tx.commit()?;
+        Ok((img, anns))
+    }
+
+    /// Add an annotation to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already an annotation with the same id in the dataset,
+    /// or if the image or category of the annotation is not in the dataset.
+    pub fn add_ann(&mut self, ann: &Annotation) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if contains(&tx, "annotations", ann.id)? {
+            return Err(MutationError::DuplicateAnnotation(ann.id).into());
+        }
+        check_ann(&tx, ann)?;
+        insert_ann(&tx, ann)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the annotation with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset,
+    /// or if the image or category of the new annotation is not in the dataset.
+    pub fn update_ann(&mut self, ann: &Annotation) -> Result<Annotation, SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let old_ann = query_anns(&tx, "WHERE annotations.id = ?1", [ann.id])?
+            .pop()
+            .ok_or(MutationError::MissingId(MissingIdError::Annotation(ann.id)))?;
+        check_ann(&tx, ann)?;
+        insert_ann(&tx, ann)?;
+        tx.commit()?;
+        Ok(old_ann)
+    }
+
+    /// Replace the annotations with the same ids in a single transaction: if one of them cannot be updated, none of them is.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset,
+    /// or if the image or category of one of the new annotations is not in the dataset.
+    pub fn update_anns(&mut self, anns: &[Annotation]) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for ann in anns {
+            if !contains(&tx, "annotations", ann.id)? {
+                return Err(MutationError::MissingId(MissingIdError::Annotation(ann.id)).into());
+            }
+            check_ann(&tx, ann)?;
+            insert_ann(&tx, ann)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the segmentations of the annotations with the given ids in a single transaction: if one of them cannot be
+    /// updated, none of them is.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the annotations is not in the dataset.
+    pub fn update_segmentations(
+        &mut self,
+        segmentations: Vec<(u64, Segmentation)>,
+    ) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for (ann_id, segmentation) in segmentations {
+            let mut ann = query_anns(&tx, "WHERE annotations.id = ?1", [ann_id])?
+                .pop()
+                .ok_or(MutationError::MissingId(MissingIdError::Annotation(ann_id)))?;
+            ann.segmentation = segmentation;
+            insert_ann(&tx, &ann)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Remove the annotation from the dataset and return it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset.
+    pub fn remove_ann(&mut self, ann_id: u64) -> Result<Annotation, SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let ann = query_anns(&tx, "WHERE annotations.id = ?1", [ann_id])?
+            .pop()
+            .ok_or(MissingIdError::Annotation(ann_id))?;
+        tx.execute("DELETE FROM annotations WHERE id = ?1", [ann_id])?;
+        tx.commit()?;
+        Ok(ann)
+    }
+
+    /// Add a category to the dataset.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is already a category with the same id in the dataset.
+    pub fn add_cat(&mut self, cat: &Category) -> Result<(), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if contains(&tx, "categories", u64::from(cat.id))? {
+            return Err(MutationError::DuplicateCategory(cat.id).into());
+        }
+        insert_cat(&tx, cat)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace the category with the same id and return the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    pub fn update_cat(&mut self, cat: &Category) -> Result<Category, SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let old_cat = get_cat(&tx, cat.id)?.ok_or(MissingIdError::Category(cat.id))?;
+        insert_cat(&tx, cat)?;
+        tx.commit()?;
+        Ok(old_cat)
+    }
+
+    /// Remove the category and all its annotations from the dataset, and return them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    pub fn remove_cat(&mut self, cat_id: u32) -> Result<(Category, Vec<Annotation>), SqliteError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let cat = get_cat(&tx, cat_id)?.ok_or(MissingIdError::Category(cat_id))?;
+        let anns = query_anns(
+            &tx,
+            "WHERE annotations.category_id = ?1 ORDER BY annotations.id",
+            [cat_id],
+        )?;
+        tx.execute("DELETE FROM annotations WHERE category_id = ?1", [cat_id])?;
+        tx.execute("DELETE FROM categories WHERE id = ?1", [cat_id])?;
+        tx.commit()?;
+        Ok((cat, anns))
+    }
+}
+
+impl DatasetView for SqliteDataset {
+    type Error = SqliteError;
+
+    fn image_folder(&self) -> &Path {
+        &self.image_folder
+    }
+
+    fn get_ann(&self, ann_id: u64) -> Result<Cow<'_, Annotation>, Self::Error> {
+        self.get_ann(ann_id).map(Cow::Owned)
+    }
+
+    fn get_anns(&self) -> Result<Vec<Cow<'_, Annotation>>, Self::Error> {
+        Ok(self.get_anns()?.into_iter().map(Cow::Owned).collect())
+    }
+
+    fn get_cat(&self, cat_id: u32) -> Result<Cow<'_, Category>, Self::Error> {
+        self.get_cat(cat_id).map(Cow::Owned)
+    }
+
+    fn get_cats(&self) -> Result<Vec<Cow<'_, Category>>, Self::Error> {
+        Ok(self.get_cats()?.into_iter().map(Cow::Owned).collect())
+    }
+
+    fn get_img(&self, img_id: u64) -> Result<Cow<'_, Image>, Self::Error> {
+        self.get_img(img_id).map(Cow::Owned)
+    }
+
+    fn get_imgs(&self) -> Result<Vec<Cow<'_, Image>>, Self::Error> {
+        Ok(self.get_imgs()?.into_iter().map(Cow::Owned).collect())
+    }
+
+    fn get_info(&self) -> Result<Cow<'_, Info>, Self::Error> {
+        self.get_info().map(Cow::Owned)
+    }
+
+    fn get_licenses(&self) -> Result<Cow<'_, [License]>, Self::Error> {
+        self.get_licenses().map(Cow::Owned)
+    }
+
+    fn get_extra_fields(&self) -> Result<Cow<'_, ExtraFields>, Self::Error> {
+        self.get_extra_fields().map(Cow::Owned)
+    }
+
+    fn get_img_anns(&self, img_id: u64) -> Result<Vec<Cow<'_, Annotation>>, Self::Error> {
+        Ok(self
+            .get_img_anns(img_id)?
+            .into_iter()
+            .map(Cow::Owned)
+            .collect())
+    }
+
+    fn get_ann_ids(
+        &self,
+        img_ids: &[u64],
+        cat_ids: &[u32],
+        area_range: Option<(f64, f64)>,
+        iscrowd: Option<bool>,
+    ) -> Result<Vec<u64>, Self::Error> {
+        self.get_ann_ids(img_ids, cat_ids, area_range, iscrowd)
+    }
+
+    fn get_cat_ids(
+        &self,
+        cat_names: &[&str],
+        sup_names: &[&str],
+        cat_ids: &[u32],
+    ) -> Result<Vec<u32>, Self::Error> {
+        self.get_cat_ids(cat_names, sup_names, cat_ids)
+    }
+
+    fn get_img_ids(&self, img_ids: &[u64], cat_ids: &[u32]) -> Result<Vec<u64>, Self::Error> {
+        self.get_img_ids(img_ids, cat_ids)
+    }
+
+    fn next_img_id(&self) -> Result<u64, Self::Error> {
+        self.next_img_id()
+    }
+
+    fn next_ann_id(&self) -> Result<u64, Self::Error> {
+        self.next_ann_id()
+    }
+
+    fn next_cat_id(&self) -> Result<u32, Self::Error> {
+        self.next_cat_id()
+    }
+}
+
+impl DatasetMut for SqliteDataset {
+    fn add_img(&mut self, img: Image) -> Result<(), Self::Error> {
+        self.add_img(&img)
+    }
+
+    fn update_img(&mut self, img: Image) -> Result<Image, Self::Error> {
+        self.update_img(&img)
+    }
+
+    fn remove_img(&mut self, img_id: u64) -> Result<(Image, Vec<Annotation>), Self::Error> {
+        self.remove_img(img_id)
+    }
+
+    fn add_ann(&mut self, ann: Annotation) -> Result<(), Self::Error> {
+        self.add_ann(&ann)
+    }
+
+    fn update_ann(&mut self, ann: Annotation) -> Result<Annotation, Self::Error> {
+        self.update_ann(&ann)
+    }
+
+    fn update_anns(&mut self, anns: Vec<Annotation>) -> Result<(), Self::Error> {
+        self.update_anns(&anns)
+    }
+
+    fn update_segmentations(
+        &mut self,
+        segmentations: Vec<(u64, Segmentation)>,
+    ) -> Result<(), Self::Error> {
+        self.update_segmentations(segmentations)
+    }
+
+    fn remove_ann(&mut self, ann_id: u64) -> Result<Annotation, Self::Error> {
+        self.remove_ann(ann_id)
+    }
+
+    fn add_cat(&mut self, cat: Category) -> Result<(), Self::Error> {
+        self.add_cat(&cat)
+    }
+
+    fn update_cat(&mut self, cat: Category) -> Result<Category, Self::Error> {
+        self.update_cat(&cat)
+    }
+
+    fn remove_cat(&mut self, cat_id: u32) -> Result<(Category, Vec<Annotation>), Self::Error> {
+        self.remove_cat(cat_id)
+    }
+}
+
+fn contains(conn: &Connection, table: &str, id: u64) -> Result<bool, SqliteError> {
+    Ok(conn
+        .prepare_cached(&format!("SELECT 1 FROM {table} WHERE id = ?1"))?
+        .query_row([id], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+fn get_img(conn: &Connection, img_id: u64) -> Result<Option<Image>, SqliteError> {
+    query_all(conn, "SELECT data FROM images WHERE id = ?1", [img_id]).map(|mut imgs| imgs.pop())
+}
+
+fn get_cat(conn: &Connection, cat_id: u32) -> Result<Option<Category>, SqliteError> {
+    query_all(conn, "SELECT data FROM categories WHERE id = ?1", [cat_id])
+        .map(|mut cats| cats.pop())
+}
+
+/// Check that the image and category of the annotation exist.
+fn check_ann(conn: &Connection, ann: &Annotation) -> Result<(), SqliteError> {
+    if !contains(conn, "categories", u64::from(ann.category_id))? {
+        return Err(MutationError::from(MissingIdError::Category(ann.category_id)).into());
+    }
+    if !contains(conn, "images", ann.image_id)? {
+        return Err(MutationError::from(MissingIdError::Image(ann.image_id)).into());
+    }
+    Ok(())
+}
+
+fn insert_img(conn: &Connection, img: &Image) -> Result<(), SqliteError> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO images (id, width, height, data) VALUES (?1, ?2, ?3, ?4)",
+    )?
+    .execute(params![
+        img.id,
+        img.width,
+        img.height,
+        serde_json::to_string(img)?
+    ])?;
+    Ok(())
+}
+
+fn insert_cat(conn: &Connection, cat: &Category) -> Result<(), SqliteError> {
+    conn.prepare_cached("INSERT OR REPLACE INTO categories (id, data) VALUES (?1, ?2)")?
+        .execute(params![cat.id, serde_json::to_string(cat)?])?;
+    Ok(())
+}
+
+/// The polygons are stored in the COCO format, their size is added back from the image when reading them.
+fn insert_ann(conn: &Connection, ann: &Annotation) -> Result<(), SqliteError> {
+    let data = serde_json::to_string(&AnnotationRef(ann, SaveOption::default()))?;
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO annotations (id, image_id, category_id, area, iscrowd, data)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?
+    .execute(params![
+        ann.id,
+        ann.image_id,
+        ann.category_id,
+        ann.area,
+        ann.iscrowd,
+        data
+    ])?;
+    Ok(())
+}
+
+/// Deserializes the json of each row returned by the query.
+fn query_all<T: DeserializeOwned, P: Params>(
+    conn: &Connection,
+    query: &str,
+    params: P,
+) -> Result<Vec<T>, SqliteError> {
+    let mut stmt = conn.prepare(query)?;
+    let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+    rows.map(|data| Ok(serde_json::from_str(&data?)?)).collect()
+}
+
+fn query_anns<P: Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> Result<Vec<Annotation>, SqliteError> {
+    let mut stmt = conn.prepare(&format!("{ANNS_QUERY} {condition}"))?;
+    let rows = stmt.query_map(params, |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, u32>(1)?,
+            row.get::<_, u32>(2)?,
+        ))
+    })?;
+    rows.map(|row| {
+        let (data, width, height) = row?;
+        let mut ann: Annotation = serde_json::from_str(&data)?;
+        if let Segmentation::Polygons(counts) = ann.segmentation {
+            ann.segmentation = Segmentation::PolygonsRS(PolygonsRS {
+                size: vec![height, width],
+                counts,
+            });
+        }
+        Ok(ann)
+    })
+    .collect()
+}
+
+fn query_ids(conn: &Connection, query: &str) -> Result<Vec<u64>, SqliteError> {
+    let mut stmt = conn.prepare(query)?;
+    let ids = stmt.query_map([], |row| row.get(0))?;
+    Ok(ids.collect::<Result<_, _>>()?)
+}
+
+fn max_id<T: rusqlite::types::FromSql>(
+    conn: &Connection,
+    table: &str,
+) -> Result<Option<T>, SqliteError> {
+    Ok(
+        conn.query_row(&format!("SELECT MAX(id) FROM {table}"), [], |row| {
+            row.get(0)
+        })?,
+    )
+}
+
+fn get_metadata<T: DeserializeOwned + Default>(
+    conn: &Connection,
+    key: &str,
+) -> Result<T, SqliteError> {
+    Ok(
+        query_all(conn, "SELECT data FROM metadata WHERE key = ?1", [key])?
+            .pop()
+            .unwrap_or_default(),
+    )
+}
+
+fn set_metadata<T: Serialize + ?Sized>(
+    conn: &Connection,
+    key: &str,
+    value: &T,
+) -> Result<(), SqliteError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (key, data) VALUES (?1, ?2)",
+        params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+/// The ids are numbers, they can be put directly in the query.
+fn join_ids<T: ToString>(ids: &[T]) -> String {
+    ids.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::Bbox;
+    use crate::test_utils::test_dir;
+    use rstest::rstest;
+    use std::fs;
+
+    const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+    const IMAGE_FOLDER: &str = "../data_samples/coco_25k/images";
+
+    fn datasets() -> (BTreemapDataset, SqliteDataset) {
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, IMAGE_FOLDER).unwrap();
+        let mut sqlite_dataset = SqliteDataset::open_in_memory(IMAGE_FOLDER).unwrap();
+        sqlite_dataset.import(&dataset).unwrap();
+        (dataset, sqlite_dataset)
+    }
+
+    fn ann(id: u64, image_id: u64, category_id: u32) -> Annotation {
+        Annotation {
+            id,
+            image_id,
+            category_id,
+            segmentation: Segmentation::Polygons(vec![vec![0.0, 0.0, 0.0, 4.0, 4.0, 4.0]]),
+            area: 8.0,
+            bbox: Bbox {
+                left: 0.0,
+                top: 0.0,
+                width: 4.0,
+                height: 4.0,
+            },
+            iscrowd: 0,
+            extra: ExtraFields::default(),
+        }
+    }
+
+    #[test]
+    fn to_dataset_roundtrip() {
+        let (dataset, sqlite_dataset) = datasets();
+        assert_eq!(sqlite_dataset.to_dataset().unwrap(), dataset);
+    }
+
+    #[test]
+    fn getters() {
+        let (dataset, sqlite_dataset) = datasets();
+        assert_eq!(
+            sqlite_dataset.get_anns().unwrap(),
+            dataset.get_anns().into_iter().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            sqlite_dataset.get_img(17627).unwrap(),
+            *dataset.get_img(17627).unwrap()
+        );
+        assert_eq!(
+            sqlite_dataset.get_cat(1).unwrap(),
+            *dataset.get_cat(1).unwrap()
+        );
+        let mut img_anns: Vec<Annotation> = dataset
+            .get_img_anns(17627)
+            .unwrap()
+            .into_iter()
+            .cloned()
+            .collect();
+        img_anns.sort_unstable_by_key(|ann| ann.id);
+        assert_eq!(sqlite_dataset.get_img_anns(17627).unwrap(), img_anns);
+        assert!(matches!(
+            sqlite_dataset.get_img_anns(0),
+            Err(SqliteError::MissingId(MissingIdError::Image(0)))
+        ));
+        assert!(matches!(
+            sqlite_dataset.get_ann(0),
+            Err(SqliteError::MissingId(MissingIdError::Annotation(0)))
+        ));
+        assert_eq!(sqlite_dataset.next_ann_id().unwrap(), dataset.next_ann_id());
+        assert_eq!(sqlite_dataset.next_img_id().unwrap(), dataset.next_img_id());
+        assert_eq!(sqlite_dataset.next_cat_id().unwrap(), dataset.next_cat_id());
+    }
+
+    #[rstest]
+    #[case::all(&[], &[], None, None)]
+    #[case::images(&[17627, 289343], &[], None, None)]
+    #[case::categories(&[], &[1, 18], None, None)]
+    #[case::images_and_categories(&[17627, 289343], &[1], None, None)]
+    #[case::area(&[], &[], Some((100.0, 5000.0)), None)]
+    #[case::iscrowd(&[], &[1], None, Some(true))]
+    #[case::not_iscrowd(&[17627], &[], Some((0.0, 1e10)), Some(false))]
+    fn get_ann_ids(
+        #[case] img_ids: &[u64],
+        #[case] cat_ids: &[u32],
+        #[case] area_range: Option<(f64, f64)>,
+        #[case] iscrowd: Option<bool>,
+    ) {
+        let (dataset, sqlite_dataset) = datasets();
+        assert_eq!(
+            sqlite_dataset
+                .get_ann_ids(img_ids, cat_ids, area_range, iscrowd)
+                .unwrap(),
+            dataset.get_ann_ids(img_ids, cat_ids, area_range, iscrowd)
+        );
+    }
+
+    #[rstest]
+    #[case::all(&[], &[])]
+    #[case::unchecked_images(&[17627, 0, 17627], &[])]
+    #[case::categories(&[], &[1, 18])]
+    #[case::images_and_categories(&[17627, 289343], &[1])]
+    #[case::unknown_category(&[], &[1000])]
+    fn get_img_ids(#[case] img_ids: &[u64], #[case] cat_ids: &[u32]) {
+        let (dataset, sqlite_dataset) = datasets();
+        assert_eq!(
+            sqlite_dataset.get_img_ids(img_ids, cat_ids).unwrap(),
+            dataset.get_img_ids(img_ids, cat_ids)
+        );
+    }
+
+    #[test]
+    fn get_cat_ids() {
+        let (dataset, sqlite_dataset) = datasets();
+        assert_eq!(
+            sqlite_dataset
+                .get_cat_ids(&["person", "dog"], &[], &[])
+                .unwrap(),
+            dataset.get_cat_ids(&["person", "dog"], &[], &[])
+        );
+        assert_eq!(
+            sqlite_dataset.get_cat_ids(&[], &["animal"], &[]).unwrap(),
+            dataset.get_cat_ids(&[], &["animal"], &[])
+        );
+    }
+
+    #[test]
+    fn mutations() {
+        let (mut dataset, mut sqlite_dataset) = datasets();
+        let img_id = 17627;
+        let ann_id = sqlite_dataset.next_ann_id().unwrap();
+
+        sqlite_dataset.add_ann(&ann(ann_id, img_id, 1)).unwrap();
+        dataset.add_ann(ann(ann_id, img_id, 1)).unwrap();
+        assert_eq!(
+            sqlite_dataset.get_ann(ann_id).unwrap(),
+            *dataset.get_ann(ann_id).unwrap()
+        );
+        assert!(matches!(
+            sqlite_dataset.add_ann(&ann(ann_id, img_id, 1)),
+            Err(SqliteError::Mutation(MutationError::DuplicateAnnotation(_)))
+        ));
+        assert!(matches!(
+            sqlite_dataset.add_ann(&ann(ann_id + 1, 0, 1)),
+            Err(SqliteError::Mutation(MutationError::MissingId(
+                MissingIdError::Image(0)
+            )))
+        ));
+
+        let old_ann = sqlite_dataset.update_ann(&ann(ann_id, img_id, 18)).unwrap();
+        assert_eq!(old_ann.category_id, 1);
+        assert_eq!(sqlite_dataset.get_ann(ann_id).unwrap().category_id, 18);
+        assert_eq!(sqlite_dataset.remove_ann(ann_id).unwrap().category_id, 18);
+        dataset.remove_ann(ann_id).unwrap();
+        assert!(sqlite_dataset.get_ann(ann_id).is_err());
+
+        let mut cat = sqlite_dataset.get_cat(1).unwrap();
+        cat.name = "human".to_string();
+        sqlite_dataset.update_cat(&cat).unwrap();
+        assert_eq!(
+            sqlite_dataset.get_cat_ids(&["human"], &[], &[]).unwrap(),
+            vec![1]
+        );
+
+        let (img, anns) = sqlite_dataset.remove_img(img_id).unwrap();
+        assert_eq!(img.id, img_id);
+        assert_eq!(
+            anns.iter().map(|ann| ann.id).collect::<Vec<_>>(),
+            dataset.get_ann_ids(&[img_id], &[], None, None)
+        );
+        dataset.remove_img(img_id).unwrap();
+        assert!(sqlite_dataset
+            .get_ann_ids(&[img_id], &[], None, None)
+            .unwrap()
+            .is_empty());
+        sqlite_dataset.add_img(&img).unwrap();
+        assert!(matches!(
+            sqlite_dataset.add_img(&img),
+            Err(SqliteError::Mutation(MutationError::DuplicateImage(_)))
+        ));
+
+        let (_, anns) = sqlite_dataset.remove_cat(1).unwrap();
+        assert_eq!(
+            anns.iter().map(|ann| ann.id).collect::<Vec<_>>(),
+            dataset.get_ann_ids(&[], &[1], None, None)
+        );
+        assert!(matches!(
+            sqlite_dataset.remove_cat(1),
+            Err(SqliteError::MissingId(MissingIdError::Category(1)))
+        ));
+    }
+
+    #[test]
+    fn concurrent_connections() {
+        let dir = test_dir("sqlite-concurrent");
+        let db_path = dir.join("dataset.db");
+        let mut writer = SqliteDataset::open(&db_path, &PathBuf::from(IMAGE_FOLDER)).unwrap();
+        writer.import_json(ANNOTATIONS_PATH).unwrap();
+        let reader = SqliteDataset::open(&db_path, &PathBuf::from(IMAGE_FOLDER)).unwrap();
+
+        let ann_id = reader.next_ann_id().unwrap();
+        writer.add_ann(&ann(ann_id, 17627, 1)).unwrap();
+        assert_eq!(reader.get_ann(ann_id).unwrap().image_id, 17627);
+
+        let handle = std::thread::spawn(move || {
+            let mut other_writer =
+                SqliteDataset::open(&db_path, &PathBuf::from(IMAGE_FOLDER)).unwrap();
+            other_writer.remove_ann(ann_id).unwrap();
+        });
+        handle.join().unwrap();
+        assert!(reader.get_ann(ann_id).is_err());
+        assert!(writer.import_json(ANNOTATIONS_PATH).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
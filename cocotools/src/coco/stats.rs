@@ -0,0 +1,298 @@
+//! Module containing the statistics of a dataset.
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::coco::object_detection::{Annotation, BTreemapDataset};
+
+/// Bounding boxes with an area below this value are "small" in the COCO evaluation.
+pub(crate) const SMALL_AREA: f64 = 32.0 * 32.0;
+/// Bounding boxes with an area below this value (and above [`SMALL_AREA`]) are "medium" in the COCO evaluation.
+pub(crate) const MEDIUM_AREA: f64 = 96.0 * 96.0;
+/// Edges of the bins of the aspect ratio (width / height) histogram.
+const ASPECT_RATIO_EDGES: [f64; 9] = [
+    1.0 / 4.0,
+    1.0 / 3.0,
+    1.0 / 2.0,
+    2.0 / 3.0,
+    1.0,
+    3.0 / 2.0,
+    2.0,
+    3.0,
+    4.0,
+];
+
+/// Statistics of a dataset.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DatasetStats {
+    pub nb_images: usize,
+    pub nb_annotations: usize,
+    pub nb_categories: usize,
+    /// Ratio of the annotations with `iscrowd=1`.
+    pub crowd_ratio: f64,
+    pub sizes: SizeDistribution,
+    pub aspect_ratios: Vec<HistogramBin>,
+    /// Number of images for each number of annotations per image.
+    pub anns_per_image: BTreeMap<usize, usize>,
+    /// Ids of the images without any annotation.
+    pub images_without_anns: Vec<u64>,
+    /// Image resolutions, from the most common to the least common.
+    pub resolutions: Vec<ResolutionCount>,
+    pub categories: Vec<CategoryStats>,
+}
+
+/// Number of bounding boxes in each of the COCO size buckets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SizeDistribution {
+    /// Area below 32².
+    pub small: usize,
+    /// Area between 32² and 96².
+    pub medium: usize,
+    /// Area above 96².
+    pub large: usize,
+}
+
+/// Bin of an histogram, with the values in `[min, max)`. A `max` of `None` means there is no upper bound.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HistogramBin {
+    pub min: f64,
+    pub max: Option<f64>,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ResolutionCount {
+    pub width: u32,
+    pub height: u32,
+    pub nb_images: usize,
+}
+
+/// Statistics of the annotations of a category.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CategoryStats {
+    pub id: u32,
+    pub name: String,
+    /// Number of images with at least one annotation of this category.
+    pub nb_images: usize,
+    pub nb_instances: usize,
+    pub crowd_ratio: f64,
+    pub sizes: SizeDistribution,
+}
+
+impl From<&BTreemapDataset> for DatasetStats {
+    fn from(dataset: &BTreemapDataset) -> Self {
+        let anns = dataset.get_anns();
+
+        let mut anns_per_image = BTreeMap::new();
+        let mut images_without_anns = Vec::new();
+        let mut resolutions: BTreeMap<(u32, u32), usize> = BTreeMap::new();
+        for img in dataset.get_imgs() {
+            let nb_anns = dataset.img_to_anns.get(&img.id).map_or(0, HashSet::len);
+            *anns_per_image.entry(nb_anns).or_insert(0) += 1;
+            if nb_anns == 0 {
+                images_without_anns.push(img.id);
+            }
+            *resolutions.entry((img.width, img.height)).or_insert(0) += 1;
+        }
+        let mut resolutions: Vec<ResolutionCount> = resolutions
+            .into_iter()
+            .map(|((width, height), nb_images)| ResolutionCount {
+                width,
+                height,
+                nb_images,
+            })
+            .collect();
+        resolutions.sort_by_key(|resolution| std::cmp::Reverse(resolution.nb_images));
+
+        let categories = dataset
+            .get_cats()
+            .into_iter()
+            .map(|cat| {
+                let cat_anns: Vec<&Annotation> = dataset
+                    .cat_to_anns
+                    .get(&cat.id)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|ann_id| dataset.anns.get(ann_id))
+                    .collect();
+                CategoryStats {
+                    id: cat.id,
+                    name: cat.name.clone(),
+                    nb_images: dataset.cat_to_imgs.get(&cat.id).map_or(0, HashSet::len),
+                    nb_instances: cat_anns.len(),
+                    crowd_ratio: crowd_ratio(&cat_anns),
+                    sizes: size_distribution(&cat_anns),
+                }
+            })
+            .collect();
+
+        Self {
+            nb_images: dataset.get_imgs().len(),
+            nb_annotations: anns.len(),
+            nb_categories: dataset.get_cats().len(),
+            crowd_ratio: crowd_ratio(&anns),
+            sizes: size_distribution(&anns),
+            aspect_ratios: aspect_ratio_histogram(&anns),
+            anns_per_image,
+            images_without_anns,
+            resolutions,
+            categories,
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn crowd_ratio(anns: &[&Annotation]) -> f64 {
+    if anns.is_empty() {
+        return 0.0;
+    }
+    anns.iter().filter(|ann| ann.iscrowd != 0).count() as f64 / anns.len() as f64
+}
+
+fn size_distribution(anns: &[&Annotation]) -> SizeDistribution {
+    let mut sizes = SizeDistribution::default();
+    for ann in anns {
+        let area = ann.bbox.width * ann.bbox.height;
+        if area < SMALL_AREA {
+            sizes.small += 1;
+        } else if area < MEDIUM_AREA {
+            sizes.medium += 1;
+        } else {
+            sizes.large += 1;
+        }
+    }
+    sizes
+}
+
+/// Histogram of the aspect ratios (width / height) of the bounding boxes. Boxes with a null height are ignored.
+fn aspect_ratio_histogram(anns: &[&Annotation]) -> Vec<HistogramBin> {
+    let mut bins: Vec<HistogramBin> = std::iter::once(0.0)
+        .chain(ASPECT_RATIO_EDGES)
+        .zip(ASPECT_RATIO_EDGES.into_iter().map(Some).chain([None]))
+        .map(|(min, max)| HistogramBin { min, max, count: 0 })
+        .collect();
+    for ann in anns.iter().filter(|ann| ann.bbox.height > 0.0) {
+        let aspect_ratio = ann.bbox.width / ann.bbox.height;
+        let bin_idx = ASPECT_RATIO_EDGES.partition_point(|edge| *edge <= aspect_ratio);
+        bins[bin_idx].count += 1;
+    }
+    bins
+}
+
+impl fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} images, {} annotations, {} categories, crowd ratio: {:.3}",
+            self.nb_images, self.nb_annotations, self.nb_categories, self.crowd_ratio
+        )?;
+        writeln!(
+            f,
+            "Bounding box sizes: {} small, {} medium, {} large",
+            self.sizes.small, self.sizes.medium, self.sizes.large
+        )?;
+        writeln!(
+            f,
+            "Images without annotations: {}",
+            self.images_without_anns.len()
+        )?;
+
+        writeln!(f, "\n{:<20} {:>10}", "Aspect ratio (w/h)", "Boxes")?;
+        for bin in &self.aspect_ratios {
+            let range = match bin.max {
+                Some(max) => format!("[{:.2}, {:.2})", bin.min, max),
+                None => format!("[{:.2}, inf)", bin.min),
+            };
+            writeln!(f, "{range:<20} {:>10}", bin.count)?;
+        }
+
+        writeln!(f, "\n{:<20} {:>10}", "Annotations/image", "Images")?;
+        for (nb_anns, nb_images) in &self.anns_per_image {
+            writeln!(f, "{nb_anns:<20} {nb_images:>10}")?;
+        }
+
+        writeln!(f, "\n{:<20} {:>10}", "Resolution", "Images")?;
+        for resolution in &self.resolutions {
+            let size = format!("{}x{}", resolution.width, resolution.height);
+            writeln!(f, "{size:<20} {:>10}", resolution.nb_images)?;
+        }
+
+        writeln!(
+            f,
+            "\n{:>6} {:<20} {:>8} {:>10} {:>7} {:>8} {:>8} {:>8}",
+            "Id", "Category", "Images", "Instances", "Crowd", "Small", "Medium", "Large"
+        )?;
+        for cat in &self.categories {
+            writeln!(
+                f,
+                "{:>6} {:<20} {:>8} {:>10} {:>7.3} {:>8} {:>8} {:>8}",
+                cat.id,
+                cat.name,
+                cat.nb_images,
+                cat.nb_instances,
+                cat.crowd_ratio,
+                cat.sizes.small,
+                cat.sizes.medium,
+                cat.sizes.large
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_utils::DatasetBuilder;
+
+    #[test]
+    fn stats_of_small_dataset() {
+        let dataset = DatasetBuilder::new()
+            .img(1, 640, 480)
+            .img(2, 640, 480)
+            .img(3, 100, 100)
+            .ann(1, 1, 1, 10.0, 10.0)
+            .ann(2, 1, 1, 50.0, 100.0)
+            .crowd_ann(3, 1, 2, 200.0, 100.0)
+            .ann(4, 2, 1, 30.0, 30.0)
+            .cat(1, "person", None)
+            .cat(2, "car", None)
+            .cat(3, "dog", None)
+            .build();
+
+        let stats = DatasetStats::from(&dataset);
+        assert_eq!(stats.nb_images, 3);
+        assert_eq!(stats.nb_annotations, 4);
+        assert!((stats.crowd_ratio - 0.25).abs() < f64::EPSILON);
+        assert_eq!(
+            stats.sizes,
+            SizeDistribution {
+                small: 2,
+                medium: 1,
+                large: 1
+            }
+        );
+        let aspect_ratio_counts: Vec<usize> =
+            stats.aspect_ratios.iter().map(|bin| bin.count).collect();
+        assert_eq!(aspect_ratio_counts, vec![0, 0, 0, 1, 0, 2, 0, 1, 0, 0]);
+        assert_eq!(
+            stats.anns_per_image,
+            BTreeMap::from([(0, 1), (1, 1), (3, 1)])
+        );
+        assert_eq!(stats.images_without_anns, vec![3]);
+        assert_eq!(
+            stats.resolutions[0],
+            ResolutionCount {
+                width: 640,
+                height: 480,
+                nb_images: 2
+            }
+        );
+        let person = &stats.categories[0];
+        assert_eq!((person.nb_images, person.nb_instances), (2, 3));
+        let dog = &stats.categories[2];
+        assert_eq!((dog.nb_images, dog.nb_instances), (0, 0));
+    }
+}
@@ -0,0 +1,591 @@
+//! Module containing a streaming reader and writer for COCO json annotation files.
+//!
+//! The reader deserializes the images, annotations and categories one by one straight into a [`BTreemapDataset`],
+//! without first loading the whole file content or an intermediate [`Dataset`](crate::coco::object_detection::Dataset) in memory.
+//! Similarly, the writer serializes the dataset element by element.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::coco::compression;
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Category, ExtraFields, Image, Info, License, PolygonsRS,
+    Segmentation,
+};
+use crate::errors::{LoadingError, MissingIdError};
+
+/// Loads a COCO dataset from the annotation file and the image folder, reading the file as a stream.
+///
+/// The annotation file can be compressed, see [`compression`](crate::coco::compression).
+///
+/// # Errors
+///
+/// Will return `Err` if the json file does not exist/cannot be read or if an error happens when deserializing and parsing it.
+/// Will return `Err` if there is an annotation with an image id X, but no image entry has this id.
+pub fn load<P: AsRef<Path>>(
+    annotations_path: P,
+    image_folder: P,
+) -> Result<BTreemapDataset, LoadingError> {
+    let annotations_path = annotations_path.as_ref().to_path_buf();
+    let reader = compression::open(&annotations_path)?;
+    read(reader, image_folder, annotations_path)
+}
+
+/// Reads a COCO dataset from a json stream, `annotations_path` is only used in the errors.
+pub(crate) fn read<R: io::Read, P: AsRef<Path>>(
+    reader: R,
+    image_folder: P,
+    annotations_path: PathBuf,
+) -> Result<BTreemapDataset, LoadingError> {
+    let mut dataset = BTreemapDataset {
+        anns: BTreeMap::new(),
+        cats: BTreeMap::new(),
+        imgs: BTreeMap::new(),
+        img_to_anns: BTreeMap::new(),
+        cat_to_anns: BTreeMap::new(),
+        cat_to_imgs: BTreeMap::new(),
+        info: Info::default(),
+        licenses: Vec::new(),
+        extra: ExtraFields::default(),
+        image_folder: image_folder.as_ref().to_path_buf(),
+    };
+    let mut pending_anns = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer
+        .deserialize_map(DatasetVisitor {
+            dataset: &mut dataset,
+            pending_anns: &mut pending_anns,
+        })
+        .and_then(|()| deserializer.end())
+        .map_err(|err| LoadingError::Deserialize(err, annotations_path))?;
+
+    // Annotations with polygons that came before their image in the file.
+    for mut ann in pending_anns {
+        if !add_polygons_size(&mut ann, &dataset) {
+            return Err(LoadingError::Parsing(MissingIdError::Image(ann.image_id)));
+        }
+        dataset.insert_ann(ann);
+    }
+    Ok(dataset)
+}
+
+/// Options of the json output, see [`write`].
+///
+/// The images, annotations and categories are always written ordered by id, and the unknown fields ordered by key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaveOption {
+    /// Pretty print the json, otherwise it is written without any whitespace.
+    pub pretty: bool,
+    /// Number of spaces per indentation level when pretty printing.
+    pub indent: usize,
+    /// Number of decimals the polygons and bounding boxes coordinates are rounded to (like pycocotools' results), not rounded if `None`.
+    pub float_precision: Option<u32>,
+    /// Also sort the licenses by id and the category ids lists of the images (LVIS' `neg_category_ids` and
+    /// `not_exhaustive_category_ids`), so that the output only depends on the content of the dataset.
+    pub canonical: bool,
+}
+
+impl SaveOption {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    #[must_use]
+    pub const fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    #[must_use]
+    pub const fn float_precision(mut self, float_precision: Option<u32>) -> Self {
+        self.float_precision = float_precision;
+        self
+    }
+
+    #[must_use]
+    pub const fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+}
+
+impl Default for SaveOption {
+    fn default() -> Self {
+        Self {
+            pretty: true,
+            indent: 2,
+            float_precision: None,
+            canonical: false,
+        }
+    }
+}
+
+/// Writes the dataset as json, one element at a time.
+///
+/// # Errors
+///
+/// Will return `Err` if the dataset cannot be serialized or written.
+pub fn write<W: io::Write>(
+    dataset: &BTreemapDataset,
+    writer: W,
+    option: SaveOption,
+) -> serde_json::Result<()> {
+    if option.pretty {
+        let indent = vec![b' '; option.indent];
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+        let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+        DatasetRef(dataset, option).serialize(&mut serializer)
+    } else {
+        let mut serializer = serde_json::Serializer::new(writer);
+        DatasetRef(dataset, option).serialize(&mut serializer)
+    }
+}
+
+/// The polygon format from COCO does not contain the size of the image, it is transformed into a more complete format.
+///
+/// Returns `false` if the polygons need the size of an image that is not in the dataset (yet).
+fn add_polygons_size(ann: &mut Annotation, dataset: &BTreemapDataset) -> bool {
+    if let Segmentation::Polygons(counts) = &mut ann.segmentation {
+        let img = match dataset.imgs.get(&ann.image_id) {
+            Some(img) => img,
+            None => return false,
+        };
+        ann.segmentation = Segmentation::PolygonsRS(PolygonsRS {
+            size: vec![img.height, img.width],
+            counts: std::mem::take(counts),
+        });
+    }
+    true
+}
+
+struct DatasetVisitor<'a> {
+    dataset: &'a mut BTreemapDataset,
+    pending_anns: &'a mut Vec<Annotation>,
+}
+
+impl<'de, 'a> Visitor<'de> for DatasetVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a COCO dataset")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let dataset = self.dataset;
+        let pending_anns = self.pending_anns;
+        let (mut has_images, mut has_annotations, mut has_categories) = (false, false, false);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "images" => {
+                    has_images = true;
+                    map.next_value_seed(ForEach::new(|img: Image| {
+                        dataset.img_to_anns.entry(img.id).or_default();
+                        dataset.imgs.insert(img.id, img);
+                    }))?;
+                }
+                "annotations" => {
+                    has_annotations = true;
+                    map.next_value_seed(ForEach::new(|mut ann: Annotation| {
+                        if add_polygons_size(&mut ann, dataset) {
+                            dataset.insert_ann(ann);
+                        } else {
+                            pending_anns.push(ann);
+                        }
+                    }))?;
+                }
+                "categories" => {
+                    has_categories = true;
+                    map.next_value_seed(ForEach::new(|cat: Category| {
+                        dataset.cat_to_anns.entry(cat.id).or_default();
+                        dataset.cat_to_imgs.entry(cat.id).or_default();
+                        dataset.cats.insert(cat.id, cat);
+                    }))?;
+                }
+                "info" => dataset.info = map.next_value()?,
+                "licenses" => dataset.licenses = map.next_value()?,
+                _ => {
+                    let value = map.next_value()?;
+                    dataset.extra.0.insert(key, value);
+                }
+            }
+        }
+
+        if !has_images {
+            return Err(de::Error::missing_field("images"));
+        }
+        if !has_annotations {
+            return Err(de::Error::missing_field("annotations"));
+        }
+        if !has_categories {
+            return Err(de::Error::missing_field("categories"));
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes a sequence by giving each element to `f` as soon as it is deserialized.
+struct ForEach<T, F> {
+    f: F,
+    element: PhantomData<T>,
+}
+
+impl<T, F> ForEach<T, F> {
+    const fn new(f: F) -> Self {
+        Self {
+            f,
+            element: PhantomData,
+        }
+    }
+}
+
+impl<'de, T, F> DeserializeSeed<'de> for ForEach<T, F>
+where
+    T: de::Deserialize<'de>,
+    F: FnMut(T),
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T, F> Visitor<'de> for ForEach<T, F>
+where
+    T: de::Deserialize<'de>,
+    F: FnMut(T),
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(element) = seq.next_element()? {
+            (self.f)(element);
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the dataset with the same layout as [`Dataset`](crate::coco::object_detection::Dataset), without building one.
+struct DatasetRef<'a>(&'a BTreemapDataset, SaveOption);
+
+impl<'a> Serialize for DatasetRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Self(dataset, option) = *self;
+        let mut map = serializer.serialize_map(None)?;
+        if dataset.info != Info::default() {
+            map.serialize_entry("info", &dataset.info)?;
+        }
+        map.serialize_entry(
+            "images",
+            &Seq(|| dataset.imgs.values().map(|img| ImageRef(img, option))),
+        )?;
+        map.serialize_entry(
+            "annotations",
+            &Seq(|| dataset.anns.values().map(|ann| AnnotationRef(ann, option))),
+        )?;
+        map.serialize_entry("categories", &Seq(|| dataset.cats.values()))?;
+        if !dataset.licenses.is_empty() {
+            if option.canonical {
+                let mut licenses: Vec<&License> = dataset.licenses.iter().collect();
+                licenses.sort_by_key(|license| license.id);
+                map.serialize_entry("licenses", &licenses)?;
+            } else {
+                map.serialize_entry("licenses", &dataset.licenses)?;
+            }
+        }
+        let ExtraFields(extra) = &dataset.extra;
+        for (key, value) in extra {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Serializes the elements of the iterator as a sequence.
+pub(crate) struct Seq<F>(pub(crate) F);
+
+impl<F, I> Serialize for Seq<F>
+where
+    F: Fn() -> I,
+    I: Iterator,
+    I::Item: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq((self.0)())
+    }
+}
+
+struct ImageRef<'a>(&'a Image, SaveOption);
+
+impl<'a> Serialize for ImageRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Self(img, option) = *self;
+        if option.canonical
+            && (img.neg_category_ids.is_some() || img.not_exhaustive_category_ids.is_some())
+        {
+            let mut img = img.clone();
+            for cat_ids in [
+                &mut img.neg_category_ids,
+                &mut img.not_exhaustive_category_ids,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                cat_ids.sort_unstable();
+            }
+            img.serialize(serializer)
+        } else {
+            img.serialize(serializer)
+        }
+    }
+}
+
+pub(crate) struct AnnotationRef<'a>(pub(crate) &'a Annotation, pub(crate) SaveOption);
+
+impl<'a> Serialize for AnnotationRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Self(ann, option) = *self;
+        let is_polygons_rs = matches!(ann.segmentation, Segmentation::PolygonsRS(_));
+        if !is_polygons_rs && option.float_precision.is_none() {
+            return ann.serialize(serializer);
+        }
+
+        let mut ann = ann.clone();
+        // The size of the polygons is not part of the COCO format.
+        if let Segmentation::PolygonsRS(poly) = &mut ann.segmentation {
+            ann.segmentation = Segmentation::Polygons(std::mem::take(&mut poly.counts));
+        }
+        if let Some(precision) = option.float_precision {
+            let bbox = &mut ann.bbox;
+            for value in [
+                &mut bbox.left,
+                &mut bbox.top,
+                &mut bbox.width,
+                &mut bbox.height,
+            ] {
+                *value = round(*value, precision);
+            }
+            if let Segmentation::Polygons(counts) = &mut ann.segmentation {
+                for value in counts.iter_mut().flatten() {
+                    *value = round(*value, precision);
+                }
+            }
+        }
+        ann.serialize(serializer)
+    }
+}
+
+/// Rounds the value to the given number of decimals.
+fn round(value: f64, precision: u32) -> f64 {
+    let factor = 10_f64.powi(i32::try_from(precision).unwrap_or(i32::MAX));
+    let rounded = (value * factor).round() / factor;
+    // Large precisions overflow the factor, the value is then kept as-is.
+    if rounded.is_finite() {
+        rounded
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::Dataset;
+    use serde_json::json;
+
+    fn dataset_json() -> serde_json::Value {
+        json!({
+            "info": {"year": 2023, "description": "test"},
+            "licenses": [{"id": 1, "name": "MIT", "url": ""}],
+            "annotations": [
+                // The annotation comes before its image, its polygons can only be converted at the end.
+                {"id": 2, "image_id": 1, "category_id": 1, "segmentation": [[0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 0.0]],
+                 "area": 16.0, "bbox": [0.0, 0.0, 4.0, 4.0], "iscrowd": 0},
+                {"id": 1, "image_id": 2, "category_id": 1, "segmentation": {"size": [5, 5], "counts": [0, 25]},
+                 "area": 25.0, "bbox": [0.0, 0.0, 5.0, 5.0], "iscrowd": 1, "attribute": "occluded"}
+            ],
+            "images": [
+                {"id": 1, "width": 10, "height": 8, "file_name": "1.jpg"},
+                {"id": 2, "width": 5, "height": 5, "file_name": "2.jpg"},
+                {"id": 3, "width": 5, "height": 5, "file_name": "3.jpg"}
+            ],
+            "categories": [{"id": 1, "name": "person", "supercategory": "person"}, {"id": 2, "name": "dog"}],
+            "split": "train"
+        })
+    }
+
+    fn load_str(content: &str) -> Result<BTreemapDataset, LoadingError> {
+        read(
+            content.as_bytes(),
+            "images",
+            PathBuf::from("annotations.json"),
+        )
+    }
+
+    #[test]
+    fn streaming_matches_from_dataset() {
+        let content = dataset_json().to_string();
+        let expected =
+            BTreemapDataset::from_dataset(serde_json::from_str(&content).unwrap(), "images")
+                .unwrap();
+        let dataset = load_str(&content).unwrap();
+
+        assert_eq!(dataset, expected);
+        assert_eq!(dataset.get_img_anns(3).unwrap().len(), 0);
+        assert_eq!(
+            dataset.get_ann(2).unwrap().segmentation,
+            Segmentation::PolygonsRS(PolygonsRS {
+                size: vec![8, 10],
+                counts: vec![vec![0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 0.0]],
+            })
+        );
+    }
+
+    #[test]
+    fn streaming_writer_matches_dataset_serialization() {
+        let dataset = load_str(&dataset_json().to_string()).unwrap();
+        let mut written = Vec::new();
+        write(&dataset, &mut written, SaveOption::default()).unwrap();
+
+        let expected = serde_json::to_string_pretty(&Dataset::from(&dataset)).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), expected);
+    }
+
+    #[test]
+    fn writer_formatting() {
+        let dataset = load_str(&dataset_json().to_string()).unwrap();
+        let write_str = |option| {
+            let mut written = Vec::new();
+            write(&dataset, &mut written, option).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        let compact = write_str(SaveOption::new().pretty(false));
+        assert_eq!(
+            compact,
+            serde_json::to_string(&Dataset::from(&dataset)).unwrap()
+        );
+        let indented = write_str(SaveOption::new().indent(4));
+        assert!(indented.starts_with("{\n    \"info\": {\n        \"year\""));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&indented).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&compact).unwrap()
+        );
+    }
+
+    #[test]
+    fn writer_float_precision() {
+        let mut content = dataset_json();
+        content["annotations"][0]["segmentation"] =
+            json!([[0.123_456, 0.0, 0.0, 4.987_654, 4.0, 4.0]]);
+        content["annotations"][0]["bbox"] = json!([0.123_456, 0.0, 4.444_449, 4.987_654]);
+        let dataset = load_str(&content.to_string()).unwrap();
+        let mut written = Vec::new();
+        write(
+            &dataset,
+            &mut written,
+            SaveOption::new().float_precision(Some(2)),
+        )
+        .unwrap();
+
+        let written: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        let ann = &written["annotations"][1];
+        assert_eq!(ann["id"], json!(2));
+        assert_eq!(
+            ann["segmentation"],
+            json!([[0.12, 0.0, 0.0, 4.99, 4.0, 4.0]])
+        );
+        assert_eq!(ann["bbox"], json!([0.12, 0.0, 4.44, 4.99]));
+        // The dataset itself is not modified.
+        assert_eq!(dataset.get_ann(2).unwrap().bbox.left, 0.123_456);
+        assert_eq!(round(1.005, 20), 1.005);
+    }
+
+    #[test]
+    fn writer_canonical_ordering() {
+        let mut content = dataset_json();
+        content["licenses"] =
+            json!([{"id": 2, "name": "b", "url": ""}, {"id": 1, "name": "a", "url": ""}]);
+        content["images"][0]["neg_category_ids"] = json!([3, 1, 2]);
+        let mut reordered = content.clone();
+        reordered["licenses"] =
+            json!([{"id": 1, "name": "a", "url": ""}, {"id": 2, "name": "b", "url": ""}]);
+        reordered["images"][0]["neg_category_ids"] = json!([2, 3, 1]);
+        let images = reordered["images"].as_array_mut().unwrap();
+        images.reverse();
+        let annotations = reordered["annotations"].as_array_mut().unwrap();
+        annotations.reverse();
+
+        let write_str = |content: &serde_json::Value| {
+            let dataset = load_str(&content.to_string()).unwrap();
+            let mut written = Vec::new();
+            write(&dataset, &mut written, SaveOption::new().canonical(true)).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        let written = write_str(&content);
+        assert_eq!(written, write_str(&reordered));
+
+        let written: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(written["licenses"][0]["id"], json!(1));
+        assert_eq!(written["images"][0]["neg_category_ids"], json!([1, 2, 3]));
+    }
+
+    #[test]
+    fn streaming_errors() {
+        let mut content = dataset_json();
+        content["annotations"][0]["image_id"] = json!(42);
+        assert!(matches!(
+            load_str(&content.to_string()),
+            Err(LoadingError::Parsing(MissingIdError::Image(42)))
+        ));
+
+        let mut content = dataset_json();
+        content.as_object_mut().unwrap().remove("categories");
+        assert!(matches!(
+            load_str(&content.to_string()),
+            Err(LoadingError::Deserialize(..))
+        ));
+        assert!(matches!(
+            load_str(&format!("{} []", dataset_json())),
+            Err(LoadingError::Deserialize(..))
+        ));
+    }
+}
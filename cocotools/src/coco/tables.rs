@@ -0,0 +1,692 @@
+//! Module converting datasets to and from [Arrow](https://arrow.apache.org/) tables, and saving them as Parquet files.
+//!
+//! The dataset is split in three tables (images, annotations and categories) with one row per element and one column per field,
+//! so that they can be queried directly with tools like DuckDB or Polars:
+//! - images: `id`, `width`, `height`, `file_name`, `license`, `flickr_url`, `coco_url`, `date_captured`, `neg_category_ids`,
+//!   `not_exhaustive_category_ids` and `extra`.
+//! - annotations: `id`, `image_id`, `category_id`, `bbox_left`, `bbox_top`, `bbox_width`, `bbox_height`, `area`, `iscrowd`,
+//!   `segmentation_height`, `segmentation_width`, `segmentation_counts` and `extra`.
+//! - categories: `id`, `name`, `supercategory`, `frequency`, `synonyms` and `extra`.
+//!
+//! The segmentations are stored as [`CocoRle`] (polygons are converted, which requires the size of their image), and the
+//! `extra` columns contain the unknown fields as a json object (null if there are none). The info, licenses and unknown
+//! top-level fields of the dataset are not exported.
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, Float64Array, ListArray, ListBuilder, StringArray, StringBuilder, UInt32Array,
+    UInt32Builder, UInt64Array,
+};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Bbox, Category, CocoRle, Dataset, ExtraFields, Frequency, Image,
+    Segmentation,
+};
+use crate::errors::TableError;
+use crate::mask::conversions;
+
+const IMAGES_FILE: &str = "images.parquet";
+const ANNOTATIONS_FILE: &str = "annotations.parquet";
+const CATEGORIES_FILE: &str = "categories.parquet";
+
+/// Images, annotations and categories of a dataset as Arrow tables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tables {
+    pub images: RecordBatch,
+    pub annotations: RecordBatch,
+    pub categories: RecordBatch,
+}
+
+/// Converts the dataset to Arrow tables.
+///
+/// # Errors
+///
+/// Will return `Err` if a segmentation cannot be converted to COCO RLE.
+pub fn to_arrow(dataset: &BTreemapDataset) -> Result<Tables, TableError> {
+    Ok(Tables {
+        images: images_batch(dataset.imgs.values())?,
+        annotations: annotations_batch(dataset)?,
+        categories: categories_batch(dataset.cats.values())?,
+    })
+}
+
+/// Builds a dataset from Arrow tables, the tables can come from another tool as long as they have the expected columns.
+///
+/// The columns are cast to the types used by [`to_arrow`], so that the tables written by tools like Polars or DuckDB (with
+/// `Int64` or `LargeUtf8` columns for example) can be read.
+///
+/// # Errors
+///
+/// Will return `Err` if a column is missing, cannot be cast to the expected type or has null values while it is required, or
+/// if an annotation references an image that does not exist.
+pub fn from_arrow<P: AsRef<Path>>(
+    tables: &Tables,
+    image_folder: P,
+) -> Result<BTreemapDataset, TableError> {
+    let dataset = Dataset {
+        images: read_images(&tables.images)?,
+        annotations: read_annotations(&tables.annotations)?,
+        categories: read_categories(&tables.categories)?,
+        ..Dataset::default()
+    };
+    Ok(BTreemapDataset::from_dataset(dataset, image_folder)?)
+}
+
+/// Saves the dataset as three Parquet files (`images.parquet`, `annotations.parquet` and `categories.parquet`) in the
+/// output directory, which is created if needed.
+///
+/// # Errors
+///
+/// Will return `Err` if a segmentation cannot be converted to COCO RLE, or if the files cannot be written.
+pub fn save_parquet<P: AsRef<Path>>(
+    dataset: &BTreemapDataset,
+    output_dir: P,
+) -> Result<(), TableError> {
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir)
+        .map_err(|err| TableError::Write(err, output_dir.to_path_buf()))?;
+    let tables = to_arrow(dataset)?;
+    write_parquet(&tables.images, &output_dir.join(IMAGES_FILE))?;
+    write_parquet(&tables.annotations, &output_dir.join(ANNOTATIONS_FILE))?;
+    write_parquet(&tables.categories, &output_dir.join(CATEGORIES_FILE))
+}
+
+/// Loads a dataset saved with [`save_parquet`].
+///
+/// # Errors
+///
+/// Will return `Err` if the files cannot be read, if a column is missing, cannot be cast to the expected type or has null
+/// values while it is required, or if an annotation references an image that does not exist.
+pub fn load_parquet<P: AsRef<Path>>(
+    input_dir: P,
+    image_folder: P,
+) -> Result<BTreemapDataset, TableError> {
+    let input_dir = input_dir.as_ref();
+    let tables = Tables {
+        images: read_parquet(&input_dir.join(IMAGES_FILE))?,
+        annotations: read_parquet(&input_dir.join(ANNOTATIONS_FILE))?,
+        categories: read_parquet(&input_dir.join(CATEGORIES_FILE))?,
+    };
+    from_arrow(&tables, image_folder)
+}
+
+fn write_parquet(batch: &RecordBatch, path: &Path) -> Result<(), TableError> {
+    let file = fs::File::create(path).map_err(|err| TableError::Write(err, path.to_path_buf()))?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)
+        .map_err(|err| TableError::Parquet(err, path.to_path_buf()))?;
+    writer
+        .write(batch)
+        .and_then(|()| writer.close().map(|_| ()))
+        .map_err(|err| TableError::Parquet(err, path.to_path_buf()))
+}
+
+fn read_parquet(path: &Path) -> Result<RecordBatch, TableError> {
+    let file = fs::File::open(path).map_err(|err| TableError::Read(err, path.to_path_buf()))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .map_err(|err| TableError::Parquet(err, path.to_path_buf()))?;
+    let schema = Arc::clone(builder.schema());
+    let reader = builder
+        .build()
+        .map_err(|err| TableError::Parquet(err, path.to_path_buf()))?;
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok(arrow::compute::concat_batches(&schema, &batches)?)
+}
+
+fn images_schema() -> SchemaRef {
+    let cat_ids = DataType::List(Arc::new(Field::new_list_field(DataType::UInt32, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::UInt64, false),
+        Field::new("width", DataType::UInt32, false),
+        Field::new("height", DataType::UInt32, false),
+        Field::new("file_name", DataType::Utf8, false),
+        Field::new("license", DataType::UInt32, true),
+        Field::new("flickr_url", DataType::Utf8, true),
+        Field::new("coco_url", DataType::Utf8, true),
+        Field::new("date_captured", DataType::Utf8, true),
+        Field::new("neg_category_ids", cat_ids.clone(), true),
+        Field::new("not_exhaustive_category_ids", cat_ids, true),
+        Field::new("extra", DataType::Utf8, true),
+    ]))
+}
+
+fn annotations_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::UInt64, false),
+        Field::new("image_id", DataType::UInt64, false),
+        Field::new("category_id", DataType::UInt32, false),
+        Field::new("bbox_left", DataType::Float64, false),
+        Field::new("bbox_top", DataType::Float64, false),
+        Field::new("bbox_width", DataType::Float64, false),
+        Field::new("bbox_height", DataType::Float64, false),
+        Field::new("area", DataType::Float64, false),
+        Field::new("iscrowd", DataType::UInt32, false),
+        Field::new("segmentation_height", DataType::UInt32, false),
+        Field::new("segmentation_width", DataType::UInt32, false),
+        Field::new("segmentation_counts", DataType::Utf8, false),
+        Field::new("extra", DataType::Utf8, true),
+    ]))
+}
+
+fn categories_schema() -> SchemaRef {
+    let synonyms = DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::UInt32, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("supercategory", DataType::Utf8, true),
+        Field::new("frequency", DataType::Utf8, true),
+        Field::new("synonyms", synonyms, true),
+        Field::new("extra", DataType::Utf8, true),
+    ]))
+}
+
+fn images_batch<'a>(
+    imgs: impl Iterator<Item = &'a Image> + Clone,
+) -> Result<RecordBatch, TableError> {
+    let strings = |f: fn(&Image) -> Option<&str>| -> ArrayRef {
+        Arc::new(imgs.clone().map(f).collect::<StringArray>())
+    };
+    let cat_ids = |f: fn(&Image) -> &Option<Vec<u32>>| -> ArrayRef {
+        let mut builder = ListBuilder::new(UInt32Builder::new());
+        for img in imgs.clone() {
+            match f(img) {
+                Some(cat_ids) => {
+                    builder.values().append_slice(cat_ids);
+                    builder.append(true);
+                }
+                None => builder.append(false),
+            }
+        }
+        Arc::new(builder.finish())
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            imgs.clone().map(|img| img.id),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            imgs.clone().map(|img| img.width),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            imgs.clone().map(|img| img.height),
+        )),
+        strings(|img| Some(&img.file_name)),
+        Arc::new(imgs.clone().map(|img| img.license).collect::<UInt32Array>()),
+        strings(|img| img.flickr_url.as_deref()),
+        strings(|img| img.coco_url.as_deref()),
+        strings(|img| img.date_captured.as_deref()),
+        cat_ids(|img| &img.neg_category_ids),
+        cat_ids(|img| &img.not_exhaustive_category_ids),
+        extra_column(imgs.clone().map(|img| &img.extra))?,
+    ];
+    Ok(RecordBatch::try_new(images_schema(), columns)?)
+}
+
+fn annotations_batch(dataset: &BTreemapDataset) -> Result<RecordBatch, TableError> {
+    let anns = || dataset.anns.values();
+    let rles = conversions::encode_coco_rles(anns().map(|ann| &ann.segmentation).collect())?;
+    let floats = |f: fn(&Annotation) -> f64| -> ArrayRef {
+        Arc::new(Float64Array::from_iter_values(anns().map(f)))
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(anns().map(|ann| ann.id))),
+        Arc::new(UInt64Array::from_iter_values(
+            anns().map(|ann| ann.image_id),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            anns().map(|ann| ann.category_id),
+        )),
+        floats(|ann| ann.bbox.left),
+        floats(|ann| ann.bbox.top),
+        floats(|ann| ann.bbox.width),
+        floats(|ann| ann.bbox.height),
+        floats(|ann| ann.area),
+        Arc::new(UInt32Array::from_iter_values(anns().map(|ann| ann.iscrowd))),
+        Arc::new(UInt32Array::from_iter_values(
+            rles.iter().map(|rle| rle.size[0]),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            rles.iter().map(|rle| rle.size[1]),
+        )),
+        Arc::new(
+            rles.iter()
+                .map(|rle| Some(rle.counts.as_str()))
+                .collect::<StringArray>(),
+        ),
+        extra_column(anns().map(|ann| &ann.extra))?,
+    ];
+    Ok(RecordBatch::try_new(annotations_schema(), columns)?)
+}
+
+fn categories_batch<'a>(
+    cats: impl Iterator<Item = &'a Category> + Clone,
+) -> Result<RecordBatch, TableError> {
+    let mut synonyms = ListBuilder::new(StringBuilder::new());
+    for cat in cats.clone() {
+        match &cat.synonyms {
+            Some(cat_synonyms) => {
+                for synonym in cat_synonyms {
+                    synonyms.values().append_value(synonym);
+                }
+                synonyms.append(true);
+            }
+            None => synonyms.append(false),
+        }
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt32Array::from_iter_values(
+            cats.clone().map(|cat| cat.id),
+        )),
+        Arc::new(
+            cats.clone()
+                .map(|cat| Some(cat.name.as_str()))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            cats.clone()
+                .map(|cat| cat.supercategory.as_deref())
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            cats.clone()
+                .map(|cat| cat.frequency.map(frequency_code))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(synonyms.finish()),
+        extra_column(cats.clone().map(|cat| &cat.extra))?,
+    ];
+    Ok(RecordBatch::try_new(categories_schema(), columns)?)
+}
+
+/// Serializes the unknown fields as json objects, null when there are none.
+fn extra_column<'a>(extras: impl Iterator<Item = &'a ExtraFields>) -> Result<ArrayRef, TableError> {
+    let extras = extras
+        .map(|ExtraFields(extra)| {
+            if extra.is_empty() {
+                Ok(None)
+            } else {
+                serde_json::to_string(extra).map(Some)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(TableError::ExtraFields)?;
+    Ok(Arc::new(StringArray::from(extras)))
+}
+
+const fn frequency_code(frequency: Frequency) -> &'static str {
+    match frequency {
+        Frequency::Rare => "r",
+        Frequency::Common => "c",
+        Frequency::Frequent => "f",
+    }
+}
+
+fn read_images(batch: &RecordBatch) -> Result<Vec<Image>, TableError> {
+    let table = "images";
+    let schema = images_schema();
+    let ids = column::<UInt64Array>(batch, &schema, table, "id")?;
+    let widths = column::<UInt32Array>(batch, &schema, table, "width")?;
+    let heights = column::<UInt32Array>(batch, &schema, table, "height")?;
+    let file_names = column::<StringArray>(batch, &schema, table, "file_name")?;
+    let licenses = column::<UInt32Array>(batch, &schema, table, "license")?;
+    let flickr_urls = column::<StringArray>(batch, &schema, table, "flickr_url")?;
+    let coco_urls = column::<StringArray>(batch, &schema, table, "coco_url")?;
+    let dates_captured = column::<StringArray>(batch, &schema, table, "date_captured")?;
+    let neg_category_ids = column::<ListArray>(batch, &schema, table, "neg_category_ids")?;
+    let not_exhaustive_category_ids =
+        column::<ListArray>(batch, &schema, table, "not_exhaustive_category_ids")?;
+    let extras = column::<StringArray>(batch, &schema, table, "extra")?;
+    let cat_ids = |list: &ListArray,
+                   row: usize,
+                   name: &'static str|
+     -> Result<Option<Vec<u32>>, TableError> {
+        if list.is_null(row) {
+            return Ok(None);
+        }
+        let values = list.value(row);
+        let values = values
+            .as_any()
+            .downcast_ref::<UInt32Array>()
+            .ok_or(TableError::Column(table, name))?;
+        Ok(Some(values.iter().flatten().collect()))
+    };
+
+    (0..batch.num_rows())
+        .map(|row| {
+            Ok(Image {
+                id: ids.value(row),
+                width: widths.value(row),
+                height: heights.value(row),
+                file_name: file_names.value(row).to_string(),
+                license: (!licenses.is_null(row)).then(|| licenses.value(row)),
+                flickr_url: optional_string(&flickr_urls, row),
+                coco_url: optional_string(&coco_urls, row),
+                date_captured: optional_string(&dates_captured, row),
+                neg_category_ids: cat_ids(&neg_category_ids, row, "neg_category_ids")?,
+                not_exhaustive_category_ids: cat_ids(
+                    &not_exhaustive_category_ids,
+                    row,
+                    "not_exhaustive_category_ids",
+                )?,
+                extra: extra_fields(&extras, row)?,
+            })
+        })
+        .collect()
+}
+
+fn read_annotations(batch: &RecordBatch) -> Result<Vec<Annotation>, TableError> {
+    let table = "annotations";
+    let schema = annotations_schema();
+    let ids = column::<UInt64Array>(batch, &schema, table, "id")?;
+    let image_ids = column::<UInt64Array>(batch, &schema, table, "image_id")?;
+    let category_ids = column::<UInt32Array>(batch, &schema, table, "category_id")?;
+    let lefts = column::<Float64Array>(batch, &schema, table, "bbox_left")?;
+    let tops = column::<Float64Array>(batch, &schema, table, "bbox_top")?;
+    let widths = column::<Float64Array>(batch, &schema, table, "bbox_width")?;
+    let heights = column::<Float64Array>(batch, &schema, table, "bbox_height")?;
+    let areas = column::<Float64Array>(batch, &schema, table, "area")?;
+    let iscrowds = column::<UInt32Array>(batch, &schema, table, "iscrowd")?;
+    let rle_heights = column::<UInt32Array>(batch, &schema, table, "segmentation_height")?;
+    let rle_widths = column::<UInt32Array>(batch, &schema, table, "segmentation_width")?;
+    let rle_counts = column::<StringArray>(batch, &schema, table, "segmentation_counts")?;
+    let extras = column::<StringArray>(batch, &schema, table, "extra")?;
+
+    (0..batch.num_rows())
+        .map(|row| {
+            Ok(Annotation {
+                id: ids.value(row),
+                image_id: image_ids.value(row),
+                category_id: category_ids.value(row),
+                segmentation: Segmentation::CocoRle(CocoRle {
+                    size: vec![rle_heights.value(row), rle_widths.value(row)],
+                    counts: rle_counts.value(row).to_string(),
+                }),
+                area: areas.value(row),
+                bbox: Bbox {
+                    left: lefts.value(row),
+                    top: tops.value(row),
+                    width: widths.value(row),
+                    height: heights.value(row),
+                },
+                iscrowd: iscrowds.value(row),
+                extra: extra_fields(&extras, row)?,
+            })
+        })
+        .collect()
+}
+
+fn read_categories(batch: &RecordBatch) -> Result<Vec<Category>, TableError> {
+    let table = "categories";
+    let schema = categories_schema();
+    let ids = column::<UInt32Array>(batch, &schema, table, "id")?;
+    let names = column::<StringArray>(batch, &schema, table, "name")?;
+    let supercategories = column::<StringArray>(batch, &schema, table, "supercategory")?;
+    let frequencies = column::<StringArray>(batch, &schema, table, "frequency")?;
+    let synonyms = column::<ListArray>(batch, &schema, table, "synonyms")?;
+    let extras = column::<StringArray>(batch, &schema, table, "extra")?;
+
+    (0..batch.num_rows())
+        .map(|row| {
+            let frequency = if frequencies.is_null(row) {
+                None
+            } else {
+                Some(match frequencies.value(row) {
+                    "r" => Frequency::Rare,
+                    "c" => Frequency::Common,
+                    "f" => Frequency::Frequent,
+                    frequency => return Err(TableError::Frequency(frequency.to_string())),
+                })
+            };
+            let synonyms = if synonyms.is_null(row) {
+                None
+            } else {
+                let values = synonyms.value(row);
+                let values = values
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .ok_or(TableError::Column(table, "synonyms"))?;
+                Some(values.iter().flatten().map(String::from).collect())
+            };
+            Ok(Category {
+                id: ids.value(row),
+                name: names.value(row).to_string(),
+                supercategory: optional_string(&supercategories, row),
+                frequency,
+                synonyms,
+                extra: extra_fields(&extras, row)?,
+            })
+        })
+        .collect()
+}
+
+/// Column of the table with the given name, cast to the type it has in the schema.
+///
+/// The cast fails instead of replacing the values that do not fit in the type (negative ids for example) by nulls.
+fn column<A: Array + Clone + 'static>(
+    batch: &RecordBatch,
+    schema: &Schema,
+    table: &'static str,
+    name: &'static str,
+) -> Result<A, TableError> {
+    let (field, column) = schema
+        .field_with_name(name)
+        .ok()
+        .zip(batch.column_by_name(name))
+        .ok_or(TableError::Column(table, name))?;
+    let options = CastOptions {
+        safe: false,
+        ..CastOptions::default()
+    };
+    let column = cast_with_options(column, field.data_type(), &options)
+        .map_err(|err| TableError::Cast(err, table, name))?;
+    if !field.is_nullable() && column.null_count() > 0 {
+        return Err(TableError::Null(table, name));
+    }
+    column
+        .as_any()
+        .downcast_ref::<A>()
+        .cloned()
+        .ok_or(TableError::Column(table, name))
+}
+
+fn optional_string(strings: &StringArray, row: usize) -> Option<String> {
+    (!strings.is_null(row)).then(|| strings.value(row).to_string())
+}
+
+fn extra_fields(extras: &StringArray, row: usize) -> Result<ExtraFields, TableError> {
+    if extras.is_null(row) {
+        Ok(ExtraFields::default())
+    } else {
+        serde_json::from_str(extras.value(row)).map_err(TableError::ExtraFields)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::errors::MaskError;
+    use crate::mask::conversions::convert_coco_segmentation;
+    use crate::test_utils::test_dir;
+    use arrow::array::Int64Array;
+    use serde_json::json;
+
+    const ANNOTATIONS_PATH: &str = "../data_samples/coco_25k/annotations.json";
+
+    /// The dataset as it is expected after a roundtrip, with all the segmentations as COCO RLE.
+    fn as_coco_rle(dataset: &BTreemapDataset) -> BTreemapDataset {
+        let mut dataset = dataset.clone();
+        convert_coco_segmentation(&mut dataset, conversions::Segmentation::CocoRle).unwrap();
+        dataset
+    }
+
+    fn lvis_dataset() -> BTreemapDataset {
+        let dataset = json!({
+            "images": [
+                {"id": 1, "width": 10, "height": 8, "coco_url": "http://images/1.jpg",
+                 "neg_category_ids": [2], "not_exhaustive_category_ids": [], "attribute": {"night": true}},
+                {"id": 2, "width": 5, "height": 5, "file_name": "2.jpg"}
+            ],
+            "annotations": [
+                {"id": 1, "image_id": 1, "category_id": 1, "segmentation": [[0.0, 0.0, 0.0, 4.0, 4.0, 4.0]],
+                 "area": 8.0, "bbox": [0.0, 0.0, 4.0, 4.0]},
+                {"id": 2, "image_id": 2, "category_id": 2, "segmentation": {"size": [5, 5], "counts": [0, 25]},
+                 "area": 25.0, "bbox": [0.0, 0.0, 5.0, 5.0], "iscrowd": 1, "occluded": 1}
+            ],
+            "categories": [
+                {"id": 1, "name": "person", "supercategory": "person", "frequency": "f", "synonyms": ["human"]},
+                {"id": 2, "name": "dog", "synonyms": []}
+            ]
+        });
+        BTreemapDataset::from_dataset(serde_json::from_value(dataset).unwrap(), "images").unwrap()
+    }
+
+    #[test]
+    fn arrow_roundtrip() {
+        let dataset = lvis_dataset();
+        let tables = to_arrow(&dataset).unwrap();
+        assert_eq!(tables.images.num_rows(), 2);
+        assert_eq!(tables.annotations.num_rows(), 2);
+        assert_eq!(
+            column::<StringArray>(
+                &tables.annotations,
+                &annotations_schema(),
+                "annotations",
+                "extra"
+            )
+            .unwrap()
+            .value(1),
+            r#"{"occluded":1}"#
+        );
+
+        assert_eq!(
+            from_arrow(&tables, "images").unwrap(),
+            as_coco_rle(&dataset)
+        );
+    }
+
+    #[test]
+    fn parquet_roundtrip() {
+        let dir = test_dir("parquet-roundtrip");
+        let dataset = BTreemapDataset::new(ANNOTATIONS_PATH, "images").unwrap();
+        save_parquet(&dataset, &dir).unwrap();
+        assert!(dir.join(ANNOTATIONS_FILE).is_file());
+
+        assert_eq!(
+            load_parquet(dir.as_path(), Path::new("images")).unwrap(),
+            as_coco_rle(&dataset)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Same type, with the signed integers and large strings and lists used by Polars and DuckDB.
+    fn polars_type(data_type: &DataType) -> DataType {
+        match data_type {
+            DataType::UInt32 | DataType::UInt64 => DataType::Int64,
+            DataType::Utf8 => DataType::LargeUtf8,
+            DataType::List(field) => DataType::LargeList(Arc::new(Field::new_list_field(
+                polars_type(field.data_type()),
+                true,
+            ))),
+            data_type => data_type.clone(),
+        }
+    }
+
+    fn polars_batch(batch: &RecordBatch) -> RecordBatch {
+        let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(field, column)| {
+                let data_type = polars_type(field.data_type());
+                let column = arrow::compute::cast(column, &data_type).unwrap();
+                (Field::new(field.name(), data_type, true), column)
+            })
+            .unzip();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    }
+
+    #[test]
+    fn tables_from_other_tools() {
+        let dataset = lvis_dataset();
+        let tables = to_arrow(&dataset).unwrap();
+        let tables = Tables {
+            images: polars_batch(&tables.images),
+            annotations: polars_batch(&tables.annotations),
+            categories: polars_batch(&tables.categories),
+        };
+        assert_eq!(
+            tables
+                .images
+                .schema()
+                .field_with_name("id")
+                .unwrap()
+                .data_type(),
+            &DataType::Int64
+        );
+
+        assert_eq!(
+            from_arrow(&tables, "images").unwrap(),
+            as_coco_rle(&dataset)
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let tables = to_arrow(&lvis_dataset()).unwrap();
+        let with_column = |name: &str, column: ArrayRef| {
+            let mut images = polars_batch(&tables.images);
+            let idx = images.schema().index_of(name).unwrap();
+            let mut columns = images.columns().to_vec();
+            columns[idx] = column;
+            images = RecordBatch::try_new(images.schema(), columns).unwrap();
+            Tables {
+                images,
+                ..tables.clone()
+            }
+        };
+
+        let widths = with_column("width", Arc::new(Int64Array::from(vec![Some(10), None])));
+        assert!(matches!(
+            from_arrow(&widths, "images"),
+            Err(TableError::Null("images", "width"))
+        ));
+        let ids = with_column("id", Arc::new(Int64Array::from(vec![1, -2])));
+        assert!(matches!(
+            from_arrow(&ids, "images"),
+            Err(TableError::Cast(_, "images", "id"))
+        ));
+        let licenses = with_column("license", Arc::new(Int64Array::from(vec![None, Some(1)])));
+        assert_eq!(
+            from_arrow(&licenses, "images").unwrap().imgs[&2].license,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn polygons_without_size() {
+        let mut dataset = lvis_dataset();
+        dataset.anns.get_mut(&1).unwrap().segmentation =
+            Segmentation::Polygons(vec![vec![0.0, 0.0, 0.0, 4.0, 4.0, 4.0]]);
+        assert!(matches!(
+            to_arrow(&dataset),
+            Err(TableError::Mask(MaskError::MissingPolygonSize))
+        ));
+    }
+
+    #[test]
+    fn missing_column() {
+        let mut tables = to_arrow(&lvis_dataset()).unwrap();
+        tables.categories = tables.categories.project(&[0, 2, 3, 4, 5]).unwrap();
+        assert!(matches!(
+            from_arrow(&tables, "images"),
+            Err(TableError::Column("categories", "name"))
+        ));
+    }
+}
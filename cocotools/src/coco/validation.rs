@@ -0,0 +1,1141 @@
+//! Module containing the checks used to find problems in a dataset.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Bbox, Category, Dataset, Image, PolygonsRS, Rle, Segmentation,
+};
+use crate::errors::LoadingError;
+use crate::mask::utils::Area;
+use crate::utils::par_map;
+
+/// Options used when validating a dataset.
+#[derive(Clone, Copy, Debug)]
+pub struct ValidationOption {
+    /// Maximum relative difference allowed between the `area` of an annotation and the area of its segmentation.
+    ///
+    /// The area of a segmentation is the number of pixels of its mask, polygons are rasterized first.
+    pub area_tolerance: f64,
+}
+
+impl ValidationOption {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn area_tolerance(mut self, area_tolerance: f64) -> Self {
+        self.area_tolerance = area_tolerance;
+        self
+    }
+}
+
+impl Default for ValidationOption {
+    fn default() -> Self {
+        Self {
+            area_tolerance: 0.05,
+        }
+    }
+}
+
+/// Problem found in a dataset.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum Issue {
+    DuplicateImageId {
+        image_id: u64,
+    },
+    DuplicateAnnotationId {
+        annotation_id: u64,
+    },
+    DuplicateCategoryId {
+        category_id: u32,
+    },
+    /// The annotation references an image that is not in the dataset.
+    MissingImage {
+        annotation_id: u64,
+        image_id: u64,
+    },
+    /// The annotation references a category that is not in the dataset.
+    MissingCategory {
+        annotation_id: u64,
+        category_id: u32,
+    },
+    /// The segmentation could not be decoded.
+    InvalidSegmentation {
+        annotation_id: u64,
+        error: String,
+    },
+    /// The counts of the RLE do not sum up to the number of pixels given by its size.
+    RleSumMismatch {
+        annotation_id: u64,
+        sum: u64,
+        nb_pixels: u64,
+    },
+    /// The size of the segmentation does not correspond to the size of the image.
+    SizeMismatch {
+        annotation_id: u64,
+        segmentation_size: Vec<u32>,
+        image_size: Vec<u32>,
+    },
+    BboxOutsideImage {
+        annotation_id: u64,
+        bbox: Bbox,
+    },
+    /// The bounding box has a null (or negative) width or height.
+    DegenerateBbox {
+        annotation_id: u64,
+        bbox: Bbox,
+    },
+    /// The area of the annotation differs from the area of its segmentation by more than the tolerance.
+    AreaMismatch {
+        annotation_id: u64,
+        area: f64,
+        segmentation_area: f64,
+    },
+    OddPolygonLength {
+        annotation_id: u64,
+        polygon_idx: usize,
+        nb_coordinates: usize,
+    },
+    TooFewPolygonPoints {
+        annotation_id: u64,
+        polygon_idx: usize,
+        nb_points: usize,
+    },
+    /// The image file is not in the image folder.
+    MissingImageFile {
+        image_id: u64,
+        file_name: String,
+    },
+    /// The header of the image file could not be read.
+    UnreadableImageFile {
+        image_id: u64,
+        file_name: String,
+        error: String,
+    },
+    /// The size of the image file does not correspond to the size of the image entry.
+    ImageFileSizeMismatch {
+        image_id: u64,
+        file_name: String,
+        width: u32,
+        height: u32,
+        file_width: u32,
+        file_height: u32,
+    },
+    /// The image file is not used by any image entry.
+    OrphanImageFile {
+        file_name: String,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateImageId { image_id } => {
+                write!(f, "Image {image_id}: the id is used by multiple images.")
+            }
+            Self::DuplicateAnnotationId { annotation_id } => write!(
+                f,
+                "Annotation {annotation_id}: the id is used by multiple annotations."
+            ),
+            Self::DuplicateCategoryId { category_id } => write!(
+                f,
+                "Category {category_id}: the id is used by multiple categories."
+            ),
+            Self::MissingImage {
+                annotation_id,
+                image_id,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the image {image_id} is not in the dataset."
+            ),
+            Self::MissingCategory {
+                annotation_id,
+                category_id,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the category {category_id} is not in the dataset."
+            ),
+            Self::InvalidSegmentation {
+                annotation_id,
+                error,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the segmentation could not be decoded. {error}"
+            ),
+            Self::RleSumMismatch {
+                annotation_id,
+                sum,
+                nb_pixels,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the RLE counts sum up to {sum}, but its size has {nb_pixels} pixels."
+            ),
+            Self::SizeMismatch {
+                annotation_id,
+                segmentation_size,
+                image_size,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the segmentation has a size of {segmentation_size:?}, but the image has a size of {image_size:?}."
+            ),
+            Self::BboxOutsideImage {
+                annotation_id,
+                bbox,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the bounding box {bbox:?} goes outside of the image."
+            ),
+            Self::DegenerateBbox {
+                annotation_id,
+                bbox,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the bounding box {bbox:?} is empty."
+            ),
+            Self::AreaMismatch {
+                annotation_id,
+                area,
+                segmentation_area,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the area is {area}, but the segmentation has an area of {segmentation_area}."
+            ),
+            Self::OddPolygonLength {
+                annotation_id,
+                polygon_idx,
+                nb_coordinates,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the polygon {polygon_idx} has an odd number of coordinates ({nb_coordinates})."
+            ),
+            Self::TooFewPolygonPoints {
+                annotation_id,
+                polygon_idx,
+                nb_points,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: the polygon {polygon_idx} has only {nb_points} points."
+            ),
+            Self::MissingImageFile { image_id, file_name } => write!(
+                f,
+                "Image {image_id}: the file {file_name:?} is not in the image folder."
+            ),
+            Self::UnreadableImageFile {
+                image_id,
+                file_name,
+                error,
+            } => write!(
+                f,
+                "Image {image_id}: the file {file_name:?} could not be read. {error}"
+            ),
+            Self::ImageFileSizeMismatch {
+                image_id,
+                file_name,
+                width,
+                height,
+                file_width,
+                file_height,
+            } => write!(
+                f,
+                "Image {image_id}: the size is {width}x{height}, but the file {file_name:?} has a size of {file_width}x{file_height}."
+            ),
+            Self::OrphanImageFile { file_name } => write!(
+                f,
+                "The file {file_name:?} of the image folder is not used by any image."
+            ),
+        }
+    }
+}
+
+/// Checks all the images, annotations and categories of the dataset and returns the problems found.
+///
+/// Duplicate ids cannot be detected once the dataset is loaded in a [`BTreemapDataset`], use [`find_duplicate_ids`] on the [`Dataset`] for this.
+#[must_use]
+pub fn validate(dataset: &BTreemapDataset, option: ValidationOption) -> Vec<Issue> {
+    par_map(dataset.anns.values().collect(), |ann| {
+        let mut issues = Vec::new();
+        if dataset.get_cat(ann.category_id).is_err() {
+            issues.push(Issue::MissingCategory {
+                annotation_id: ann.id,
+                category_id: ann.category_id,
+            });
+        }
+        match dataset.get_img(ann.image_id) {
+            Ok(img) => validate_ann(ann, img, option, &mut issues),
+            Err(_) => issues.push(Issue::MissingImage {
+                annotation_id: ann.id,
+                image_id: ann.image_id,
+            }),
+        }
+        issues
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Returns an issue for each id used by more than one image, annotation or category.
+#[must_use]
+pub fn find_duplicate_ids(dataset: &Dataset) -> Vec<Issue> {
+    fn duplicates<T: Ord + Copy>(ids: impl Iterator<Item = T>) -> BTreeSet<T> {
+        let mut counts = BTreeMap::new();
+        for id in ids {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .filter_map(|(id, count)| (count > 1).then_some(id))
+            .collect()
+    }
+
+    let mut issues: Vec<Issue> = duplicates(dataset.images.iter().map(|img| img.id))
+        .into_iter()
+        .map(|image_id| Issue::DuplicateImageId { image_id })
+        .collect();
+    issues.extend(
+        duplicates(dataset.annotations.iter().map(|ann| ann.id))
+            .into_iter()
+            .map(|annotation_id| Issue::DuplicateAnnotationId { annotation_id }),
+    );
+    issues.extend(
+        duplicates(dataset.categories.iter().map(|cat| cat.id))
+            .into_iter()
+            .map(|category_id| Issue::DuplicateCategoryId { category_id }),
+    );
+    issues
+}
+
+/// Checks that the image files of the dataset exist in its image folder and have the right size.
+///
+/// Only the headers of the images are read. The image files present in the image folder (or its subfolders) but not used by any image are reported as orphans.
+///
+/// # Errors
+///
+/// Will return `Err` if the image folder (or one of its subfolders) cannot be read.
+pub fn check_image_files(dataset: &BTreemapDataset) -> Result<Vec<Issue>, LoadingError> {
+    let imgs: Vec<&Image> = dataset.imgs.values().collect();
+    let mut issues: Vec<Issue> = par_map(imgs.clone(), |img| {
+        check_image_file(img, &dataset.image_folder)
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let used_files: HashSet<PathBuf> = imgs
+        .iter()
+        .map(|img| PathBuf::from(&img.file_name))
+        .collect();
+    let mut image_files = Vec::new();
+    list_image_files(&dataset.image_folder, &mut image_files)?;
+    image_files.sort();
+    issues.extend(image_files.into_iter().filter_map(|path| {
+        let file_name = path.strip_prefix(&dataset.image_folder).ok()?;
+        (!used_files.contains(file_name)).then(|| Issue::OrphanImageFile {
+            file_name: file_name.to_string_lossy().into_owned(),
+        })
+    }));
+    Ok(issues)
+}
+
+fn check_image_file(img: &Image, image_folder: &Path) -> Option<Issue> {
+    let img_path = image_folder.join(&img.file_name);
+    if !img_path.is_file() {
+        return Some(Issue::MissingImageFile {
+            image_id: img.id,
+            file_name: img.file_name.clone(),
+        });
+    }
+    match image::image_dimensions(&img_path) {
+        Ok((file_width, file_height)) => (file_width != img.width || file_height != img.height)
+            .then(|| Issue::ImageFileSizeMismatch {
+                image_id: img.id,
+                file_name: img.file_name.clone(),
+                width: img.width,
+                height: img.height,
+                file_width,
+                file_height,
+            }),
+        Err(error) => Some(Issue::UnreadableImageFile {
+            image_id: img.id,
+            file_name: img.file_name.clone(),
+            error: error.to_string(),
+        }),
+    }
+}
+
+/// Recursively lists the files with an image extension in the folder.
+fn list_image_files(folder: &Path, image_files: &mut Vec<PathBuf>) -> Result<(), LoadingError> {
+    let entries = fs::read_dir(folder)
+        .map_err(|err| LoadingError::ReadImageFolder(err, folder.to_path_buf()))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|err| LoadingError::ReadImageFolder(err, folder.to_path_buf()))?
+            .path();
+        if path.is_dir() {
+            list_image_files(&path, image_files)?;
+        } else if image::ImageFormat::from_path(&path).is_ok() {
+            image_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn validate_ann(ann: &Annotation, img: &Image, option: ValidationOption, issues: &mut Vec<Issue>) {
+    let bbox = &ann.bbox;
+    if !(bbox.width > 0.0 && bbox.height > 0.0) {
+        issues.push(Issue::DegenerateBbox {
+            annotation_id: ann.id,
+            bbox: bbox.clone(),
+        });
+    } else if bbox.left < 0.0
+        || bbox.top < 0.0
+        || bbox.left + bbox.width > f64::from(img.width)
+        || bbox.top + bbox.height > f64::from(img.height)
+    {
+        issues.push(Issue::BboxOutsideImage {
+            annotation_id: ann.id,
+            bbox: bbox.clone(),
+        });
+    }
+
+    if let Some(segmentation_area) = validate_segmentation(ann, img, issues) {
+        let tolerance = option.area_tolerance * segmentation_area.max(1.0);
+        if (ann.area - segmentation_area).abs() > tolerance {
+            issues.push(Issue::AreaMismatch {
+                annotation_id: ann.id,
+                area: ann.area,
+                segmentation_area,
+            });
+        }
+    }
+}
+
+/// Checks the segmentation of the annotation, and returns its area if it is valid.
+fn validate_segmentation(ann: &Annotation, img: &Image, issues: &mut Vec<Issue>) -> Option<f64> {
+    let image_size = vec![img.height, img.width];
+    let size = match &ann.segmentation {
+        Segmentation::Rle(rle) => &rle.size,
+        Segmentation::CocoRle(coco_rle) => &coco_rle.size,
+        Segmentation::PolygonsRS(poly) => &poly.size,
+        Segmentation::Polygons(_) => &image_size,
+    };
+    let mut is_valid = true;
+    if size != &image_size {
+        issues.push(Issue::SizeMismatch {
+            annotation_id: ann.id,
+            segmentation_size: size.clone(),
+            image_size: image_size.clone(),
+        });
+        is_valid = false;
+    }
+
+    match &ann.segmentation {
+        Segmentation::Rle(rle) => {
+            is_valid &= validate_rle(ann.id, rle, issues);
+            is_valid.then(|| f64::from(rle.area()))
+        }
+        Segmentation::CocoRle(coco_rle) => match Rle::try_from(coco_rle) {
+            Ok(rle) => {
+                is_valid &= validate_rle(ann.id, &rle, issues);
+                is_valid.then(|| f64::from(rle.area()))
+            }
+            Err(error) => {
+                issues.push(Issue::InvalidSegmentation {
+                    annotation_id: ann.id,
+                    error: error.to_string(),
+                });
+                None
+            }
+        },
+        Segmentation::PolygonsRS(poly) => {
+            is_valid &= validate_polygons(ann.id, &poly.counts, issues);
+            is_valid.then(|| f64::from(poly.area()))
+        }
+        Segmentation::Polygons(poly) => {
+            is_valid &= validate_polygons(ann.id, poly, issues);
+            is_valid.then(|| {
+                f64::from(
+                    PolygonsRS {
+                        size: image_size,
+                        counts: poly.clone(),
+                    }
+                    .area(),
+                )
+            })
+        }
+    }
+}
+
+fn validate_rle(annotation_id: u64, rle: &Rle, issues: &mut Vec<Issue>) -> bool {
+    let sum: u64 = rle.counts.iter().map(|count| u64::from(*count)).sum();
+    let nb_pixels: u64 = rle.size.iter().map(|dim| u64::from(*dim)).product();
+    if sum != nb_pixels {
+        issues.push(Issue::RleSumMismatch {
+            annotation_id,
+            sum,
+            nb_pixels,
+        });
+        return false;
+    }
+    true
+}
+
+fn validate_polygons(annotation_id: u64, polygons: &[Vec<f64>], issues: &mut Vec<Issue>) -> bool {
+    let mut is_valid = true;
+    for (polygon_idx, polygon) in polygons.iter().enumerate() {
+        if polygon.len() % 2 != 0 {
+            issues.push(Issue::OddPolygonLength {
+                annotation_id,
+                polygon_idx,
+                nb_coordinates: polygon.len(),
+            });
+            is_valid = false;
+        } else if polygon.len() < 6 {
+            issues.push(Issue::TooFewPolygonPoints {
+                annotation_id,
+                polygon_idx,
+                nb_points: polygon.len() / 2,
+            });
+        }
+    }
+    is_valid
+}
+
+/// Modification made to a dataset when fixing it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    RemovedAnnotation {
+        annotation_id: u64,
+        reason: String,
+    },
+    /// The bounding box was clipped to the image bounds.
+    ClippedBbox {
+        annotation_id: u64,
+        old_bbox: Bbox,
+        new_bbox: Bbox,
+    },
+    /// The bounding box was replaced by the one of the segmentation.
+    RecomputedBbox {
+        annotation_id: u64,
+        old_bbox: Bbox,
+        new_bbox: Bbox,
+    },
+    /// The area was replaced by the one of the segmentation.
+    RecomputedArea {
+        annotation_id: u64,
+        old_area: f64,
+        new_area: f64,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemovedAnnotation {
+                annotation_id,
+                reason,
+            } => write!(f, "Annotation {annotation_id}: removed, {reason}."),
+            Self::ClippedBbox {
+                annotation_id,
+                old_bbox,
+                new_bbox,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: clipped the bounding box from {old_bbox:?} to {new_bbox:?}."
+            ),
+            Self::RecomputedBbox {
+                annotation_id,
+                old_bbox,
+                new_bbox,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: replaced the bounding box {old_bbox:?} by the one of the segmentation {new_bbox:?}."
+            ),
+            Self::RecomputedArea {
+                annotation_id,
+                old_area,
+                new_area,
+            } => write!(
+                f,
+                "Annotation {annotation_id}: replaced the area {old_area} by the one of the segmentation {new_area}."
+            ),
+        }
+    }
+}
+
+/// Fixes the common annotation problems that can be fixed automatically, and returns the list of all the changes made.
+///
+/// - Annotations referencing an unknown category or image are removed.
+/// - The area is recomputed from the segmentation when it differs by more than the `area_tolerance` of the option.
+/// - The bounding box is recomputed from the segmentation when it differs by more than one pixel (the difference can come from the box convention used).
+/// - The bounding boxes are clipped to the image bounds.
+/// - Annotations with an empty mask or an empty bounding box are removed.
+///
+/// Annotations whose segmentation is invalid only have their bounding box clipped.
+pub fn fix(dataset: &mut BTreemapDataset, option: ValidationOption) -> Vec<Change> {
+    let (cats, imgs) = (&dataset.cats, &dataset.imgs);
+    let fixes = par_map(dataset.anns.values_mut().collect(), |ann| {
+        fix_ann(ann, cats, imgs, option)
+    });
+    let mut changes = Vec::new();
+    let mut removed_anns = Vec::new();
+    for (ann_changes, removal_reason) in fixes {
+        changes.extend(ann_changes);
+        removed_anns.extend(removal_reason);
+    }
+
+    for (ann_id, reason) in removed_anns {
+        if dataset.remove_ann(ann_id).is_ok() {
+            changes.push(Change::RemovedAnnotation {
+                annotation_id: ann_id,
+                reason,
+            });
+        }
+    }
+    changes
+}
+
+/// Fixes the annotation in place, returns the changes made and, if the annotation should be removed, its id and the reason why.
+fn fix_ann(
+    ann: &mut Annotation,
+    cats: &BTreeMap<u32, Category>,
+    imgs: &BTreeMap<u64, Image>,
+    option: ValidationOption,
+) -> (Vec<Change>, Option<(u64, String)>) {
+    let mut changes = Vec::new();
+    if !cats.contains_key(&ann.category_id) {
+        let reason = format!("the category {} does not exist", ann.category_id);
+        return (changes, Some((ann.id, reason)));
+    }
+    let img = match imgs.get(&ann.image_id) {
+        Some(img) => img,
+        None => {
+            let reason = format!("the image {} does not exist", ann.image_id);
+            return (changes, Some((ann.id, reason)));
+        }
+    };
+
+    if let Some(segmentation_area) = validate_segmentation(ann, img, &mut Vec::new()) {
+        if segmentation_area <= 0.0 {
+            return (changes, Some((ann.id, "the mask is empty".to_string())));
+        }
+        let tolerance = option.area_tolerance * segmentation_area.max(1.0);
+        if (ann.area - segmentation_area).abs() > tolerance {
+            changes.push(Change::RecomputedArea {
+                annotation_id: ann.id,
+                old_area: ann.area,
+                new_area: segmentation_area,
+            });
+            ann.area = segmentation_area;
+        }
+        if let Some(segmentation_bbox) = segmentation_bbox(&ann.segmentation) {
+            if bbox_distance(&ann.bbox, &segmentation_bbox) > 1.0 {
+                changes.push(Change::RecomputedBbox {
+                    annotation_id: ann.id,
+                    old_bbox: ann.bbox.clone(),
+                    new_bbox: segmentation_bbox.clone(),
+                });
+                ann.bbox = segmentation_bbox;
+            }
+        }
+    }
+
+    let clipped_bbox = clip_bbox(&ann.bbox, img);
+    if clipped_bbox != ann.bbox {
+        changes.push(Change::ClippedBbox {
+            annotation_id: ann.id,
+            old_bbox: ann.bbox.clone(),
+            new_bbox: clipped_bbox.clone(),
+        });
+        ann.bbox = clipped_bbox;
+    }
+    if !(ann.bbox.width > 0.0 && ann.bbox.height > 0.0) {
+        return (
+            changes,
+            Some((ann.id, "the bounding box is empty".to_string())),
+        );
+    }
+    (changes, None)
+}
+
+/// Bounding box of a segmentation that has already been validated.
+fn segmentation_bbox(segmentation: &Segmentation) -> Option<Bbox> {
+    match segmentation {
+        Segmentation::Rle(rle) => Some(Bbox::from(rle)),
+        Segmentation::CocoRle(coco_rle) => Bbox::try_from(coco_rle).ok(),
+        Segmentation::PolygonsRS(poly) => Some(Bbox::from(poly)),
+        Segmentation::Polygons(poly) => Some(Bbox::from(poly)),
+    }
+}
+
+/// Largest difference between the sides of the two boxes.
+fn bbox_distance(bbox: &Bbox, other: &Bbox) -> f64 {
+    [
+        bbox.left - other.left,
+        bbox.top - other.top,
+        (bbox.left + bbox.width) - (other.left + other.width),
+        (bbox.top + bbox.height) - (other.top + other.height),
+    ]
+    .into_iter()
+    .fold(0.0, |distance, difference| distance.max(difference.abs()))
+}
+
+fn clip_bbox(bbox: &Bbox, img: &Image) -> Bbox {
+    let (width, height) = (f64::from(img.width), f64::from(img.height));
+    let left = bbox.left.clamp(0.0, width);
+    let top = bbox.top.clamp(0.0, height);
+    let right = (bbox.left + bbox.width).clamp(0.0, width);
+    let bottom = (bbox.top + bbox.height).clamp(0.0, height);
+    if left == bbox.left
+        && top == bbox.top
+        && right == bbox.left + bbox.width
+        && bottom == bbox.top + bbox.height
+    {
+        return bbox.clone();
+    }
+    Bbox {
+        left,
+        top,
+        width: (right - left).max(0.0),
+        height: (bottom - top).max(0.0),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::{Category, CocoRle, PolygonsRS};
+
+    fn ann(id: u64, segmentation: Segmentation, area: f64, bbox: Bbox) -> Annotation {
+        Annotation {
+            id,
+            image_id: 1,
+            category_id: 1,
+            segmentation,
+            area,
+            bbox,
+            iscrowd: 0,
+            extra: Default::default(),
+        }
+    }
+
+    fn bbox(left: f64, top: f64, width: f64, height: f64) -> Bbox {
+        Bbox {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn validate_finds_all_problems() {
+        let square = Segmentation::PolygonsRS(PolygonsRS {
+            size: vec![10, 10],
+            counts: vec![vec![1.0, 1.0, 5.0, 1.0, 5.0, 5.0, 1.0, 5.0]],
+        });
+        let dataset = Dataset {
+            images: vec![Image {
+                id: 1,
+                width: 10,
+                height: 10,
+                file_name: "1.jpg".to_string(),
+                ..Default::default()
+            }],
+            annotations: vec![
+                ann(1, square.clone(), 25.0, bbox(1.0, 1.0, 4.0, 4.0)),
+                ann(2, square.clone(), 30.0, bbox(8.0, 8.0, 4.0, 4.0)),
+                ann(3, square, 25.0, bbox(1.0, 1.0, 0.0, 4.0)),
+                ann(
+                    4,
+                    Segmentation::Rle(Rle {
+                        size: vec![10, 10],
+                        counts: vec![50, 10],
+                    }),
+                    10.0,
+                    bbox(5.0, 0.0, 1.0, 10.0),
+                ),
+                ann(
+                    5,
+                    Segmentation::CocoRle(CocoRle {
+                        size: vec![10, 10],
+                        counts: "5X".to_string(),
+                    }),
+                    10.0,
+                    bbox(5.0, 0.0, 1.0, 10.0),
+                ),
+                ann(
+                    6,
+                    Segmentation::Rle(Rle {
+                        size: vec![5, 20],
+                        counts: vec![50, 10, 40],
+                    }),
+                    10.0,
+                    bbox(5.0, 0.0, 1.0, 10.0),
+                ),
+                ann(
+                    7,
+                    Segmentation::PolygonsRS(PolygonsRS {
+                        size: vec![10, 10],
+                        counts: vec![vec![1.0, 1.0, 5.0, 1.0, 5.0], vec![1.0, 1.0, 5.0, 5.0]],
+                    }),
+                    8.0,
+                    bbox(1.0, 1.0, 4.0, 4.0),
+                ),
+                Annotation {
+                    category_id: 2,
+                    ..ann(
+                        8,
+                        Segmentation::Rle(Rle {
+                            size: vec![10, 10],
+                            counts: vec![50, 10, 40],
+                        }),
+                        10.0,
+                        bbox(5.0, 0.0, 1.0, 10.0),
+                    )
+                },
+            ],
+            categories: vec![Category {
+                id: 1,
+                name: "object".to_string(),
+                supercategory: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+
+        let issues = validate(&dataset, ValidationOption::default());
+        assert_eq!(
+            issues,
+            vec![
+                Issue::BboxOutsideImage {
+                    annotation_id: 2,
+                    bbox: bbox(8.0, 8.0, 4.0, 4.0)
+                },
+                Issue::AreaMismatch {
+                    annotation_id: 2,
+                    area: 30.0,
+                    segmentation_area: 25.0
+                },
+                Issue::DegenerateBbox {
+                    annotation_id: 3,
+                    bbox: bbox(1.0, 1.0, 0.0, 4.0)
+                },
+                Issue::RleSumMismatch {
+                    annotation_id: 4,
+                    sum: 60,
+                    nb_pixels: 100
+                },
+                Issue::InvalidSegmentation {
+                    annotation_id: 5,
+                    error: "The COCO RLE counts are truncated, the count starting at position 1 is not terminated.".to_string()
+                },
+                Issue::SizeMismatch {
+                    annotation_id: 6,
+                    segmentation_size: vec![5, 20],
+                    image_size: vec![10, 10]
+                },
+                Issue::OddPolygonLength {
+                    annotation_id: 7,
+                    polygon_idx: 0,
+                    nb_coordinates: 5
+                },
+                Issue::TooFewPolygonPoints {
+                    annotation_id: 7,
+                    polygon_idx: 1,
+                    nb_points: 2
+                },
+                Issue::MissingCategory {
+                    annotation_id: 8,
+                    category_id: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn find_duplicate_ids_reports_each_id_once() {
+        let img = Image {
+            id: 1,
+            width: 10,
+            height: 10,
+            file_name: "1.jpg".to_string(),
+            ..Default::default()
+        };
+        let dataset = Dataset {
+            images: vec![img.clone(), img.clone(), img, Image::default()],
+            ..Default::default()
+        };
+        let issues = find_duplicate_ids(&dataset);
+        assert_eq!(issues, vec![Issue::DuplicateImageId { image_id: 1 }]);
+        assert_eq!(
+            serde_json::to_string(&issues).unwrap(),
+            r#"[{"issue":"duplicate_image_id","image_id":1}]"#
+        );
+    }
+
+    #[test]
+    fn dangling_polygons_are_reported() {
+        let dataset = Dataset {
+            annotations: vec![Annotation {
+                image_id: 2,
+                ..ann(
+                    1,
+                    Segmentation::Polygons(vec![vec![1.0, 1.0, 5.0, 1.0, 5.0, 5.0]]),
+                    8.0,
+                    bbox(1.0, 1.0, 4.0, 4.0),
+                )
+            }],
+            categories: vec![Category {
+                id: 1,
+                name: "object".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(BTreemapDataset::from_dataset(dataset.clone(), "").is_err());
+        let mut dataset = BTreemapDataset::from_dataset_unchecked(dataset, "");
+        assert_eq!(
+            validate(&dataset, ValidationOption::default()),
+            vec![Issue::MissingImage {
+                annotation_id: 1,
+                image_id: 2
+            }]
+        );
+        assert_eq!(
+            fix(&mut dataset, ValidationOption::default()),
+            vec![Change::RemovedAnnotation {
+                annotation_id: 1,
+                reason: "the image 2 does not exist".to_string()
+            }]
+        );
+        assert!(dataset.get_ann(1).is_err());
+    }
+
+    #[test]
+    fn fix_repairs_and_removes_anns() {
+        let square = Segmentation::PolygonsRS(PolygonsRS {
+            size: vec![10, 10],
+            counts: vec![vec![1.0, 1.0, 5.0, 1.0, 5.0, 5.0, 1.0, 5.0]],
+        });
+        // The segmentations with an odd number of coordinates are invalid, only their bbox can be fixed.
+        let invalid_segmentation = Segmentation::PolygonsRS(PolygonsRS {
+            size: vec![10, 10],
+            counts: vec![vec![1.0, 1.0, 5.0]],
+        });
+        let dataset = Dataset {
+            images: vec![Image {
+                id: 1,
+                width: 10,
+                height: 10,
+                file_name: "1.jpg".to_string(),
+                ..Default::default()
+            }],
+            annotations: vec![
+                ann(1, square.clone(), 30.0, bbox(1.0, 1.0, 4.0, 4.0)),
+                ann(2, square.clone(), 25.0, bbox(3.0, 2.0, 4.0, 4.0)),
+                ann(
+                    3,
+                    invalid_segmentation.clone(),
+                    16.0,
+                    bbox(8.0, -2.0, 4.0, 4.0),
+                ),
+                ann(4, invalid_segmentation, 16.0, bbox(12.0, 1.0, 4.0, 4.0)),
+                ann(
+                    5,
+                    Segmentation::Rle(Rle {
+                        size: vec![10, 10],
+                        counts: vec![100],
+                    }),
+                    0.0,
+                    bbox(1.0, 1.0, 4.0, 4.0),
+                ),
+                Annotation {
+                    category_id: 2,
+                    ..ann(6, square, 25.0, bbox(1.0, 1.0, 4.0, 4.0))
+                },
+                // The polygon is outside of the image, its mask is empty even if its geometric area is not.
+                ann(
+                    7,
+                    Segmentation::PolygonsRS(PolygonsRS {
+                        size: vec![10, 10],
+                        counts: vec![vec![12.0, 1.0, 15.0, 1.0, 15.0, 5.0]],
+                    }),
+                    6.0,
+                    bbox(12.0, 1.0, 3.0, 4.0),
+                ),
+            ],
+            categories: vec![Category {
+                id: 1,
+                name: "object".to_string(),
+                supercategory: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+
+        let changes = fix(&mut dataset, ValidationOption::default());
+        assert_eq!(
+            changes,
+            vec![
+                Change::RecomputedArea {
+                    annotation_id: 1,
+                    old_area: 30.0,
+                    new_area: 25.0
+                },
+                Change::RecomputedBbox {
+                    annotation_id: 2,
+                    old_bbox: bbox(3.0, 2.0, 4.0, 4.0),
+                    new_bbox: bbox(1.0, 1.0, 4.0, 4.0)
+                },
+                Change::ClippedBbox {
+                    annotation_id: 3,
+                    old_bbox: bbox(8.0, -2.0, 4.0, 4.0),
+                    new_bbox: bbox(8.0, 0.0, 2.0, 2.0)
+                },
+                Change::ClippedBbox {
+                    annotation_id: 4,
+                    old_bbox: bbox(12.0, 1.0, 4.0, 4.0),
+                    new_bbox: bbox(10.0, 1.0, 0.0, 4.0)
+                },
+                Change::RemovedAnnotation {
+                    annotation_id: 4,
+                    reason: "the bounding box is empty".to_string()
+                },
+                Change::RemovedAnnotation {
+                    annotation_id: 5,
+                    reason: "the mask is empty".to_string()
+                },
+                Change::RemovedAnnotation {
+                    annotation_id: 6,
+                    reason: "the category 2 does not exist".to_string()
+                },
+                Change::RemovedAnnotation {
+                    annotation_id: 7,
+                    reason: "the mask is empty".to_string()
+                },
+            ]
+        );
+        assert_eq!(dataset.get_img_anns(1).unwrap().len(), 3);
+        assert!(dataset.get_ann(4).is_err());
+        assert_eq!(
+            validate(&dataset, ValidationOption::default())
+                .into_iter()
+                .filter(|issue| !matches!(issue, Issue::OddPolygonLength { .. }))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn fix_keeps_correct_multi_column_rle_bboxes() {
+        // A 4x6 rectangle spanning the columns 3 to 6, and a run wrapping from the bottom of the first column to the top of the second.
+        let rectangle = Rle {
+            size: vec![10, 10],
+            counts: vec![32, 6, 4, 6, 4, 6, 4, 6, 32],
+        };
+        let wrapping = Rle {
+            size: vec![10, 10],
+            counts: vec![8, 4, 88],
+        };
+        let crowd = |ann: Annotation| Annotation { iscrowd: 1, ..ann };
+        let dataset = Dataset {
+            images: vec![Image {
+                id: 1,
+                width: 10,
+                height: 10,
+                file_name: "1.jpg".to_string(),
+                ..Default::default()
+            }],
+            annotations: vec![
+                crowd(ann(
+                    1,
+                    Segmentation::CocoRle(CocoRle::try_from(&rectangle).unwrap()),
+                    24.0,
+                    bbox(3.0, 2.0, 4.0, 6.0),
+                )),
+                crowd(ann(
+                    2,
+                    Segmentation::Rle(rectangle),
+                    24.0,
+                    bbox(3.0, 2.0, 4.0, 6.0),
+                )),
+                crowd(ann(
+                    3,
+                    Segmentation::Rle(wrapping),
+                    4.0,
+                    bbox(0.0, 0.0, 2.0, 10.0),
+                )),
+            ],
+            categories: vec![Category {
+                id: 1,
+                name: "object".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+        let expected = dataset.clone();
+
+        assert_eq!(fix(&mut dataset, ValidationOption::default()), vec![]);
+        assert_eq!(dataset, expected);
+        assert_eq!(validate(&dataset, ValidationOption::default()), vec![]);
+    }
+
+    #[test]
+    fn check_image_files_against_folder() {
+        let img = |id, file_name: &str, width, height| Image {
+            id,
+            width,
+            height,
+            file_name: file_name.to_string(),
+            ..Default::default()
+        };
+        let dataset = Dataset {
+            images: vec![
+                img(1, "000000174482.jpg", 640, 388),
+                img(2, "000000480985.jpg", 500, 375),
+                img(3, "missing.jpg", 640, 480),
+            ],
+            ..Default::default()
+        };
+        let dataset =
+            BTreemapDataset::from_dataset(dataset, "../data_samples/coco_25k/images").unwrap();
+
+        let issues = check_image_files(&dataset).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                Issue::ImageFileSizeMismatch {
+                    image_id: 2,
+                    file_name: "000000480985.jpg".to_string(),
+                    width: 500,
+                    height: 375,
+                    file_width: 375,
+                    file_height: 500
+                },
+                Issue::MissingImageFile {
+                    image_id: 3,
+                    file_name: "missing.jpg".to_string()
+                },
+                Issue::OrphanImageFile {
+                    file_name: "000000017627.jpg".to_string()
+                },
+                Issue::OrphanImageFile {
+                    file_name: "000000289393.jpg".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -3,6 +3,9 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::coco::compression::Compression;
+use crate::coco::remap::CategoryRef;
+
 /// Error returned when trying to access an element of the dataset that does not exist.
 #[derive(thiserror::Error)]
 pub enum MissingIdError {
@@ -16,6 +19,34 @@ pub enum MissingIdError {
     // InvalidValue(#[from] anyhow::Error),
 }
 
+/// Error returned when an element cannot be added to (or updated in) the dataset.
+#[derive(thiserror::Error)]
+pub enum MutationError {
+    #[error("An annotation with the id `{0}` is already in the dataset.")]
+    DuplicateAnnotation(u64),
+    #[error("A category with the id `{0}` is already in the dataset.")]
+    DuplicateCategory(u32),
+    #[error("An image with the id `{0}` is already in the dataset.")]
+    DuplicateImage(u64),
+    #[error(transparent)]
+    MissingId(#[from] MissingIdError),
+}
+
+/// Error returned when the categories of a dataset cannot be remapped.
+#[derive(Error)]
+pub enum RemapError {
+    #[error("Failed to read the mapping file {1:?}.")]
+    Read(#[source] std::io::Error, PathBuf),
+    #[error("Failed to deserialize the mapping file {1:?}.")]
+    Deserialize(#[source] serde_json::Error, PathBuf),
+    #[error("The mapping refers to the category {0}, which is not in the dataset.")]
+    UnknownCategory(CategoryRef),
+    #[error("The category with id {0} is mapped more than once.")]
+    DuplicateMapping(u32),
+    #[error("The categories {1:?} and {2:?} would both have the id {0} after remapping.")]
+    ConflictingIds(u32, String, String),
+}
+
 /// Error returned when a json annotations file cannot be loaded/parsed or when an image cannot be loaded.
 #[derive(Error)]
 pub enum LoadingError {
@@ -25,10 +56,84 @@ pub enum LoadingError {
     Deserialize(#[source] serde_json::Error, PathBuf),
     #[error("Failed to parse the annotation file/dataset. Found an annotation for an image id not in the dataset.")]
     Parsing(#[source] MissingIdError),
+    #[error("The annotation file {1:?} is compressed with {0}, which requires the `{0}` feature of cocotools.")]
+    UnsupportedCompression(Compression, PathBuf),
+    #[error("Failed to read the image folder {1:?}.")]
+    ReadImageFolder(#[source] std::io::Error, PathBuf),
     #[error(transparent)]
     Image(#[from] anyhow::Error),
 }
 
+/// Error returned when a binary cache file cannot be read or written.
+#[cfg(feature = "cache")]
+#[derive(Error)]
+pub enum CacheError {
+    #[error("Failed to read the cache file {1:?}.")]
+    Read(#[source] std::io::Error, PathBuf),
+    #[error("Failed to write the cache file {1:?}.")]
+    Write(#[source] std::io::Error, PathBuf),
+    #[error("The file {0:?} is not a cocotools cache file.")]
+    NotACache(PathBuf),
+    #[error("The cache file {0:?} has the format version {1}, but version {2} is expected.")]
+    Version(PathBuf, u32, u32),
+    #[error("Failed to decode the cache file {1:?}.")]
+    Decode(#[source] rmp_serde::decode::Error, PathBuf),
+    #[error("Failed to encode the cache file {1:?}.")]
+    Encode(#[source] rmp_serde::encode::Error, PathBuf),
+    #[error(transparent)]
+    Loading(#[from] LoadingError),
+}
+
+/// Error returned when a dataset cannot be converted to or from Arrow tables, or saved to or loaded from Parquet files.
+#[cfg(feature = "parquet")]
+#[derive(Error)]
+pub enum TableError {
+    #[error("Failed to read the file {1:?}.")]
+    Read(#[source] std::io::Error, PathBuf),
+    #[error("Failed to write the file {1:?}.")]
+    Write(#[source] std::io::Error, PathBuf),
+    #[error("Failed to read or write the Parquet file {1:?}.")]
+    Parquet(#[source] parquet::errors::ParquetError, PathBuf),
+    #[error("Failed to build the Arrow table.")]
+    Arrow(#[from] arrow::error::ArrowError),
+    #[error("The {0} table has no {1:?} column.")]
+    Column(&'static str, &'static str),
+    #[error("The {2:?} column of the {1} table cannot be cast to the expected type.")]
+    Cast(
+        #[source] arrow::error::ArrowError,
+        &'static str,
+        &'static str,
+    ),
+    #[error("The {1:?} column of the {0} table is required, but it has null values.")]
+    Null(&'static str, &'static str),
+    #[error("Invalid category frequency {0:?}, expected \"r\", \"c\" or \"f\".")]
+    Frequency(String),
+    #[error("Failed to convert the unknown fields from/to json.")]
+    ExtraFields(#[source] serde_json::Error),
+    #[error("Failed to convert a segmentation to COCO RLE.")]
+    Mask(#[from] MaskError),
+    #[error(transparent)]
+    Loading(#[from] LoadingError),
+}
+
+/// Error returned when a dataset stored in a SQLite database cannot be read or edited.
+#[cfg(feature = "sqlite")]
+#[derive(Error)]
+pub enum SqliteError {
+    #[error("Failed to query the SQLite database.")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Failed to convert an element of the dataset from/to json.")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    MissingId(#[from] MissingIdError),
+    #[error(transparent)]
+    Mutation(#[from] MutationError),
+    #[error(transparent)]
+    Mask(#[from] MaskError),
+    #[error(transparent)]
+    Loading(#[from] LoadingError),
+}
+
 /// Error returned converting a segmentation mask to another format fails.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
@@ -39,6 +144,22 @@ pub enum MaskError {
     StrConversion(#[source] std::str::Utf8Error, Vec<u8>),
     #[error("Failed to convert an image mask to an ndarray version of it.")]
     ImageToNDArrayConversion(#[source] ndarray::ShapeError),
+    #[error("Polygons must have an even number of coordinates, but found a polygon with {0} coordinates.")]
+    OddPolygonLength(usize),
+    #[error("Invalid character {0:?} at position {1} of the COCO RLE counts, only the ascii characters '0' to 'o' (48-111) are allowed.")]
+    InvalidCocoRleChar(char, usize),
+    #[error(
+        "The COCO RLE counts are truncated, the count starting at position {0} is not terminated."
+    )]
+    TruncatedCocoRle(usize),
+    #[error("The count starting at position {0} of the COCO RLE counts is encoded on too many characters to fit in a u32.")]
+    CocoRleCountOverflow(usize),
+    #[error("The count starting at position {0} of the COCO RLE counts decodes to {1}, which is not a valid number of pixels.")]
+    InvalidCocoRleCount(usize, i64),
+    #[error("Polygons without the size of their image cannot be encoded as a mask.")]
+    MissingPolygonSize,
+    #[error("Cannot compare masks of different sizes, got {0:?} and {1:?}.")]
+    SizeMismatch(Vec<u32>, Vec<u32>),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -52,6 +173,8 @@ pub enum CocoError {
     Loading(#[from] LoadingError),
     #[error(transparent)]
     Mask(#[from] MaskError),
+    #[error(transparent)]
+    Mutation(#[from] MutationError),
 }
 
 // From https://www.lpalmieri.com/posts/error-handling-rust/
@@ -61,11 +184,39 @@ impl std::fmt::Debug for MissingIdError {
         error_chain_fmt(self, f)
     }
 }
+impl std::fmt::Debug for MutationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+impl std::fmt::Debug for RemapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
 impl std::fmt::Debug for LoadingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
     }
 }
+#[cfg(feature = "cache")]
+impl std::fmt::Debug for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+#[cfg(feature = "parquet")]
+impl std::fmt::Debug for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+#[cfg(feature = "sqlite")]
+impl std::fmt::Debug for SqliteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
 
 fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -1,21 +1,30 @@
 use std::error;
+use std::fs;
 use std::path::PathBuf;
 
 use clap::Parser;
-use visualize::draw::DrawOption;
+use cocotools::coco::filter::Filter;
+use cocotools::coco::object_detection;
+use cocotools::coco::remap::CategoryRemapping;
+use cocotools::coco::stats::DatasetStats;
+use cocotools::coco::streaming::SaveOption;
+use cocotools::coco::validation::{self, ValidationOption};
+use cocotools::mask;
+use cocotools::mask::conversions::PolygonOption;
+use cocotools::visualize::display;
+use cocotools::visualize::draw::DrawOption;
+use cocotools::COCO;
 
 mod argparse;
-mod coco;
-mod errors;
-mod mask;
-mod utils;
-mod visualize;
 use crate::argparse::{Cli, Commands};
-use crate::coco::COCO;
-use crate::visualize::display;
 
 fn main() -> Result<(), Box<dyn error::Error>> {
     let cli = Cli::parse();
+    let save_option = SaveOption::new()
+        .pretty(!cli.save.compact)
+        .indent(cli.save.indent)
+        .float_precision(cli.save.float_precision)
+        .canonical(cli.save.canonical);
 
     match &cli.command {
         Commands::Visualize {
@@ -39,13 +48,159 @@ fn main() -> Result<(), Box<dyn error::Error>> {
             annotations_path,
             target_segmentation,
             output_path,
+            tolerance,
+            max_vertices,
+            smoothing,
         } => {
             let mut dataset = COCO::new(annotations_path, &PathBuf::from("N/A"))?;
-            mask::conversions::convert_coco_segmentation(&mut dataset, *target_segmentation)?;
-            let output_path = output_path
-                .as_ref()
-                .map_or_else(|| annotations_path, |output_path| output_path);
-            dataset.save_to(output_path)?;
+            let polygon_option = PolygonOption::default()
+                .tolerance(*tolerance)
+                .max_vertices(*max_vertices)
+                .smoothing(*smoothing);
+            mask::conversions::convert_coco_segmentation_with_option(
+                &mut dataset,
+                *target_segmentation,
+                polygon_option,
+            )?;
+            let output_path = output_path.as_ref().unwrap_or(annotations_path);
+            dataset.save_to_with_option(output_path, save_option)?;
+        }
+        Commands::Validate {
+            annotations_path,
+            image_folder,
+            json,
+            area_tolerance,
+        } => {
+            let dataset = object_detection::load_json(annotations_path)?;
+            let mut issues = validation::find_duplicate_ids(&dataset);
+            let dataset = COCO::from_dataset_unchecked(
+                dataset,
+                image_folder.clone().unwrap_or_else(|| PathBuf::from("N/A")),
+            );
+            issues.extend(validation::validate(
+                &dataset,
+                ValidationOption::default().area_tolerance(*area_tolerance),
+            ));
+            if image_folder.is_some() {
+                issues.extend(validation::check_image_files(&dataset)?);
+            }
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&issues)?);
+            } else {
+                for issue in &issues {
+                    println!("{issue}");
+                }
+                println!("Found {} problem(s).", issues.len());
+            }
+            if !issues.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Stats {
+            annotations_path,
+            json,
+        } => {
+            let dataset = COCO::new(annotations_path, &PathBuf::from("N/A"))?;
+            let stats = DatasetStats::from(&dataset);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{stats}");
+            }
+        }
+        Commands::Filter {
+            annotations_path,
+            output_path,
+            cat_ids,
+            cat_names,
+            min_area,
+            max_area,
+            iscrowd,
+            min_bbox_size,
+            max_bbox_size,
+            img_ids,
+            file_name_glob,
+            min_anns,
+            max_anns,
+            drop_empty_images,
+        } => {
+            let dataset = COCO::new(annotations_path, &PathBuf::from("N/A"))?;
+            let mut filter = Filter::new()
+                .min_area(*min_area)
+                .max_area(*max_area)
+                .iscrowd(*iscrowd)
+                .min_bbox_size(min_bbox_size.as_ref().map(|size| (size[0], size[1])))
+                .max_bbox_size(max_bbox_size.as_ref().map(|size| (size[0], size[1])))
+                .file_name_glob(file_name_glob.clone())
+                .min_anns_per_image(*min_anns)
+                .max_anns_per_image(*max_anns)
+                .keep_empty_images(!drop_empty_images);
+            if let Some(cat_ids) = cat_ids {
+                filter = filter.cat_ids(cat_ids.iter().copied());
+            }
+            if let Some(cat_names) = cat_names {
+                filter = filter.cat_names(cat_names.iter().cloned());
+            }
+            if let Some(img_ids) = img_ids {
+                filter = filter.img_ids(img_ids.iter().copied());
+            }
+            let filtered_dataset = dataset.filter(&filter);
+            println!(
+                "Kept {} images and {} annotations.",
+                filtered_dataset.get_imgs().len(),
+                filtered_dataset.get_anns().len()
+            );
+            filtered_dataset.save_to_with_option(output_path, save_option)?;
+        }
+        Commands::RemapCategories {
+            annotations_path,
+            mapping_path,
+            output_path,
+            drop_unmapped,
+            renumber,
+        } => {
+            let mut dataset = COCO::new(annotations_path, &PathBuf::from("N/A"))?;
+            let mut remapping = CategoryRemapping::from_file(mapping_path)?;
+            remapping.drop_unmapped |= *drop_unmapped;
+            remapping.renumber |= *renumber;
+            let old_cats: Vec<(u32, String)> = dataset
+                .get_cats()
+                .into_iter()
+                .map(|cat| (cat.id, cat.name.clone()))
+                .collect();
+            let id_map = dataset.remap_categories(&remapping)?;
+            for (old_id, old_name) in old_cats {
+                match id_map.get(&old_id).copied().flatten() {
+                    Some(new_id) => println!(
+                        "{old_id} {old_name} -> {new_id} {}",
+                        dataset.get_cat(new_id)?.name
+                    ),
+                    None => println!("{old_id} {old_name} -> dropped"),
+                }
+            }
+            dataset.save_to_with_option(output_path, save_option)?;
+        }
+        Commands::Fix {
+            annotations_path,
+            output_path,
+            change_log,
+            area_tolerance,
+        } => {
+            let dataset = object_detection::load_json(annotations_path)?;
+            let mut dataset = COCO::from_dataset_unchecked(dataset, PathBuf::from("N/A"));
+            let changes = validation::fix(
+                &mut dataset,
+                ValidationOption::default().area_tolerance(*area_tolerance),
+            );
+            for change in &changes {
+                println!("{change}");
+            }
+            println!("Made {} change(s).", changes.len());
+            if let Some(change_log) = change_log {
+                fs::write(change_log, serde_json::to_string_pretty(&changes)?)?;
+            }
+            let output_path = output_path.as_ref().unwrap_or(annotations_path);
+            dataset.save_to_with_option(output_path, save_option)?;
         }
     }
     Ok(())
@@ -1,12 +1,18 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
 use clap::ValueEnum;
 use image;
 use imageproc::contours;
 use imageproc::drawing;
+use imageproc::point::Point;
 use ndarray::{s, ArrayViewMut, ShapeBuilder};
 
 use super::Mask;
+use crate::coco::backend::DatasetMut;
 use crate::coco::object_detection;
 use crate::errors::MaskError;
+use crate::utils::try_par_map;
 
 /// Segmentation types.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -18,39 +24,90 @@ pub enum Segmentation {
 
 /// Converts all the segmentation masks in the dataset to the desired type.
 ///
+/// The masks are converted to polygons with the default [`PolygonOption`], see [`convert_coco_segmentation_with_option`]
+/// to simplify or smooth them.
+///
+/// # Errors
+///
+/// Will return `Err` if the conversion failed or if the dataset cannot be read or updated, the dataset is then left unchanged.
+pub fn convert_coco_segmentation<D: DatasetMut>(
+    dataset: &mut D,
+    target_segmentation: Segmentation,
+) -> Result<(), D::Error>
+where
+    D::Error: From<MaskError>,
+{
+    convert_coco_segmentation_with_option(dataset, target_segmentation, PolygonOption::default())
+}
+
+/// Same as [`convert_coco_segmentation`], with the options used when converting to [`Segmentation::Polygons`].
+///
 /// # Errors
 ///
-/// Will return `Err` if the conversion failed.
-pub fn convert_coco_segmentation(
-    dataset: &mut object_detection::BTreemapDataset,
+/// Will return `Err` if the conversion failed or if the dataset cannot be read or updated, the dataset is then left unchanged.
+pub fn convert_coco_segmentation_with_option<D: DatasetMut>(
+    dataset: &mut D,
+    target_segmentation: Segmentation,
+    polygon_option: PolygonOption,
+) -> Result<(), D::Error>
+where
+    D::Error: From<MaskError>,
+{
+    let anns = dataset.get_anns()?;
+    let segmentations = try_par_map(anns.iter().collect(), |ann| {
+        convert_segmentation(&ann.segmentation, target_segmentation, polygon_option)
+            .map(|segmentation| (ann.id, segmentation))
+    })?;
+    drop(anns);
+    dataset.update_segmentations(segmentations)
+}
+
+/// Encode the segmentations as COCO RLE, in parallel when the `rayon` feature is enabled.
+///
+/// ## Errors
+///
+/// Will return `Err` if one of the segmentations cannot be encoded, polygons without the size of their image
+/// cannot be.
+pub fn encode_coco_rles(
+    segmentations: Vec<&object_detection::Segmentation>,
+) -> Result<Vec<object_detection::CocoRle>, MaskError> {
+    use object_detection::Segmentation::{CocoRle, Polygons, PolygonsRS, Rle};
+    try_par_map(segmentations, |segmentation| match segmentation {
+        CocoRle(coco_rle) => Ok(coco_rle.clone()),
+        Rle(rle) => object_detection::CocoRle::try_from(rle),
+        PolygonsRS(poly) => object_detection::CocoRle::try_from(poly),
+        Polygons(_) => Err(MaskError::MissingPolygonSize),
+    })
+}
+
+pub(crate) fn convert_segmentation(
+    segmentation: &object_detection::Segmentation,
     target_segmentation: Segmentation,
-) -> Result<(), MaskError> {
+    polygon_option: PolygonOption,
+) -> Result<object_detection::Segmentation, MaskError> {
     use object_detection::Segmentation::{CocoRle, Polygons, PolygonsRS, Rle};
     use Segmentation as S;
-    for ann in dataset.anns.values_mut() {
-        let converted_segmentation = match &ann.segmentation {
-            Rle(rle) => match target_segmentation {
-                S::Rle => Rle(rle.clone()),
-                S::CocoRle => CocoRle(object_detection::CocoRle::try_from(rle)?),
-                S::Polygons => Polygons(object_detection::Polygons::from(rle)),
-            },
-            CocoRle(coco_rle) => match target_segmentation {
-                S::Rle => Rle(object_detection::Rle::from(coco_rle)),
-                S::CocoRle => CocoRle(coco_rle.clone()),
-                S::Polygons => Polygons(object_detection::Polygons::from(
-                    &object_detection::Rle::from(coco_rle),
-                )),
-            },
-            PolygonsRS(poly) => match target_segmentation {
-                S::Rle => Rle(object_detection::Rle::try_from(poly)?),
-                S::CocoRle => CocoRle(object_detection::CocoRle::try_from(poly)?),
-                S::Polygons => Polygons(poly.counts.clone()),
-            },
-            Polygons(_) => unimplemented!(),
-        };
-        ann.segmentation = converted_segmentation;
-    }
-    Ok(())
+    Ok(match segmentation {
+        Rle(rle) => match target_segmentation {
+            S::Rle => Rle(rle.clone()),
+            S::CocoRle => CocoRle(object_detection::CocoRle::try_from(rle)?),
+            S::Polygons => Polygons(poly_from_mask(&Mask::from(rle), polygon_option)),
+        },
+        CocoRle(coco_rle) => match target_segmentation {
+            S::Rle => Rle(object_detection::Rle::try_from(coco_rle)?),
+            S::CocoRle => CocoRle(coco_rle.clone()),
+            S::Polygons => Polygons(poly_from_mask(
+                &Mask::from(&object_detection::Rle::try_from(coco_rle)?),
+                polygon_option,
+            )),
+        },
+        PolygonsRS(poly) => match target_segmentation {
+            S::Rle => Rle(object_detection::Rle::try_from(poly)?),
+            S::CocoRle => CocoRle(object_detection::CocoRle::try_from(poly)?),
+            S::Polygons => Polygons(poly.counts.clone()),
+        },
+        Polygons(_) => unimplemented!(),
+    })
 }
 
 impl TryFrom<&object_detection::PolygonsRS> for object_detection::Rle {
@@ -95,74 +152,120 @@ impl From<&Mask> for object_detection::Rle {
 }
 
 /// Decode COCO RLE segmentation information into RLE.
-
+///
 /// See the (hard to read) implementation:
 /// <https://github.com/cocodataset/cocoapi/blob/master/common/maskApi.c#L218>
 /// <https://github.com/cocodataset/cocoapi/blob/8c9bcc3cf640524c4c20a9c40e89cb6a2f2fa0e9/PythonAPI/pycocotools/_mask.pyx#L145>
-
+///
 /// [LEB128 wikipedia article](https://en.wikipedia.org/wiki/LEB128#Decode_signed_integer)
 /// It is similar to LEB128, but here shift is incremented by 5 instead of 7 because the implementation uses
 /// 6 bits per byte instead of 8. (no idea why, I guess it's more efficient for the COCO dataset?)
-#[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
-impl From<&object_detection::CocoRle> for object_detection::Rle {
+impl TryFrom<&object_detection::CocoRle> for object_detection::Rle {
+    type Error = MaskError;
+
     /// Converts a compressed RLE to its uncompressed version.
-    fn from(coco_rle: &object_detection::CocoRle) -> Self {
-        assert!(
-            coco_rle.counts.is_ascii(),
-            "COCO RLE is not in valid ascii."
-        );
+    fn try_from(coco_rle: &object_detection::CocoRle) -> Result<Self, Self::Error> {
+        Ok(Self {
+            size: coco_rle.size.clone(),
+            counts: CocoRleDecoder::new(coco_rle).collect::<Result<_, _>>()?,
+        })
+    }
+}
 
-        let bytes_rle = coco_rle.counts.as_bytes();
-
-        let mut current_count_idx: usize = 0;
-        let mut current_byte_idx: usize = 0;
-        let mut counts: Vec<u32> = vec![0; coco_rle.counts.len()];
-        while current_byte_idx < bytes_rle.len() {
-            let mut continuous_pixels: i32 = 0;
-            let mut shift = 0;
-            let mut high_order_bit = 1;
-
-            // When the high order bit of a byte becomes 0, we have decoded the integer and can move on to the next one.
-            while high_order_bit != 0 {
-                let byte = bytes_rle[current_byte_idx] - 48; // The encoding uses the ascii chars 48-111.
-
-                // 0x1f is 31, i.e. 001111 --> Here we select the first four bits of the byte.
-                continuous_pixels |= (i32::from(byte) & 31) << shift;
-                // 0x20 is 32 as int, i.e. 2**5, i.e 010000 --> Here we select the fifth bit of the byte.
-                high_order_bit = byte & 32;
-                current_byte_idx += 1;
-                shift += 5;
-                // 0x10 is 16 as int, i.e. 1000
-                if high_order_bit == 0 && (byte & 16 != 0) {
-                    continuous_pixels |= !0 << shift;
-                }
-            }
+/// Iterator decoding the counts of a compressed RLE one at a time.
+///
+/// Each count is encoded on one or more characters using 6 bits per character: 5 bits of value and a continuation bit.\
+/// Apart from the first 3 counts, each count is encoded as the difference with the count preceding the previous one.
+/// My hypothesis as to why, is that most objects are going to be somewhat "vertically convex" (i.e. have only one continuous run per column).
+/// In which case, the next column of background/foreground pixels is going to be similar to the one preceding it,
+/// which makes the difference a smaller int that uses less characters to encode.
+///
+/// After an error, the iterator does not yield anything anymore.
+pub struct CocoRleDecoder<'a> {
+    counts: &'a str,
+    byte_idx: usize,
+    count_idx: usize,
+    /// The two last decoded counts, the one before the previous one is at index `count_idx % 2`.
+    previous_counts: [u32; 2],
+}
 
-            if current_count_idx > 2 {
-                // My hypothesis as to what is happening here, is that most objects are going to be somewhat
-                // "vertically convex" (i.e. have only one continuous run per line).
-                // In which case, the next "row" of black/white pixels is going to be similar to the one preceding it.
-                // Therefore, by having the continuous count of pixels be an offset of the one preceding it, we can have it be
-                // a smaller int and therefore use less bits to encode it.
-                continuous_pixels += counts[current_count_idx - 2] as i32;
-            }
-            counts[current_count_idx] = continuous_pixels as u32;
-            current_count_idx += 1;
+impl<'a> CocoRleDecoder<'a> {
+    #[must_use]
+    pub fn new(coco_rle: &'a object_detection::CocoRle) -> Self {
+        Self {
+            counts: &coco_rle.counts,
+            byte_idx: 0,
+            count_idx: 0,
+            previous_counts: [0; 2],
         }
+    }
+
+    fn decode_count(&mut self) -> Result<u32, MaskError> {
+        let count_start = self.byte_idx;
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = match self.counts.as_bytes().get(self.byte_idx) {
+                Some(&byte) => byte,
+                None => return Err(MaskError::TruncatedCocoRle(count_start)),
+            };
+            // The encoding uses the ascii chars 48-111.
+            if !(48..=111).contains(&byte) {
+                // All the previous characters are ascii, so byte_idx is on a char boundary.
+                let invalid_char = self.counts[self.byte_idx..]
+                    .chars()
+                    .next()
+                    .unwrap_or_default();
+                return Err(MaskError::InvalidCocoRleChar(invalid_char, self.byte_idx));
+            }
+            if shift >= 35 {
+                // Counts fit in a u32, their differences can therefore always be encoded on 7 characters (35 bits).
+                return Err(MaskError::CocoRleCountOverflow(count_start));
+            }
+            let byte = byte - 48;
+            self.byte_idx += 1;
 
-        // Added the while loop to make it work, but it should not be there. Something is wrong somewhere else.
-        while let Some(last) = counts.last() {
-            if *last == 0 {
-                counts.pop();
-            } else {
+            // 0x1f is 31, i.e. 011111 --> Here we select the five value bits of the byte.
+            value |= (i64::from(byte) & 0x1f) << shift;
+            shift += 5;
+            // 0x20 is 32, i.e. 100000 --> Here we select the continuation bit of the byte.
+            if byte & 0x20 == 0 {
+                // 0x10 is 16, i.e. 010000 --> The last value bit is the sign bit.
+                if byte & 0x10 != 0 {
+                    value |= !0 << shift;
+                }
                 break;
             }
         }
 
-        Self {
-            size: coco_rle.size.clone(),
-            counts,
+        if self.count_idx > 2 {
+            value += i64::from(self.previous_counts[self.count_idx % 2]);
+        }
+        let count =
+            u32::try_from(value).map_err(|_| MaskError::InvalidCocoRleCount(count_start, value))?;
+        self.previous_counts[self.count_idx % 2] = count;
+        self.count_idx += 1;
+        Ok(count)
+    }
+}
+
+impl Iterator for CocoRleDecoder<'_> {
+    type Item = Result<u32, MaskError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.byte_idx >= self.counts.len() {
+            return None;
+        }
+        let count = self.decode_count();
+        if count.is_err() {
+            self.byte_idx = self.counts.len();
         }
+        Some(count)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining_bytes = self.counts.len() - self.byte_idx;
+        ((remaining_bytes + 6) / 7, Some(remaining_bytes))
     }
 }
 
@@ -221,61 +324,9 @@ impl TryFrom<&Mask> for object_detection::CocoRle {
     }
 }
 
-#[allow(clippy::expect_used)]
 impl From<&object_detection::Rle> for object_detection::Polygons {
     fn from(rle: &object_detection::Rle) -> Self {
-        let mask = Mask::from(rle);
-        let mask_img = mask
-            .as_slice_memory_order()
-            .map(|slice| {
-                image::GrayImage::from_raw(rle.size[1], rle.size[0], slice.to_owned()).expect(
-                    "Buffer already contains a mask created using the rle sizes and is threfore big enough."
-                )
-            })
-            .expect("The mask is created just above and should therefore be continuous in memory.");
-
-        let contours = contours::find_contours::<u32>(&mask_img);
-
-        // find_contours returns all the points defining the contours, the following for loop removes all the points formings lines as they are not needed.
-        let mut counts: Self = Self::new();
-        let mut prev_prev_x: u32;
-        let mut prev_prev_y: u32;
-        let mut prev_x: u32;
-        let mut prev_y: u32;
-        for (i, contour) in contours.iter().enumerate() {
-            // Valid polygons must have at least 3 points.
-            // The case of having less than 3 points is not expected to occur on real data, hence the silent failt if it occurs.
-            if contour.points.len() > 3 {
-                counts.push(Vec::with_capacity(2 * contour.points.len()));
-
-                counts[i].push(f64::from(contour.points[0].y));
-                counts[i].push(f64::from(contour.points[0].x));
-                prev_prev_x = contour.points[0].x;
-                prev_prev_y = contour.points[0].y;
-                prev_x = contour.points[1].x;
-                prev_y = contour.points[1].y;
-                for point in &contour.points {
-                    if !((prev_prev_x == prev_x && prev_x == point.x)
-                        || (prev_prev_y == prev_y && prev_y == point.y))
-                    {
-                        counts[i].push(f64::from(prev_y));
-                        counts[i].push(f64::from(prev_x));
-                    }
-                    prev_prev_x = prev_x;
-                    prev_prev_y = prev_y;
-                    prev_x = point.x;
-                    prev_y = point.y;
-                }
-
-                if !((prev_prev_x == prev_x && prev_x == contour.points[0].x)
-                    || (prev_prev_y == prev_y && prev_y == contour.points[0].y))
-                {
-                    counts[i].push(f64::from(prev_y));
-                    counts[i].push(f64::from(prev_x));
-                }
-            }
-        }
-        counts
+        poly_from_mask(&Mask::from(rle), PolygonOption::default())
     }
 }
 
@@ -284,7 +335,7 @@ impl From<&Mask> for object_detection::PolygonsRS {
     fn from(mask: &Mask) -> Self {
         Self {
             size: vec![mask.shape()[0] as u32, mask.shape()[1] as u32],
-            counts: object_detection::Polygons::from(&object_detection::Rle::from(mask)),
+            counts: poly_from_mask(mask, PolygonOption::default()),
         }
     }
 }
@@ -324,7 +375,7 @@ impl TryFrom<&object_detection::Segmentation> for Mask {
         let mask = match coco_segmentation {
             object_detection::Segmentation::Rle(rle) => Self::from(rle),
             object_detection::Segmentation::CocoRle(coco_rle) => {
-                Self::from(&object_detection::Rle::from(coco_rle))
+                Self::from(&object_detection::Rle::try_from(coco_rle)?)
             }
             object_detection::Segmentation::PolygonsRS(poly) => Self::try_from(poly)?,
             object_detection::Segmentation::Polygons(_) => {
@@ -335,39 +386,12 @@ impl TryFrom<&object_detection::Segmentation> for Mask {
     }
 }
 
-#[allow(clippy::cast_possible_truncation)]
 impl TryFrom<&object_detection::PolygonsRS> for Mask {
     type Error = MaskError;
 
     /// Create a mask from a compressed polygon representation.
     fn try_from(poly_ann: &object_detection::PolygonsRS) -> Result<Self, Self::Error> {
-        let mut mask = image::GrayImage::new(poly_ann.size[1], poly_ann.size[0]);
-
-        for poly in &poly_ann.counts {
-            let mut points_poly: Vec<imageproc::point::Point<i32>> = Vec::new();
-            for i in (0..poly.len()).step_by(2) {
-                points_poly.push(imageproc::point::Point::new(
-                    poly[i] as i32,
-                    poly[i + 1] as i32,
-                ));
-            }
-
-            while let Some(last_point) = points_poly.last() {
-                if points_poly[0].x == last_point.x && points_poly[0].y == last_point.y {
-                    points_poly.pop();
-                } else {
-                    break;
-                }
-            }
-
-            drawing::draw_polygon_mut(&mut mask, &points_poly, image::Luma([1u8]));
-        }
-
-        Self::from_shape_vec(
-            (poly_ann.size[1] as usize, poly_ann.size[0] as usize),
-            mask.into_raw(),
-        )
-        .map_err(MaskError::ImageToNDArrayConversion)
+        rasterize_polygons(&poly_ann.counts, poly_ann.size[1], poly_ann.size[0])
     }
 }
 
@@ -379,35 +403,415 @@ impl TryFrom<&object_detection::PolygonsRS> for Mask {
 /// - height: The original height of the image the polygon annotation corresponds to.
 ///
 /// ## Errors
-/// Will return `Err` if the internal conversion from `ImageBuffer` to Mask (ndarray) fails.
+/// Will return `Err` if one of the polygons has an odd number of coordinates.
 ///
 /// ## Returns:
 /// - The decompressed mask.
-#[allow(clippy::cast_possible_truncation, clippy::module_name_repetitions)]
+#[allow(clippy::module_name_repetitions)]
 pub fn mask_from_poly(
     poly: &object_detection::Polygons,
     width: u32,
     height: u32,
 ) -> Result<Mask, MaskError> {
-    // FIXME TODO: handle more than one poly.
-    let mut points_poly: Vec<imageproc::point::Point<i32>> = Vec::new();
-    for i in (0..poly[0].len()).step_by(2) {
-        points_poly.push(imageproc::point::Point::new(
-            poly[0][i] as i32,
-            poly[0][i + 1] as i32,
-        ));
-    }
+    rasterize_polygons(poly, width, height)
+}
+
+/// Draw the polygons on a mask of the given size.
+///
+/// The coordinates are truncated to integers, and each polygon is filled independently (with imageproc's scanline fill).
+/// The holes of an object must therefore be part of the polygon enclosing them (see [`poly_from_mask`]).
+#[allow(clippy::cast_possible_truncation)]
+fn rasterize_polygons(polygons: &[Vec<f64>], width: u32, height: u32) -> Result<Mask, MaskError> {
     let mut mask = image::GrayImage::new(width, height);
-    drawing::draw_polygon_mut(&mut mask, &points_poly, image::Luma([1u8]));
+
+    for poly in polygons {
+        if poly.len() % 2 != 0 {
+            return Err(MaskError::OddPolygonLength(poly.len()));
+        }
+        let mut points_poly: Vec<Point<i32>> = poly
+            .chunks_exact(2)
+            .map(|point| Point::new(point[0] as i32, point[1] as i32))
+            .collect();
+
+        // imageproc expects open polygons.
+        while points_poly.len() > 1 && points_poly.first() == points_poly.last() {
+            points_poly.pop();
+        }
+
+        match points_poly.as_slice() {
+            [] => {}
+            // Degenerate polygons, like the ones of objects only one pixel wide, are drawn as lines.
+            [start] | [start, _] => {
+                let end = &points_poly[points_poly.len() - 1];
+                drawing::draw_line_segment_mut(
+                    &mut mask,
+                    (start.x as f32, start.y as f32),
+                    (end.x as f32, end.y as f32),
+                    image::Luma([1u8]),
+                );
+            }
+            _ => drawing::draw_polygon_mut(&mut mask, &points_poly, image::Luma([1u8])),
+        }
+    }
 
     Mask::from_shape_vec((height as usize, width as usize), mask.into_raw())
         .map_err(MaskError::ImageToNDArrayConversion)
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::module_name_repetitions)]
+/// Options used when converting a mask to polygons.
+///
+/// By default the polygons follow the pixel contours exactly, only the points lying in the middle of straight lines are removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PolygonOption {
+    /// Tolerance (in pixels) used by the Douglas–Peucker simplification. A value of 0 disables the simplification.
+    pub tolerance: f64,
+    /// Maximum number of vertices each polygon can have.
+    pub max_vertices: Option<usize>,
+    /// Number of iterations of (Chaikin) corner cutting applied to smooth the polygons. The resulting vertices have sub-pixel coordinates.
+    pub smoothing: u32,
+}
+
+impl PolygonOption {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn tolerance(mut self, x: f64) -> Self {
+        self.tolerance = x;
+        self
+    }
+
+    #[must_use]
+    pub const fn max_vertices(mut self, x: Option<usize>) -> Self {
+        self.max_vertices = x;
+        self
+    }
+
+    #[must_use]
+    pub const fn smoothing(mut self, x: u32) -> Self {
+        self.smoothing = x;
+        self
+    }
+}
+
+/// Convert a mask into polygons by tracing the contours of the mask.
+///
+/// Each object (connected component) of the mask gives one polygon. Holes are kept by connecting them to the outer border of the
+/// object (see `merge_holes`), this way converting the polygons back to a mask gives the original mask.
+///
+/// ## Args:
+/// - mask: A binary mask indicating for each pixel whether it belongs to the object or not.
+/// - polygon_option: The smoothing/simplification to apply to the polygons.
+///
+/// ## Returns:
+/// - The polygons corresponding to the mask.
+///
+/// ## Example:
+/// ```rust
+/// # use ndarray::Array2;
+/// use cocotools::mask::conversions::{poly_from_mask, PolygonOption};
+/// let mut mask: Array2<u8> = Array2::zeros((40, 40));
+/// mask.slice_mut(ndarray::s![5..35, 5..35]).fill(1);
+/// mask[[5, 5]] = 0;
+/// let poly = poly_from_mask(&mask, PolygonOption::default().tolerance(1.5));
+/// assert_eq!(poly[0].len(), 2 * 4);
+/// ```
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::module_name_repetitions,
+    clippy::needless_pass_by_value
+)]
 #[must_use]
-pub fn poly_from_mask(mask: &Mask) -> object_detection::Polygons {
-    object_detection::Polygons::from(&object_detection::Rle::from(mask))
+pub fn poly_from_mask(mask: &Mask, polygon_option: PolygonOption) -> object_detection::Polygons {
+    // The mask is padded with a one pixel border as `find_contours` does not always correctly detect objects touching the border of the image.
+    let (nrows, ncols) = (mask.nrows() as u32, mask.ncols() as u32);
+    let mask_img = image::GrayImage::from_fn(ncols + 2, nrows + 2, |x, y| {
+        if x == 0 || y == 0 || x > ncols || y > nrows {
+            image::Luma([0])
+        } else {
+            image::Luma([mask[[y as usize - 1, x as usize - 1]]])
+        }
+    });
+    let mut contours = contours::find_contours::<u32>(&mask_img);
+    for point in contours
+        .iter_mut()
+        .flat_map(|contour| contour.points.iter_mut())
+    {
+        point.x -= 1;
+        point.y -= 1;
+    }
+
+    contours
+        .iter()
+        .enumerate()
+        .filter(|(_, contour)| contour.border_type == contours::BorderType::Outer)
+        .map(|(outer_idx, outer)| {
+            let mut holes: Vec<&[Point<u32>]> = contours
+                .iter()
+                .filter(|contour| {
+                    contour.border_type == contours::BorderType::Hole
+                        && contour.parent == Some(outer_idx)
+                })
+                .map(|hole| hole.points.as_slice())
+                .collect();
+            // Merge the holes from left to right, that way the bridge of a hole always ends on the outer border or on an already merged hole.
+            holes.sort_by_key(|hole| leftmost_point(hole));
+
+            let mut ring = remove_straight_line_points(&merge_holes(mask, &outer.points, &holes));
+            for _ in 0..polygon_option.smoothing {
+                ring = chaikin_smoothing(&ring);
+            }
+            if polygon_option.tolerance > 0.0 || polygon_option.max_vertices.is_some() {
+                ring = douglas_peucker(
+                    &ring,
+                    polygon_option.tolerance,
+                    polygon_option.max_vertices.unwrap_or(usize::MAX),
+                );
+            }
+            // Valid polygons must have at least 3 points, very small objects (1 or 2 pixels) are therefore padded with duplicated points.
+            while ring.len() < 3 {
+                ring.push(ring[ring.len() - 1]);
+            }
+            ring.into_iter().flat_map(|(x, y)| [x, y]).collect()
+        })
+        .collect()
+}
+
+/// Return the leftmost point of a contour (the topmost one if there are several).
+fn leftmost_point(contour: &[Point<u32>]) -> Option<(u32, u32)> {
+    contour.iter().map(|point| (point.x, point.y)).min()
+}
+
+/// Merge the holes into the outer border to get a single polygon.
+///
+/// The COCO polygon format has no notion of holes. Each hole is therefore connected to the outer border by a "bridge",
+/// a horizontal segment going from the leftmost point of the hole to the left until reaching the border of the object.
+/// The bridge is traversed once in each direction and does not change the area enclosed by the polygon, while the hole is
+/// now outside of it.
+#[allow(clippy::cast_possible_truncation)]
+fn merge_holes(mask: &Mask, outer: &[Point<u32>], holes: &[&[Point<u32>]]) -> Vec<Point<u32>> {
+    let outer_orientation = signed_area(outer).signum();
+    let mut ring = outer.to_vec();
+    for hole in holes {
+        let (start_idx, start) = match hole
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, point)| (point.x, point.y))
+        {
+            Some(start) => start,
+            None => continue,
+        };
+        let mut end = *start;
+        while end.x > 0 && mask[[end.y as usize, end.x as usize - 1]] != 0 {
+            end.x -= 1;
+        }
+        // The end of the bridge should always be in the ring, this is only there as a safeguard.
+        let bridge_idx = match ring.iter().position(|point| *point == end) {
+            Some(bridge_idx) => bridge_idx,
+            None => continue,
+        };
+
+        let mut hole_ring: Vec<Point<u32>> = hole[start_idx..]
+            .iter()
+            .chain(&hole[..start_idx])
+            .copied()
+            .collect();
+        // Go around the hole in the opposite direction to the outer border so that non-zero winding fill rules also work.
+        if signed_area(hole).signum() == outer_orientation {
+            hole_ring[1..].reverse();
+        }
+        hole_ring.push(*start);
+        hole_ring.push(end);
+        ring.splice(bridge_idx + 1..bridge_idx + 1, hole_ring);
+    }
+    ring
+}
+
+/// Twice the signed area of a polygon, computed with the [shoelace formula](https://en.wikipedia.org/wiki/Shoelace_formula).
+fn signed_area(points: &[Point<u32>]) -> f64 {
+    (0..points.len())
+        .map(|i| {
+            let (current, next) = (points[i], points[(i + 1) % points.len()]);
+            f64::from(current.x)
+                .mul_add(f64::from(next.y), -f64::from(next.x) * f64::from(current.y))
+        })
+        .sum()
+}
+
+/// Remove all the points lying in the middle of a straight line (and duplicated points) as they are not needed.
+///
+/// Points where the contour goes back on itself are kept, otherwise one pixel wide parts of the mask would be lost.
+#[allow(clippy::cast_precision_loss)]
+fn remove_straight_line_points(points: &[Point<u32>]) -> Vec<(f64, f64)> {
+    let mut points: Vec<(i64, i64)> = points
+        .iter()
+        .map(|point| (i64::from(point.x), i64::from(point.y)))
+        .collect();
+    points.dedup();
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    let nb_points = points.len();
+    (0..nb_points)
+        .filter(|&i| {
+            let (prev, point, next) = (
+                points[(i + nb_points - 1) % nb_points],
+                points[i],
+                points[(i + 1) % nb_points],
+            );
+            let (dx1, dy1, dx2, dy2) = (
+                point.0 - prev.0,
+                point.1 - prev.1,
+                next.0 - point.0,
+                next.1 - point.1,
+            );
+            nb_points < 3 || dx1 * dy2 != dy1 * dx2 || dx1 * dx2 + dy1 * dy2 <= 0
+        })
+        .map(|i| (points[i].0 as f64, points[i].1 as f64))
+        .collect()
+}
+
+/// Smooth a closed polygon by cutting each of its corners at 1/4 and 3/4 of the adjacent edges.
+///
+/// See [Chaikin's algorithm](https://www.cs.unc.edu/~dm/UNC/COMP258/LECTURES/Chaikins-Algorithm.pdf).
+fn chaikin_smoothing(ring: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    if ring.len() < 3 {
+        return ring.to_vec();
+    }
+    let mut smoothed = Vec::with_capacity(2 * ring.len());
+    for (i, &(x0, y0)) in ring.iter().enumerate() {
+        let (x1, y1) = ring[(i + 1) % ring.len()];
+        smoothed.push((
+            0.75f64.mul_add(x0, 0.25 * x1),
+            0.75f64.mul_add(y0, 0.25 * y1),
+        ));
+        smoothed.push((
+            0.25f64.mul_add(x0, 0.75 * x1),
+            0.25f64.mul_add(y0, 0.75 * y1),
+        ));
+    }
+    smoothed
+}
+
+/// Segment of a polygon waiting to be refined, ordered by the distance of its farthest point.
+struct DouglasPeuckerSegment {
+    start: usize,
+    end: usize,
+    farthest: usize,
+    distance: f64,
+}
+
+impl PartialEq for DouglasPeuckerSegment {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DouglasPeuckerSegment {}
+
+impl PartialOrd for DouglasPeuckerSegment {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DouglasPeuckerSegment {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+impl DouglasPeuckerSegment {
+    /// Return the segment going from `start` to `end` (possibly wrapping around the end of the ring) if it contains intermediate points.
+    fn new(ring: &[(f64, f64)], start: usize, end: usize) -> Option<Self> {
+        let nb_points = ring.len();
+        let end = if end <= start { end + nb_points } else { end };
+        (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    point_segment_distance(ring[i % nb_points], ring[start], ring[end % nb_points]),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(farthest, distance)| Self {
+                start,
+                end: end % nb_points,
+                farthest: farthest % nb_points,
+                distance,
+            })
+    }
+}
+
+/// Simplify a closed polygon using the [Douglas–Peucker algorithm](https://en.wikipedia.org/wiki/Ramer%E2%80%93Douglas%E2%80%93Peucker_algorithm).
+///
+/// The segments are refined in decreasing order of error, which makes it possible to stop once `max_vertices` vertices have been selected.
+/// At least 3 vertices are kept (if the polygon has them) so that the result remains a valid polygon.
+fn douglas_peucker(ring: &[(f64, f64)], tolerance: f64, max_vertices: usize) -> Vec<(f64, f64)> {
+    if ring.len() <= 3 {
+        return ring.to_vec();
+    }
+    let max_vertices = max_vertices.max(3);
+
+    // A closed polygon has no natural end points, use the first point and the point farthest from it as anchors.
+    let anchor = ring
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            point_segment_distance(**a, ring[0], ring[0])
+                .total_cmp(&point_segment_distance(**b, ring[0], ring[0]))
+        })
+        .map_or(ring.len() / 2, |(i, _)| i);
+
+    let mut kept = vec![false; ring.len()];
+    kept[0] = true;
+    kept[anchor] = true;
+    let mut nb_kept = 2;
+    let mut heap: BinaryHeap<DouglasPeuckerSegment> = [
+        DouglasPeuckerSegment::new(ring, 0, anchor),
+        DouglasPeuckerSegment::new(ring, anchor, 0),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    while let Some(segment) = heap.pop() {
+        if nb_kept >= max_vertices || (nb_kept >= 3 && segment.distance <= tolerance) {
+            break;
+        }
+        kept[segment.farthest] = true;
+        nb_kept += 1;
+        heap.extend(DouglasPeuckerSegment::new(
+            ring,
+            segment.start,
+            segment.farthest,
+        ));
+        heap.extend(DouglasPeuckerSegment::new(
+            ring,
+            segment.farthest,
+            segment.end,
+        ));
+    }
+
+    ring.iter()
+        .zip(kept)
+        .filter_map(|(point, kept)| kept.then_some(*point))
+        .collect()
+}
+
+/// Distance between a point and the segment going from `start` to `end`.
+fn point_segment_distance(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let squared_length = dx.mul_add(dx, dy * dy);
+    let t = if squared_length == 0.0 {
+        0.0
+    } else {
+        ((point.0 - start.0).mul_add(dx, (point.1 - start.1) * dy) / squared_length).clamp(0.0, 1.0)
+    };
+    (point.0 - t.mul_add(dx, start.0)).hypot(point.1 - t.mul_add(dy, start.1))
 }
 
 #[cfg(test)]
@@ -448,9 +852,15 @@ mod tests {
         #[test]
         fn rle_decode_inverts_encode(rle in generate_rle(50, 20)){
             let coco_rle = CocoRle::try_from(&rle).unwrap();
-            let decoded_rle = Rle::from(&coco_rle);
+            let decoded_rle = Rle::try_from(&coco_rle).unwrap();
             prop_assert_eq!(decoded_rle, rle);
         }
+
+        #[test]
+        fn decoding_arbitrary_coco_rle_does_not_panic(counts in "\\PC*"){
+            let coco_rle = CocoRle { size: vec![10, 10], counts };
+            let _ = Rle::try_from(&coco_rle);
+        }
     }
 
     proptest! {
@@ -469,10 +879,117 @@ mod tests {
     fn rle_to_poly_to_rle(#[case] rle: &Rle) {
         let poly = Polygons::from(rle);
         let mask = mask_from_poly(&poly, rle.size[1], rle.size[0]).unwrap();
-        let result_rle = Rle::try_from(&mask).unwrap();
+        let result_rle = Rle::from(&mask);
         assert_eq!(&result_rle, rle);
     }
 
+    proptest! {
+        #[test]
+        fn mask_to_poly_to_mask(mask in generate_mask(100, 100)){
+            let poly = poly_from_mask(&mask, PolygonOption::default());
+            let decoded_mask = mask_from_poly(&poly, mask.ncols() as u32, mask.nrows() as u32).unwrap();
+            prop_assert_eq!(decoded_mask, mask);
+        }
+    }
+
+    prop_compose! {
+        fn generate_blob_mask(max_size: usize)
+            (size in 8..max_size)
+            (size in Just(size),
+             radius in 2..size / 2,
+             noise in prop::collection::vec(0..=1u8, size * size),
+            ) -> Mask {
+                let center = size / 2;
+                Mask::from_shape_fn((size, size), |(row, col)| {
+                    let distance = (row.abs_diff(center).pow(2) + col.abs_diff(center).pow(2)) as f64;
+                    u8::from(distance.sqrt() < radius as f64 || (distance.sqrt() < radius as f64 + 1.0 && noise[row * size + col] == 1))
+                })
+            }
+    }
+
+    proptest! {
+        #[test]
+        fn simplified_poly_respects_vertex_budget(mask in generate_blob_mask(60), max_vertices in 3..12usize){
+            let polygon_option = PolygonOption::default().tolerance(0.5).max_vertices(Some(max_vertices));
+            for poly in poly_from_mask(&mask, polygon_option) {
+                prop_assert!(poly.len() <= 2 * max_vertices);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn simplified_poly_is_close_to_mask(mask in generate_blob_mask(60), tolerance in 0.5..3.0){
+            let polygon_option = PolygonOption::default().tolerance(tolerance);
+            let poly = poly_from_mask(&mask, polygon_option);
+            let raw_poly = poly_from_mask(&mask, PolygonOption::default());
+            prop_assert!(poly[0].len() <= raw_poly[0].len());
+            for point in raw_poly[0].chunks(2) {
+                let ring: Vec<(f64, f64)> = poly[0].chunks(2).map(|p| (p[0], p[1])).collect();
+                let distance = (0..ring.len())
+                    .map(|i| point_segment_distance((point[0], point[1]), ring[i], ring[(i + 1) % ring.len()]))
+                    .fold(f64::INFINITY, f64::min);
+                prop_assert!(distance <= tolerance + 1e-9);
+            }
+        }
+    }
+
+    #[rstest]
+    #[case::square(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)], 1.0, 4)]
+    #[case::staircase(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (3.0, 2.0), (3.0, 3.0), (0.0, 3.0)], 1.0, 3)]
+    #[case::small_tolerance(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (3.0, 2.0), (3.0, 3.0), (0.0, 3.0)], 0.1, 8)]
+    fn douglas_peucker_simplification(
+        #[case] ring: &[(f64, f64)],
+        #[case] tolerance: f64,
+        #[case] expected_nb_vertices: usize,
+    ) {
+        assert_eq!(
+            douglas_peucker(ring, tolerance, usize::MAX).len(),
+            expected_nb_vertices
+        );
+    }
+
+    #[rstest]
+    #[case::square(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)], &[(1.0, 0.0), (3.0, 0.0), (4.0, 1.0), (4.0, 3.0), (3.0, 4.0), (1.0, 4.0), (0.0, 3.0), (0.0, 1.0)])]
+    fn chaikin_corner_cutting(#[case] ring: &[(f64, f64)], #[case] expected_ring: &[(f64, f64)]) {
+        assert_eq!(chaikin_smoothing(ring), expected_ring);
+    }
+
+    #[rstest]
+    #[case::donut(
+        &array![[0, 0, 0, 0, 0, 0, 0],
+                [0, 1, 1, 1, 1, 1, 0],
+                [0, 1, 0, 0, 0, 1, 0],
+                [0, 1, 0, 0, 0, 1, 0],
+                [0, 1, 1, 1, 1, 1, 0],
+                [0, 0, 0, 0, 0, 0, 0]],
+        1
+    )]
+    #[case::donut_with_island(
+        &array![[1, 1, 1, 1, 1, 1, 1],
+                [1, 0, 0, 0, 0, 0, 1],
+                [1, 0, 1, 1, 1, 0, 1],
+                [1, 0, 1, 0, 1, 0, 1],
+                [1, 0, 1, 1, 1, 0, 1],
+                [1, 0, 0, 0, 0, 0, 1],
+                [1, 1, 1, 1, 1, 1, 1]],
+        2
+    )]
+    #[case::two_holes(
+        &array![[1, 1, 1, 1, 1, 1, 1, 1],
+                [1, 0, 0, 1, 1, 1, 1, 1],
+                [1, 0, 0, 1, 1, 0, 1, 1],
+                [1, 1, 1, 1, 0, 0, 1, 1],
+                [1, 1, 1, 1, 1, 1, 1, 1]],
+        1
+    )]
+    fn mask_with_holes_to_poly_to_mask(#[case] mask: &Mask, #[case] expected_nb_polygons: usize) {
+        let poly = poly_from_mask(mask, PolygonOption::default());
+        assert_eq!(poly.len(), expected_nb_polygons);
+        let decoded_mask = mask_from_poly(&poly, mask.ncols() as u32, mask.nrows() as u32).unwrap();
+        assert_eq!(&decoded_mask, mask);
+    }
+
     #[rstest]
     #[case::horizontal_thick_line(
         &PolygonsRS {size: vec![7, 7], counts: vec![vec![1.0, 2.0, 1.0, 4.0, 5.0, 4.0, 5.0, 2.0]]},
@@ -484,7 +1001,13 @@ mod tests {
                 [0, 0, 0, 0, 0, 0, 0],
                 [0, 0, 0, 0, 0, 0, 0]],
     )]
-    // FIXME: Non-square tests seem to fail.
+    #[case::non_square(
+        &PolygonsRS {size: vec![4, 6], counts: vec![vec![1.0, 1.0, 4.0, 1.0, 4.0, 2.0, 1.0, 2.0]]},
+        &array![[0, 0, 0, 0, 0, 0],
+                [0, 1, 1, 1, 1, 0],
+                [0, 1, 1, 1, 1, 0],
+                [0, 0, 0, 0, 0, 0]],
+    )]
     fn poly_rs_to_mask(#[case] poly: &PolygonsRS, #[case] expected_mask: &Mask) {
         let mask = Mask::try_from(poly).unwrap();
         assert_eq!(&mask, expected_mask);
@@ -592,4 +1115,48 @@ mod tests {
         let coco_rle = CocoRle::try_from(rle).unwrap();
         assert_eq!(&coco_rle, expected_coco_rle);
     }
+
+    #[rstest]
+    #[case::square(
+        &CocoRle { size: vec![4, 4], counts: "52203".to_string() },
+        &Rle {size: vec![4, 4], counts: vec![5, 2, 2, 2, 5]})]
+    #[case::square2(
+        &CocoRle {size: vec![9, 10], counts: "61X13mN000`0".to_string()},
+        &Rle {counts: vec![6, 1, 40, 4, 5, 4, 5, 4, 21], size: vec![9, 10]})]
+    #[case::empty(
+        &CocoRle {size: vec![0, 0], counts: String::new()},
+        &Rle {counts: vec![], size: vec![0, 0]})]
+    fn decode_coco_rle(#[case] coco_rle: &CocoRle, #[case] expected_rle: &Rle) {
+        let rle = Rle::try_from(coco_rle).unwrap();
+        assert_eq!(&rle, expected_rle);
+    }
+
+    #[rstest]
+    #[case::invalid_char("52 03", MaskError::InvalidCocoRleChar(' ', 2))]
+    #[case::non_ascii_char("52é03", MaskError::InvalidCocoRleChar('é', 2))]
+    #[case::truncated("5X", MaskError::TruncatedCocoRle(1))]
+    #[case::overflow("5oooooooo0", MaskError::CocoRleCountOverflow(1))]
+    #[case::negative("5O", MaskError::InvalidCocoRleCount(1, -1))]
+    fn decode_malformed_coco_rle(#[case] counts: &str, #[case] expected_error: MaskError) {
+        let coco_rle = CocoRle {
+            size: vec![4, 4],
+            counts: counts.to_string(),
+        };
+        let error = Rle::try_from(&coco_rle).unwrap_err();
+        assert_eq!(error.to_string(), expected_error.to_string());
+    }
+
+    #[test]
+    fn decoder_stops_after_error() {
+        let coco_rle = CocoRle {
+            size: vec![4, 4],
+            counts: "52 03".to_string(),
+        };
+        let decoded: Vec<_> = CocoRleDecoder::new(&coco_rle).collect();
+        assert_eq!(decoded.len(), 3);
+        assert!(matches!(
+            decoded[..],
+            [Ok(5), Ok(2), Err(MaskError::InvalidCocoRleChar(' ', 2))]
+        ));
+    }
 }
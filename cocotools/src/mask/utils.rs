@@ -1,5 +1,13 @@
-use crate::coco::object_detection::{Bbox, CocoRle, Polygons, PolygonsRS, Rle};
-use crate::mask::conversions::mask_from_poly;
+use crate::coco::object_detection::{
+    self, Annotation, BTreemapDataset, Bbox, CocoRle, Image, Polygons, PolygonsRS, Rle,
+    Segmentation,
+};
+use crate::errors::MaskError;
+use crate::mask::conversions::{mask_from_poly, poly_from_mask, PolygonOption};
+use crate::mask::Mask;
+use crate::utils::try_par_map;
+pub use imageproc::region_labelling::Connectivity;
+use ndarray::Array2;
 use std::cmp;
 
 pub trait Area {
@@ -12,13 +20,6 @@ impl Area for Rle {
     }
 }
 
-impl Area for CocoRle {
-    fn area(&self) -> u32 {
-        let rle = Rle::from(self);
-        rle.counts[1..].iter().step_by(2).sum()
-    }
-}
-
 #[allow(clippy::unwrap_used)]
 impl Area for PolygonsRS {
     fn area(&self) -> u32 {
@@ -61,54 +62,19 @@ impl Area for Polygons {
     }
 }
 
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+/// Same as pycocotools' `rleToBbox`: the smallest box containing all the foreground pixels.
 impl From<&Rle> for Bbox {
     fn from(rle: &Rle) -> Self {
-        if rle.counts.len() <= 1 {
-            return Self {
-                left: 0.0,
-                top: 0.0,
-                width: 0.0,
-                height: 0.0,
-            };
-        }
-        let height = rle.size[0];
-        let width = rle.size[1];
-        let mut pos: u32 = 0;
-        let mut current: u32;
-        let mut left: u32 = 0;
-        let mut right: u32 = 0;
-        let mut top = width;
-        let mut bot: u32 = 0;
-        for (i, count) in rle.counts[..rle.counts.len() - 1].iter().enumerate() {
-            pos += count;
-            current = pos - ((i % 2) as u32); // Do not count "current" pixel when adding mask pixels.
-
-            // In the lines below, left/right and top/bot might seem inverted, that's because the RLE corresponds to a fortran array.
-            if i == 0 {
-                left = current / height;
-            } else if i == rle.counts.len() - 2 {
-                right = current / height;
-            }
-
-            let y = current % height;
-            top = cmp::min(top, y);
-            bot = cmp::max(bot, y);
-        }
-
-        Self {
-            left: f64::from(left),
-            top: f64::from(top),
-            width: f64::from(right - left),
-            height: f64::from(bot - top),
-        }
+        bbox_from_column_runs(&column_runs(rle))
     }
 }
 
-impl From<&CocoRle> for Bbox {
-    fn from(coco_rle: &CocoRle) -> Self {
-        let rle = Rle::from(coco_rle);
-        Self::from(&rle)
+impl TryFrom<&CocoRle> for Bbox {
+    type Error = MaskError;
+
+    fn try_from(coco_rle: &CocoRle) -> Result<Self, Self::Error> {
+        let rle = Rle::try_from(coco_rle)?;
+        Ok(Self::from(&rle))
     }
 }
 
@@ -230,3 +196,949 @@ impl From<&Polygons> for Bbox {
         }
     }
 }
+
+/// A connected component of a segmentation mask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component {
+    /// The mask of the component, with the same size as the original mask.
+    pub rle: Rle,
+    pub area: u32,
+    pub bbox: Bbox,
+}
+
+pub trait ConnectedComponents {
+    /// Split the mask into its connected components, ordered by position of their first pixel (in column-major order).
+    fn connected_components(&self, connectivity: Connectivity) -> Vec<Component>;
+}
+
+impl ConnectedComponents for Rle {
+    fn connected_components(&self, connectivity: Connectivity) -> Vec<Component> {
+        let runs = column_runs(self);
+        // Runs in the same column are never connected, only the runs in the previous column need to be checked.
+        let margin = u32::from(connectivity == Connectivity::Eight);
+        let mut parents: Vec<usize> = (0..runs.len()).collect();
+        let mut prev_col_start = 0;
+        let mut col_start = 0;
+        for i in 0..runs.len() {
+            if runs[i].col != runs[col_start].col {
+                prev_col_start = if runs[i].col == runs[col_start].col + 1 {
+                    col_start
+                } else {
+                    i
+                };
+                col_start = i;
+            }
+            for j in prev_col_start..col_start {
+                if runs[j].start < runs[i].end + margin && runs[i].start < runs[j].end + margin {
+                    let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                    parents[root_i.max(root_j)] = root_i.min(root_j);
+                }
+            }
+        }
+
+        let mut components: Vec<Vec<ColumnRun>> = Vec::new();
+        let mut component_idx: Vec<Option<usize>> = vec![None; runs.len()];
+        for (i, run) in runs.iter().enumerate() {
+            let root = find_root(&mut parents, i);
+            let idx = *component_idx[root].get_or_insert_with(|| {
+                components.push(Vec::new());
+                components.len() - 1
+            });
+            components[idx].push(*run);
+        }
+
+        components
+            .into_iter()
+            .map(|component_runs| {
+                let rle = rle_from_column_runs(&component_runs, &self.size);
+                Component {
+                    area: rle.area(),
+                    bbox: bbox_from_column_runs(&component_runs),
+                    rle,
+                }
+            })
+            .collect()
+    }
+}
+
+impl ConnectedComponents for Mask {
+    fn connected_components(&self, connectivity: Connectivity) -> Vec<Component> {
+        Rle::from(self).connected_components(connectivity)
+    }
+}
+
+/// Vertical run of foreground pixels, contained in a single column of the mask.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ColumnRun {
+    pub col: u32,
+    /// First row of the run.
+    pub start: u32,
+    /// Row after the last row of the run.
+    pub end: u32,
+}
+
+/// Split the foreground runs of a RLE at the column boundaries.
+pub(crate) fn column_runs(rle: &Rle) -> Vec<ColumnRun> {
+    let height = rle.size[0];
+    let mut runs = Vec::new();
+    let mut position: u32 = 0;
+    for (i, count) in rle.counts.iter().enumerate() {
+        if i % 2 == 1 && height > 0 {
+            let end = position + count;
+            let mut start = position;
+            while start < end {
+                let col = start / height;
+                let run_end = end.min((col + 1) * height);
+                runs.push(ColumnRun {
+                    col,
+                    start: start % height,
+                    end: run_end - col * height,
+                });
+                start = run_end;
+            }
+        }
+        position += count;
+    }
+    runs
+}
+
+/// Smallest box containing all the runs, or an empty box at the origin if there are none.
+pub(crate) fn bbox_from_column_runs(runs: &[ColumnRun]) -> Bbox {
+    if runs.is_empty() {
+        return Bbox {
+            left: 0.0,
+            top: 0.0,
+            width: 0.0,
+            height: 0.0,
+        };
+    }
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for run in runs {
+        left = left.min(run.col);
+        right = right.max(run.col + 1);
+        top = top.min(run.start);
+        bottom = bottom.max(run.end);
+    }
+    Bbox {
+        left: f64::from(left),
+        top: f64::from(top),
+        width: f64::from(right - left),
+        height: f64::from(bottom - top),
+    }
+}
+
+/// Build a RLE from foreground runs sorted in column-major order.
+pub(crate) fn rle_from_column_runs(runs: &[ColumnRun], size: &[u32]) -> Rle {
+    let height = size[0];
+    let mut counts = Vec::with_capacity(2 * runs.len() + 1);
+    let mut position: u32 = 0;
+    for run in runs {
+        let (start, end) = (run.col * height + run.start, run.col * height + run.end);
+        match counts.last_mut() {
+            // The run continues the previous one (it starts at the top of the column the previous one ended on).
+            Some(last) if start == position => *last += end - start,
+            _ => {
+                counts.push(start - position);
+                counts.push(end - start);
+            }
+        }
+        position = end;
+    }
+    counts.push(size[0] * size[1] - position);
+    Rle {
+        size: size.to_vec(),
+        counts,
+    }
+}
+
+fn find_root(parents: &mut [usize], mut idx: usize) -> usize {
+    while parents[idx] != idx {
+        parents[idx] = parents[parents[idx]];
+        idx = parents[idx];
+    }
+    idx
+}
+
+/// Split the annotations whose mask is made of several disjoint parts into one annotation per part.
+///
+/// Only the parts with an area of at least `min_area` pixels become separate annotations, the smaller parts are kept with the largest part.
+/// The largest part keeps the original annotation id, the new annotations are given ids following the biggest annotation id of the dataset.
+/// Crowd annotations (`iscrowd=1`) are left untouched as they are expected to contain several objects.
+///
+/// ## Returns:
+/// - The ids of the newly created annotations.
+///
+/// # Errors
+///
+/// Will return `Err` if the segmentation masks could not be decoded/encoded.
+pub fn split_disjoint_anns(
+    dataset: &mut BTreemapDataset,
+    min_area: u32,
+    connectivity: Connectivity,
+) -> Result<Vec<u64>, MaskError> {
+    let splits = try_par_map(dataset.anns.values().collect(), |ann| {
+        split_ann(ann, min_area, connectivity)
+    })?;
+
+    // The ids are given after the parallel part to not depend on the order in which the annotations are processed.
+    let mut next_id = dataset.next_ann_id();
+    let mut new_ids = Vec::new();
+    for (main_ann, parts) in splits.into_iter().flatten() {
+        dataset.insert_ann(main_ann);
+        for mut part in parts {
+            part.id = next_id;
+            next_id += 1;
+            new_ids.push(part.id);
+            dataset.insert_ann(part);
+        }
+    }
+    Ok(new_ids)
+}
+
+/// Split the annotation in its main part and its other large parts (with the same id as the annotation).
+///
+/// Returns `None` if the annotation does not need to be split.
+fn split_ann(
+    ann: &Annotation,
+    min_area: u32,
+    connectivity: Connectivity,
+) -> Result<Option<(Annotation, Vec<Annotation>)>, MaskError> {
+    if ann.iscrowd != 0 {
+        return Ok(None);
+    }
+    let mut components = Mask::try_from(&ann.segmentation)?.connected_components(connectivity);
+    components.sort_by_key(|component| cmp::Reverse(component.area));
+    if components
+        .iter()
+        .filter(|component| component.area >= min_area)
+        .count()
+        < 2
+    {
+        return Ok(None);
+    }
+
+    let (large, small): (Vec<Component>, Vec<Component>) = components
+        .into_iter()
+        .partition(|component| component.area >= min_area);
+    let mut parts = large.into_iter();
+    let main_part = match parts.next() {
+        Some(main_part) => main_part,
+        None => return Ok(None),
+    };
+    // Put the small parts back with the largest one.
+    let main_rle = if small.is_empty() {
+        main_part.rle
+    } else {
+        let mut runs: Vec<ColumnRun> = column_runs(&main_part.rle)
+            .into_iter()
+            .chain(
+                small
+                    .iter()
+                    .flat_map(|component| column_runs(&component.rle)),
+            )
+            .collect();
+        runs.sort_by_key(|run| (run.col, run.start));
+        rle_from_column_runs(&runs, &main_part.rle.size)
+    };
+
+    let parts = parts
+        .map(|part| {
+            Ok(Annotation {
+                segmentation: encode_like(&ann.segmentation, &part.rle)?,
+                area: f64::from(part.area),
+                bbox: part.bbox,
+                ..ann.clone()
+            })
+        })
+        .collect::<Result<Vec<_>, MaskError>>()?;
+    let main_ann = Annotation {
+        segmentation: encode_like(&ann.segmentation, &main_rle)?,
+        area: f64::from(main_rle.area()),
+        bbox: Bbox::from(&main_rle),
+        ..ann.clone()
+    };
+    Ok(Some((main_ann, parts)))
+}
+
+/// Encode `rle` in the same format as `segmentation`.
+fn encode_like(segmentation: &Segmentation, rle: &Rle) -> Result<Segmentation, MaskError> {
+    Ok(match segmentation {
+        Segmentation::Rle(_) => Segmentation::Rle(rle.clone()),
+        Segmentation::CocoRle(_) => Segmentation::CocoRle(CocoRle::try_from(rle)?),
+        Segmentation::PolygonsRS(_) => Segmentation::PolygonsRS(object_detection::PolygonsRS {
+            size: rle.size.clone(),
+            counts: poly_from_mask(&Mask::from(rle), PolygonOption::default()),
+        }),
+        Segmentation::Polygons(_) => {
+            Segmentation::Polygons(poly_from_mask(&Mask::from(rle), PolygonOption::default()))
+        }
+    })
+}
+
+/// Structuring element used by the morphological operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructuringElement {
+    /// Square of side `2 * radius + 1`.
+    Square(u32),
+    /// Disk of the given radius.
+    Disk(u32),
+}
+
+impl StructuringElement {
+    const fn radius(self) -> u32 {
+        match self {
+            Self::Square(radius) | Self::Disk(radius) => radius,
+        }
+    }
+
+    /// Half height of the structuring element in the column at the (absolute) horizontal offset `dx` from its center.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn half_height(self, dx: u32) -> u32 {
+        match self {
+            Self::Square(radius) => radius,
+            Self::Disk(radius) => {
+                let squared = u64::from(radius).pow(2) - u64::from(dx).pow(2);
+                let mut half_height = (squared as f64).sqrt() as u64;
+                // Correct potential rounding errors of the float square root.
+                while half_height * half_height > squared {
+                    half_height -= 1;
+                }
+                while (half_height + 1) * (half_height + 1) <= squared {
+                    half_height += 1;
+                }
+                half_height as u32
+            }
+        }
+    }
+}
+
+/// [Morphological operations](https://en.wikipedia.org/wiki/Mathematical_morphology) on segmentation masks.
+///
+/// The operations work directly on the runs of the RLE, one column at a time, and are therefore fast even for large masks.
+/// Like in OpenCV, the pixels outside of the image are considered as background for the dilation, and are ignored by the erosion.
+pub trait Morphology {
+    /// Grow the mask by the structuring element.
+    fn dilate(&self, element: StructuringElement) -> Rle;
+    /// Shrink the mask by the structuring element.
+    fn erode(&self, element: StructuringElement) -> Rle;
+    /// Erosion followed by a dilation, removes the parts of the mask smaller than the structuring element.
+    fn open(&self, element: StructuringElement) -> Rle;
+    /// Dilation followed by an erosion, fills the holes and gaps smaller than the structuring element.
+    fn close(&self, element: StructuringElement) -> Rle;
+}
+
+impl Morphology for Rle {
+    fn dilate(&self, element: StructuringElement) -> Rle {
+        let columns = runs_per_column(self);
+        let (height, width) = (self.size[0], self.size[1]);
+        let radius = element.radius();
+
+        let mut runs = Vec::new();
+        let mut intervals: Vec<(u32, u32)> = Vec::new();
+        for col in 0..width {
+            intervals.clear();
+            for other_col in
+                col.saturating_sub(radius)..=col.saturating_add(radius).min(width.saturating_sub(1))
+            {
+                let half_height = element.half_height(col.abs_diff(other_col));
+                intervals.extend(columns[other_col as usize].iter().map(|&(start, end)| {
+                    (
+                        start.saturating_sub(half_height),
+                        end.saturating_add(half_height).min(height),
+                    )
+                }));
+            }
+            intervals.sort_unstable();
+            for &(start, end) in &intervals {
+                match runs.last_mut() {
+                    Some(ColumnRun {
+                        col: last_col,
+                        end: last_end,
+                        ..
+                    }) if *last_col == col && start <= *last_end => {
+                        *last_end = (*last_end).max(end);
+                    }
+                    _ => runs.push(ColumnRun { col, start, end }),
+                }
+            }
+        }
+        rle_from_column_runs(&runs, &self.size)
+    }
+
+    fn erode(&self, element: StructuringElement) -> Rle {
+        erode(self, element, false)
+    }
+
+    fn open(&self, element: StructuringElement) -> Rle {
+        self.erode(element).dilate(element)
+    }
+
+    fn close(&self, element: StructuringElement) -> Rle {
+        self.dilate(element).erode(element)
+    }
+}
+
+impl Morphology for Mask {
+    fn dilate(&self, element: StructuringElement) -> Rle {
+        Rle::from(self).dilate(element)
+    }
+
+    fn erode(&self, element: StructuringElement) -> Rle {
+        Rle::from(self).erode(element)
+    }
+
+    fn open(&self, element: StructuringElement) -> Rle {
+        Rle::from(self).open(element)
+    }
+
+    fn close(&self, element: StructuringElement) -> Rle {
+        Rle::from(self).close(element)
+    }
+}
+
+/// Erode the mask. If `background_border` is true, then the pixels outside of the image are considered as background (and erode the mask).
+pub(crate) fn erode(rle: &Rle, element: StructuringElement, background_border: bool) -> Rle {
+    let columns = runs_per_column(rle);
+    let (height, width) = (rle.size[0], rle.size[1]);
+    let radius = element.radius();
+
+    let mut runs = Vec::new();
+    for col in 0..width {
+        let mut intervals = vec![(0, height)];
+        for other_col in i64::from(col) - i64::from(radius)..=i64::from(col) + i64::from(radius) {
+            let other_col = match u32::try_from(other_col) {
+                Ok(other_col) if other_col < width => other_col,
+                _ if background_border => {
+                    intervals.clear();
+                    break;
+                }
+                _ => continue,
+            };
+            let half_height = element.half_height(col.abs_diff(other_col));
+            let shrunk_intervals =
+                columns[other_col as usize]
+                    .iter()
+                    .filter_map(|&(start, end)| {
+                        let start = if start == 0 && !background_border {
+                            0
+                        } else {
+                            start + half_height
+                        };
+                        let end = if end == height && !background_border {
+                            height
+                        } else {
+                            end.saturating_sub(half_height)
+                        };
+                        (start < end).then_some((start, end))
+                    });
+            intervals = intersect_intervals(&intervals, shrunk_intervals);
+            if intervals.is_empty() {
+                break;
+            }
+        }
+        runs.extend(
+            intervals
+                .into_iter()
+                .map(|(start, end)| ColumnRun { col, start, end }),
+        );
+    }
+    rle_from_column_runs(&runs, &rle.size)
+}
+
+/// Intersection of two sorted lists of disjoint intervals.
+fn intersect_intervals(
+    intervals: &[(u32, u32)],
+    other: impl Iterator<Item = (u32, u32)>,
+) -> Vec<(u32, u32)> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    for (other_start, other_end) in other {
+        while i < intervals.len() && intervals[i].1 <= other_start {
+            i += 1;
+        }
+        let mut j = i;
+        while j < intervals.len() && intervals[j].0 < other_end {
+            let (start, end) = (
+                intervals[j].0.max(other_start),
+                intervals[j].1.min(other_end),
+            );
+            if start < end {
+                result.push((start, end));
+            }
+            j += 1;
+        }
+    }
+    result
+}
+
+/// Foreground runs of each column of the mask, as (first row, row after the last row).
+fn runs_per_column(rle: &Rle) -> Vec<Vec<(u32, u32)>> {
+    let mut columns = vec![Vec::new(); rle.size[1] as usize];
+    for run in column_runs(rle) {
+        columns[run.col as usize].push((run.start, run.end));
+    }
+    columns
+}
+
+/// Kind of overlap measure used to match detections and ground truths during evaluation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IouType {
+    /// IoU of the bounding boxes.
+    Bbox,
+    /// IoU of the segmentation masks.
+    Segm,
+    /// [Boundary IoU](https://arxiv.org/abs/2103.16562) of the segmentation masks, with the given dilation ratio (0.02 in the paper).
+    Boundary(f64),
+}
+
+/// Compute the IoU between each detection and ground truth of an image, in the same way as pycocotools.
+///
+/// The result has one row per detection and one column per ground truth.
+/// For crowd ground truths (`iscrowd=1`), the union is taken to be the detection area.
+///
+/// # Errors
+///
+/// Will return `Err` if a segmentation cannot be decoded or if two masks do not have the same size.
+pub fn compute_ious(
+    dts: &[&Annotation],
+    gts: &[&Annotation],
+    img: &Image,
+    iou_type: IouType,
+) -> Result<Array2<f64>, MaskError> {
+    let mut ious = Array2::zeros((dts.len(), gts.len()));
+    if let IouType::Bbox = iou_type {
+        for ((dt_idx, gt_idx), iou) in ious.indexed_iter_mut() {
+            let gt = gts[gt_idx];
+            *iou = bbox_iou(&dts[dt_idx].bbox, &gt.bbox, gt.iscrowd != 0);
+        }
+        return Ok(ious);
+    }
+
+    let regions = |anns: &[&Annotation]| -> Result<Vec<Vec<(u32, u32)>>, MaskError> {
+        anns.iter()
+            .map(|ann| {
+                let rle = rle_from_segmentation(&ann.segmentation, img.width, img.height)?;
+                Ok(match iou_type {
+                    IouType::Boundary(dilation_ratio) => boundary_intervals(&rle, dilation_ratio),
+                    _ => foreground_intervals(&rle),
+                })
+            })
+            .collect()
+    };
+    let dt_regions = regions(dts)?;
+    let gt_regions = regions(gts)?;
+    for ((dt_idx, gt_idx), iou) in ious.indexed_iter_mut() {
+        *iou = intervals_iou(
+            &dt_regions[dt_idx],
+            &gt_regions[gt_idx],
+            gts[gt_idx].iscrowd != 0,
+        );
+    }
+    Ok(ious)
+}
+
+/// Compute the [Boundary IoU](https://arxiv.org/abs/2103.16562) between two segmentations.
+///
+/// The boundary of a mask is the band of pixels of the mask within `dilation_ratio` times the image diagonal of its contour.\
+/// The `width` and `height` of the image are only used for [`Segmentation::Polygons`], the other formats already contain the size of the mask.
+///
+/// # Errors
+///
+/// Will return `Err` if a segmentation cannot be decoded or if the two masks do not have the same size.
+pub fn boundary_iou(
+    dt: &Segmentation,
+    gt: &Segmentation,
+    width: u32,
+    height: u32,
+    dilation_ratio: f64,
+) -> Result<f64, MaskError> {
+    let dt = rle_from_segmentation(dt, width, height)?;
+    let gt = rle_from_segmentation(gt, width, height)?;
+    if dt.size != gt.size {
+        return Err(MaskError::SizeMismatch(dt.size, gt.size));
+    }
+    Ok(intervals_iou(
+        &boundary_intervals(&dt, dilation_ratio),
+        &boundary_intervals(&gt, dilation_ratio),
+        false,
+    ))
+}
+
+pub(crate) fn rle_from_segmentation(
+    segmentation: &Segmentation,
+    width: u32,
+    height: u32,
+) -> Result<Rle, MaskError> {
+    Ok(match segmentation {
+        Segmentation::Rle(rle) => rle.clone(),
+        Segmentation::CocoRle(coco_rle) => Rle::try_from(coco_rle)?,
+        Segmentation::PolygonsRS(poly) => Rle::from(&Mask::try_from(poly)?),
+        Segmentation::Polygons(poly) => Rle::from(&mask_from_poly(poly, width, height)?),
+    })
+}
+
+fn bbox_iou(dt: &Bbox, gt: &Bbox, iscrowd: bool) -> f64 {
+    let inter_width = (dt.left + dt.width).min(gt.left + gt.width) - dt.left.max(gt.left);
+    let inter_height = (dt.top + dt.height).min(gt.top + gt.height) - dt.top.max(gt.top);
+    if inter_width <= 0.0 || inter_height <= 0.0 {
+        return 0.0;
+    }
+    let intersection = inter_width * inter_height;
+    let union = if iscrowd {
+        dt.width * dt.height
+    } else {
+        dt.width.mul_add(dt.height, gt.width * gt.height) - intersection
+    };
+    intersection / union
+}
+
+/// IoU of two sets of pixels, each given as sorted disjoint intervals of (column major) pixel indices.
+fn intervals_iou(dt: &[(u32, u32)], gt: &[(u32, u32)], iscrowd: bool) -> f64 {
+    let total = |intervals: &[(u32, u32)]| {
+        intervals
+            .iter()
+            .map(|(start, end)| u64::from(end - start))
+            .sum::<u64>()
+    };
+    let intersection = total(&intersect_intervals(dt, gt.iter().copied()));
+    let union = if iscrowd {
+        total(dt)
+    } else {
+        total(dt) + total(gt) - intersection
+    };
+    if union == 0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let iou = intersection as f64 / union as f64;
+    iou
+}
+
+/// Foreground pixels of the mask, as intervals of (column major) pixel indices.
+fn foreground_intervals(rle: &Rle) -> Vec<(u32, u32)> {
+    let mut intervals = Vec::with_capacity(rle.counts.len() / 2);
+    let mut pos = 0;
+    for (i, count) in rle.counts.iter().enumerate() {
+        if i % 2 == 1 && *count > 0 {
+            intervals.push((pos, pos + count));
+        }
+        pos += count;
+    }
+    intervals
+}
+
+/// Pixels of the boundary band of the mask, as intervals of (column major) pixel indices.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn boundary_intervals(rle: &Rle, dilation_ratio: f64) -> Vec<(u32, u32)> {
+    let diagonal = f64::from(rle.size[0]).hypot(f64::from(rle.size[1]));
+    let dilation = ((dilation_ratio * diagonal).round() as u32).max(1);
+    // Like in the reference implementation, the pixels outside of the image are considered as background.
+    let eroded = erode(rle, StructuringElement::Square(dilation), true);
+
+    let mut eroded_intervals = foreground_intervals(&eroded).into_iter().peekable();
+    let mut intervals = Vec::new();
+    for (start, end) in foreground_intervals(rle) {
+        // The eroded mask is contained in the mask, so each of its intervals is inside one of the mask's intervals.
+        let mut pos = start;
+        while let Some((eroded_start, eroded_end)) =
+            eroded_intervals.next_if(|(eroded_start, _)| *eroded_start < end)
+        {
+            if pos < eroded_start {
+                intervals.push((pos, eroded_start));
+            }
+            pos = eroded_end;
+        }
+        if pos < end {
+            intervals.push((pos, end));
+        }
+    }
+    intervals
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::coco::object_detection::{Category, Dataset, Image};
+    use ndarray::array;
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    prop_compose! {
+        fn generate_mask(max_ncols: usize, max_nrows: usize)
+            (ncols in 2..max_ncols, nrows in 2..max_nrows)
+            (ncols in Just(ncols),
+             nrows in Just(nrows),
+             mask_data in prop::collection::vec(0..=1u8, ncols * nrows),
+            ) -> Mask {
+                Mask::from_shape_vec((nrows, ncols), mask_data).unwrap()
+            }
+    }
+
+    proptest! {
+        #[test]
+        fn components_partition_mask(mask in generate_mask(40, 40), eight_connectivity: bool){
+            let connectivity = if eight_connectivity { Connectivity::Eight } else { Connectivity::Four };
+            let components = mask.connected_components(connectivity);
+            let mut merged_mask = Mask::zeros(mask.raw_dim());
+            for component in &components {
+                let component_mask = Mask::from(&component.rle);
+                prop_assert_eq!(component_mask.iter().map(|value| u32::from(*value)).sum::<u32>(), component.area);
+                merged_mask += &component_mask;
+            }
+            prop_assert_eq!(&merged_mask, &mask);
+
+            let mask_img = image::GrayImage::from_fn(mask.ncols() as u32, mask.nrows() as u32, |x, y| {
+                image::Luma([mask[[y as usize, x as usize]]])
+            });
+            let labels = imageproc::region_labelling::connected_components(&mask_img, connectivity, image::Luma([0]));
+            prop_assert_eq!(components.len() as u32, labels.pixels().map(|label| label[0]).max().unwrap_or(0));
+        }
+
+        #[test]
+        fn morphology_matches_naive_implementation(mask in generate_mask(30, 30), radius in 0..4u32, disk: bool) {
+            let element = if disk { StructuringElement::Disk(radius) } else { StructuringElement::Square(radius) };
+            prop_assert_eq!(Mask::from(&mask.dilate(element)), naive_morphology(&mask, element, true));
+            prop_assert_eq!(Mask::from(&mask.erode(element)), naive_morphology(&mask, element, false));
+            prop_assert_eq!(Mask::from(&mask.open(element)), naive_morphology(&naive_morphology(&mask, element, false), element, true));
+            prop_assert_eq!(Mask::from(&mask.close(element)), naive_morphology(&naive_morphology(&mask, element, true), element, false));
+        }
+    }
+
+    /// Pixel by pixel dilation (or erosion), pixels outside of the image being ignored.
+    #[allow(clippy::cast_possible_wrap)]
+    fn naive_morphology(mask: &Mask, element: StructuringElement, dilate: bool) -> Mask {
+        let radius = element.radius() as isize;
+        let in_element = |dx: isize, dy: isize| match element {
+            StructuringElement::Square(_) => true,
+            StructuringElement::Disk(_) => dx * dx + dy * dy <= radius * radius,
+        };
+        let (nrows, ncols) = mask.dim();
+        Mask::from_shape_fn((nrows, ncols), |(row, col)| {
+            let mut neighbors = (-radius..=radius)
+                .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                .filter(|&(dx, dy)| in_element(dx, dy))
+                .filter_map(|(dx, dy)| {
+                    let (row, col) = (row as isize + dy, col as isize + dx);
+                    (row >= 0 && col >= 0 && row < nrows as isize && col < ncols as isize)
+                        .then(|| mask[[row as usize, col as usize]])
+                });
+            u8::from(if dilate {
+                neighbors.any(|value| value == 1)
+            } else {
+                neighbors.all(|value| value == 1)
+            })
+        })
+    }
+
+    #[test]
+    fn disk_dilation_of_single_pixel() {
+        let mut mask = Mask::zeros((7, 7));
+        mask[[3, 3]] = 1;
+        let expected = array![
+            [0, 0, 0, 1, 0, 0, 0],
+            [0, 1, 1, 1, 1, 1, 0],
+            [0, 1, 1, 1, 1, 1, 0],
+            [1, 1, 1, 1, 1, 1, 1],
+            [0, 1, 1, 1, 1, 1, 0],
+            [0, 1, 1, 1, 1, 1, 0],
+            [0, 0, 0, 1, 0, 0, 0],
+        ];
+        let dilated = mask.dilate(StructuringElement::Disk(3));
+        assert_eq!(Mask::from(&dilated), expected);
+        assert_eq!(
+            Mask::from(&dilated.erode(StructuringElement::Disk(3))),
+            mask
+        );
+    }
+
+    #[rstest]
+    #[case::diagonal_squares(
+        &array![[1, 1, 0, 0],
+                [1, 1, 0, 0],
+                [0, 0, 1, 1],
+                [0, 0, 1, 1]],
+        Connectivity::Four,
+        &[4, 4],
+    )]
+    #[case::diagonal_squares_eight_connectivity(
+        &array![[1, 1, 0, 0],
+                [1, 1, 0, 0],
+                [0, 0, 1, 1],
+                [0, 0, 1, 1]],
+        Connectivity::Eight,
+        &[8],
+    )]
+    #[case::u_shape(
+        &array![[1, 0, 1],
+                [1, 0, 1],
+                [1, 1, 1]],
+        Connectivity::Four,
+        &[7],
+    )]
+    #[case::empty(&array![[0, 0], [0, 0]], Connectivity::Four, &[])]
+    fn connected_components_areas(
+        #[case] mask: &Mask,
+        #[case] connectivity: Connectivity,
+        #[case] expected_areas: &[u32],
+    ) {
+        let areas: Vec<u32> = mask
+            .connected_components(connectivity)
+            .iter()
+            .map(|component| component.area)
+            .collect();
+        assert_eq!(areas, expected_areas);
+    }
+
+    fn square_segmentation(left: usize, top: usize, side: usize) -> Segmentation {
+        let mut mask = Mask::zeros((20, 20));
+        mask.slice_mut(ndarray::s![top..top + side, left..left + side])
+            .fill(1);
+        Segmentation::Rle(Rle::from(&mask))
+    }
+
+    #[rstest]
+    #[case::identical(square_segmentation(5, 5, 10), square_segmentation(5, 5, 10), 1.0)]
+    #[case::disjoint(square_segmentation(0, 0, 5), square_segmentation(10, 10, 5), 0.0)]
+    // The top and bottom sides of the bands overlap on 9 pixels each, the left and right sides do not overlap.
+    #[case::shifted(square_segmentation(5, 5, 10), square_segmentation(6, 5, 10), 18.0 / 54.0)]
+    // The band of a 3x3 square is the square without its center, the overlapping columns share 4 band pixels.
+    #[case::small(square_segmentation(5, 5, 3), square_segmentation(6, 5, 3), 4.0 / 12.0)]
+    fn boundary_iou_values(
+        #[case] dt: Segmentation,
+        #[case] gt: Segmentation,
+        #[case] expected_iou: f64,
+    ) {
+        let iou = boundary_iou(&dt, &gt, 20, 20, 0.02).unwrap();
+        assert!(
+            (iou - expected_iou).abs() < 1e-9,
+            "expected {expected_iou}, got {iou}"
+        );
+    }
+
+    #[test]
+    fn compute_ious_per_type() {
+        let img = Image {
+            id: 1,
+            width: 20,
+            height: 20,
+            file_name: "1.jpg".to_string(),
+            ..Default::default()
+        };
+        let ann = |id, segmentation: Segmentation, iscrowd| {
+            let rle = rle_from_segmentation(&segmentation, 20, 20).unwrap();
+            Annotation {
+                id,
+                image_id: 1,
+                category_id: 1,
+                area: f64::from(rle.area()),
+                bbox: Bbox::from(&rle),
+                segmentation,
+                iscrowd,
+                extra: Default::default(),
+            }
+        };
+        let dts = [
+            ann(1, square_segmentation(6, 5, 10), 0),
+            ann(2, square_segmentation(0, 0, 4), 0),
+        ];
+        let gts = [
+            ann(3, square_segmentation(5, 5, 10), 0),
+            ann(4, square_segmentation(0, 0, 8), 1),
+        ];
+        let dts: Vec<_> = dts.iter().collect();
+        let gts: Vec<_> = gts.iter().collect();
+
+        let segm_ious = compute_ious(&dts, &gts, &img, IouType::Segm).unwrap();
+        assert!((segm_ious[[0, 0]] - 90.0 / 110.0).abs() < 1e-9);
+        // The crowd ground truth covers the whole second detection.
+        assert!((segm_ious[[1, 1]] - 1.0).abs() < 1e-9);
+        assert!((segm_ious[[1, 0]]).abs() < 1e-9);
+
+        let boundary_ious = compute_ious(&dts, &gts, &img, IouType::Boundary(0.02)).unwrap();
+        assert!((boundary_ious[[0, 0]] - 18.0 / 54.0).abs() < 1e-9);
+        assert!(boundary_ious[[0, 0]] < segm_ious[[0, 0]]);
+
+        let bbox_ious = compute_ious(&dts, &gts, &img, IouType::Bbox).unwrap();
+        assert_eq!(bbox_ious.dim(), (2, 2));
+        assert!((bbox_ious[[1, 1]] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn split_disjoint_anns_creates_new_anns() {
+        let mask = array![
+            [1, 1, 0, 0, 0, 0],
+            [1, 1, 0, 0, 1, 0],
+            [0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 1, 1, 1],
+            [0, 0, 0, 1, 1, 1]
+        ];
+        let rle = Rle::from(&mask);
+        let dataset = Dataset {
+            images: vec![Image {
+                id: 1,
+                width: 6,
+                height: 5,
+                file_name: "1.jpg".to_string(),
+                ..Default::default()
+            }],
+            annotations: vec![Annotation {
+                id: 3,
+                image_id: 1,
+                category_id: 1,
+                area: f64::from(rle.area()),
+                bbox: Bbox::from(&rle),
+                segmentation: Segmentation::Rle(rle),
+                iscrowd: 0,
+                extra: Default::default(),
+            }],
+            categories: vec![Category {
+                id: 1,
+                name: "object".to_string(),
+                supercategory: None,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut dataset = BTreemapDataset::from_dataset(dataset, "").unwrap();
+
+        let new_ids = split_disjoint_anns(&mut dataset, 2, Connectivity::Eight).unwrap();
+        assert_eq!(new_ids, vec![4]);
+        assert_eq!(dataset.get_img_anns(1).unwrap().len(), 2);
+        // The single pixel part is too small and stays with the largest part.
+        assert_eq!(dataset.get_ann(3).unwrap().area, 7.0);
+        assert_eq!(dataset.get_ann(4).unwrap().area, 4.0);
+        assert_eq!(
+            dataset.get_ann(4).unwrap().bbox,
+            Bbox {
+                left: 0.0,
+                top: 0.0,
+                width: 2.0,
+                height: 2.0
+            }
+        );
+        assert_eq!(
+            dataset.get_ann(3).unwrap().bbox,
+            Bbox {
+                left: 3.0,
+                top: 1.0,
+                width: 3.0,
+                height: 4.0
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::empty(vec![16], Bbox { left: 0.0, top: 0.0, width: 0.0, height: 0.0 })]
+    #[case::single_pixel(vec![5, 1, 10], Bbox { left: 1.0, top: 1.0, width: 1.0, height: 1.0 })]
+    #[case::square(vec![1, 2, 2, 2, 9], Bbox { left: 0.0, top: 1.0, width: 2.0, height: 2.0 })]
+    #[case::wrapping_run(vec![2, 4, 10], Bbox { left: 0.0, top: 0.0, width: 2.0, height: 4.0 })]
+    #[case::full(vec![0, 16], Bbox { left: 0.0, top: 0.0, width: 4.0, height: 4.0 })]
+    fn bbox_from_rle(#[case] counts: Vec<u32>, #[case] expected: Bbox) {
+        let rle = Rle {
+            size: vec![4, 4],
+            counts,
+        };
+        assert_eq!(Bbox::from(&rle), expected);
+    }
+}
@@ -0,0 +1,112 @@
+//! Helpers shared by the unit tests.
+#![allow(clippy::unwrap_used)]
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::coco::object_detection::{
+    Annotation, BTreemapDataset, Bbox, Category, Dataset, ExtraFields, Image, Rle, Segmentation,
+};
+
+/// Returns an empty directory for the files written by the test `name`.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cocotools-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Builds the small datasets used by the tests, element by element.
+#[derive(Default)]
+pub struct DatasetBuilder {
+    dataset: Dataset,
+}
+
+impl DatasetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an image named `{id}.jpg`.
+    pub fn img(self, id: u64, width: u32, height: u32) -> Self {
+        self.named_img(id, width, height, &format!("{id}.jpg"))
+    }
+
+    pub fn named_img(mut self, id: u64, width: u32, height: u32, file_name: &str) -> Self {
+        self.dataset.images.push(Image {
+            id,
+            width,
+            height,
+            file_name: file_name.to_string(),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Adds an annotation whose box of size `width`x`height` is in the top left corner of its image, with the area of
+    /// the box and a mask covering the whole image. Its image has to be added first.
+    pub fn ann(self, id: u64, image_id: u64, category_id: u32, width: f64, height: f64) -> Self {
+        self.push_ann(id, image_id, category_id, (width, height), 0)
+    }
+
+    /// Same as [`DatasetBuilder::ann`], for a crowd annotation.
+    pub fn crowd_ann(
+        self,
+        id: u64,
+        image_id: u64,
+        category_id: u32,
+        width: f64,
+        height: f64,
+    ) -> Self {
+        self.push_ann(id, image_id, category_id, (width, height), 1)
+    }
+
+    fn push_ann(
+        mut self,
+        id: u64,
+        image_id: u64,
+        category_id: u32,
+        (width, height): (f64, f64),
+        iscrowd: u32,
+    ) -> Self {
+        let img = self
+            .dataset
+            .images
+            .iter()
+            .find(|img| img.id == image_id)
+            .unwrap();
+        self.dataset.annotations.push(Annotation {
+            id,
+            image_id,
+            category_id,
+            segmentation: Segmentation::Rle(Rle {
+                size: vec![img.height, img.width],
+                counts: vec![img.height * img.width],
+            }),
+            area: width * height,
+            bbox: Bbox {
+                left: 0.0,
+                top: 0.0,
+                width,
+                height,
+            },
+            iscrowd,
+            extra: ExtraFields::default(),
+        });
+        self
+    }
+
+    pub fn cat(mut self, id: u32, name: &str, supercategory: Option<&str>) -> Self {
+        self.dataset.categories.push(Category {
+            id,
+            name: name.to_string(),
+            supercategory: supercategory.map(ToString::to_string),
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn build(self) -> BTreemapDataset {
+        BTreemapDataset::from_dataset(self.dataset, "").unwrap()
+    }
+}
@@ -24,3 +24,64 @@ pub fn load_img(
         .with_context(|| format!("Could not decode the image `{}`.", img_path.display()))?
         .into_rgb8())
 }
+
+/// Apply `f` to each item, in parallel when the `rayon` feature is enabled.
+///
+/// The results are in the same order as the items.
+pub(crate) fn par_map<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        items.into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        items.into_iter().map(f).collect()
+    }
+}
+
+/// Same as [`par_map`] for a fallible `f`.
+///
+/// ## Errors
+///
+/// Will return the error of the first item (in order) for which `f` failed.
+pub(crate) fn try_par_map<T, U, E, F>(items: Vec<T>, f: F) -> Result<Vec<U>, E>
+where
+    T: Send,
+    U: Send,
+    E: Send,
+    F: Fn(T) -> Result<U, E> + Send + Sync,
+{
+    par_map(items, f).into_iter().collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn par_map_keeps_the_order() {
+        let items: Vec<u32> = (0..10_000).collect();
+        let expected: Vec<u64> = items.iter().map(|item| u64::from(*item) * 2).collect();
+        assert_eq!(par_map(items, |item| u64::from(item) * 2), expected);
+    }
+
+    #[test]
+    fn try_par_map_returns_the_first_error() {
+        let items: Vec<u32> = (0..10_000).collect();
+        let result = try_par_map(items, |item| {
+            if item % 1000 == 999 {
+                Err(item)
+            } else {
+                Ok(item)
+            }
+        });
+        assert_eq!(result, Err(999));
+    }
+}
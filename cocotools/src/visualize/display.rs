@@ -3,7 +3,8 @@ use std::path::PathBuf;
 use minifb::{Key, Window, WindowOptions};
 
 use super::draw::{self, DrawOption, ToBuffer};
-use crate::coco::object_detection::{Annotation, BTreemapDataset};
+use crate::coco::backend::DatasetView;
+use crate::coco::object_detection::Annotation;
 use crate::utils;
 
 /// Visualize the annotations for the given image id.
@@ -11,12 +12,20 @@ use crate::utils;
 /// # Errors
 ///
 /// Will return `Err` if `img_id` is not present in the dataset.
-pub fn img_anns(dataset: &BTreemapDataset, img_id: u64, draw_option: DrawOption) -> Result<(), Box<dyn std::error::Error>> {
+pub fn img_anns<D: DatasetView>(
+    dataset: &D,
+    img_id: u64,
+    draw_option: DrawOption,
+) -> Result<(), Box<dyn std::error::Error>> {
     let anns = dataset.get_img_anns(img_id)?;
     let img_name = &dataset.get_img(img_id)?.file_name;
-    let img_path = dataset.image_folder.join(img_name);
+    let img_path = dataset.image_folder().join(img_name);
 
-    self::anns(&img_path, &anns, draw_option)?;
+    self::anns(
+        &img_path,
+        &anns.iter().map(AsRef::as_ref).collect(),
+        draw_option,
+    )?;
 
     Ok(())
 }
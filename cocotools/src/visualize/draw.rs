@@ -6,6 +6,7 @@ use imageproc::{drawing::draw_hollow_rect_mut, rect::Rect};
 use crate::coco::object_detection;
 use crate::errors::MaskError;
 use crate::mask;
+use crate::utils::try_par_map;
 
 /// Draw the bounding box on the image.
 ///
@@ -19,11 +20,11 @@ use crate::mask;
 /// ```rust
 /// # use image::RgbImage;
 /// # use cocotools::coco::object_detection::Bbox;
-/// use cocotools::visualize::draw;
+/// use cocotools::visualize::draw::{self, DrawOption};
 /// let mut img = RgbImage::new(60, 60);
 /// let bbox = Bbox{left: 40.0, top: 40.0, width: 10.0, height: 10.0};
-/// let color = image::Rgb([255, 0, 0]);
-/// draw::bbox(&mut img, &bbox, color);
+/// let draw_option = DrawOption::default().color(image::Rgb([255, 0, 0]));
+/// draw::bbox(&mut img, &bbox, draw_option);
 /// ```
 #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
 pub fn bbox(
@@ -96,7 +97,7 @@ pub fn mask(img: &mut image::RgbImage, mask: &mask::Mask, color: image::Rgb<u8>)
 /// ```rust
 /// # use cocotools::coco::object_detection;
 /// # use image::RgbImage;
-/// use cocotools::visualize::draw;
+/// use cocotools::visualize::draw::{self, DrawOption};
 /// let mut img = RgbImage::new(40, 40);
 /// let anns = vec![
 ///     object_detection::Annotation {
@@ -116,6 +117,7 @@ pub fn mask(img: &mut image::RgbImage, mask: &mask::Mask, color: image::Rgb<u8>)
 ///             height: 20.0,
 ///         },
 ///         iscrowd: 0,
+///         extra: Default::default(),
 ///     },
 ///     object_detection::Annotation {
 ///         id: 2,
@@ -133,9 +135,11 @@ pub fn mask(img: &mut image::RgbImage, mask: &mask::Mask, color: image::Rgb<u8>)
 ///             height: 24.0,
 ///         },
 ///         iscrowd: 0,
+///         extra: Default::default(),
 ///     },
 /// ];
-/// draw::anns(&mut img, &anns.iter().collect(), true);
+/// draw::anns(&mut img, &anns.iter().collect(), DrawOption::default().with_bbox(true).with_mask(true))?;
+/// # Ok::<(), cocotools::errors::MaskError>(())
 /// ```
 ///
 /// ## Errors
@@ -146,15 +150,20 @@ pub fn anns(
     anns: &Vec<&object_detection::Annotation>,
     mut draw_option: DrawOption,
 ) -> Result<(), MaskError> {
-    for ann in anns {
+    let with_mask = draw_option.with_mask;
+    let masks = try_par_map(anns.clone(), |ann| {
+        with_mask
+            .then(|| mask::Mask::try_from(&ann.segmentation))
+            .transpose()
+    })?;
+    for (ann, mask) in anns.iter().zip(masks) {
         let color = image::Rgb(get_color(ann.id as usize).into());
         draw_option = draw_option.color(color);
 
         if draw_option.with_bbox {
             self::bbox(img, &ann.bbox, draw_option);
         }
-        if draw_option.with_mask {
-            let mask = mask::Mask::try_from(&ann.segmentation)?;
+        if let Some(mask) = mask {
             self::mask(img, &mask, draw_option.color);
         }
     }
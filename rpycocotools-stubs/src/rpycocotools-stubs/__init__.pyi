@@ -1,7 +1,11 @@
 __version__ = "0.0.7"  # noqa: Y052, RUF100
 
+from collections.abc import Sequence
+
 import numpy as np
 import numpy.typing as npt
+import pandas
+import pyarrow
 from typing_extensions import Self
 
 from . import mask  # noqa: F401  # pyright: ignore[reportUnusedImport]
@@ -9,6 +13,9 @@ from .anns import _AnnotationAny, Category, Image
 
 class COCO:
     def __init__(self: Self, annotation_path: str, image_folder_path: str) -> None: ...
+    @staticmethod
+    def load_cached(annotation_path: str, image_folder_path: str, cache_dir: str) -> COCO:
+        """Load the dataset from a binary cache kept in `cache_dir`, rebuilt when the annotation file changes."""
     def get_ann(self: Self, ann_id: int) -> _AnnotationAny: ...
     def get_anns(self: Self) -> list[_AnnotationAny]: ...
     def get_cat(self: Self, cat_id: int) -> Category: ...
@@ -16,10 +23,87 @@ class COCO:
     def get_img(self: Self, img_id: int) -> Image: ...
     def get_imgs(self: Self) -> list[Image]: ...
     def get_img_anns(self: Self, img_id: int) -> list[_AnnotationAny]: ...
+    def get_ann_ids(
+        self: Self,
+        img_ids: int | Sequence[int] | None = None,
+        cat_ids: int | Sequence[int] | None = None,
+        area_rng: Sequence[float] | None = None,
+        iscrowd: int | None = None,
+    ) -> list[int]:
+        """Return the ids of the annotations satisfying all the given conditions."""
+        ...
+    def get_cat_ids(
+        self: Self,
+        cat_nms: str | Sequence[str] | None = None,
+        sup_nms: str | Sequence[str] | None = None,
+        cat_ids: int | Sequence[int] | None = None,
+    ) -> list[int]:
+        """Return the ids of the categories satisfying all the given conditions."""
+        ...
+    def get_img_ids(
+        self: Self,
+        img_ids: int | Sequence[int] | None = None,
+        cat_ids: int | Sequence[int] | None = None,
+    ) -> list[int]:
+        """Return the ids of the images containing at least one annotation of each of the given categories."""
+        ...
+    def getAnnIds(  # noqa: N802
+        self: Self,
+        imgIds: int | Sequence[int] | None = None,  # noqa: N803
+        catIds: int | Sequence[int] | None = None,  # noqa: N803
+        areaRng: Sequence[float] | None = None,  # noqa: N803
+        iscrowd: int | None = None,
+    ) -> list[int]: ...
+    def getCatIds(  # noqa: N802
+        self: Self,
+        catNms: str | Sequence[str] | None = None,  # noqa: N803
+        supNms: str | Sequence[str] | None = None,  # noqa: N803
+        catIds: int | Sequence[int] | None = None,  # noqa: N803
+    ) -> list[int]: ...
+    def getImgIds(  # noqa: N802
+        self: Self,
+        imgIds: int | Sequence[int] | None = None,  # noqa: N803
+        catIds: int | Sequence[int] | None = None,  # noqa: N803
+    ) -> list[int]: ...
+    def next_img_id(self: Self) -> int: ...
+    def next_ann_id(self: Self) -> int: ...
+    def next_cat_id(self: Self) -> int: ...
+    def add_img(self: Self, img: Image) -> None: ...
+    def update_img(self: Self, img: Image) -> Image:
+        """Replace the image with the same id and return the previous one."""
+        ...
+    def remove_img(self: Self, img_id: int) -> tuple[Image, list[_AnnotationAny]]:
+        """Remove the image and its annotations from the dataset, and return them."""
+        ...
+    def add_ann(self: Self, ann: _AnnotationAny) -> None: ...
+    def update_ann(self: Self, ann: _AnnotationAny) -> _AnnotationAny:
+        """Replace the annotation with the same id and return the previous one."""
+        ...
+    def remove_ann(self: Self, ann_id: int) -> _AnnotationAny: ...
+    def add_cat(self: Self, cat: Category) -> None: ...
+    def update_cat(self: Self, cat: Category) -> Category:
+        """Replace the category with the same id and return the previous one."""
+        ...
+    def remove_cat(self: Self, cat_id: int) -> tuple[Category, list[_AnnotationAny]]:
+        """Remove the category and its annotations from the dataset, and return them."""
+        ...
     def visualize_img(self: Self, img_id: int) -> None: ...
     def draw_anns(self: Self, img_id: int, draw_bboxes: bool) -> npt.NDArray[np.uint8]:
         """Draw the annotations on the image and returns it as a (RGB) numpy array."""
         ...
+    def to_numpy(self: Self) -> dict[str, npt.NDArray[np.uint64 | np.uint32 | np.float64] | list[str]]:
+        """Return the annotations as a dict of columns, built in Rust without creating a Python object per annotation.
+
+        The columns are `id`, `image_id`, `category_id`, `area`, `bbox_x`, `bbox_y`, `bbox_w`, `bbox_h`, `iscrowd`,
+        `segmentation_height`, `segmentation_width` (numpy arrays) and `segmentation_counts` (COCO RLE counts).
+        """
+        ...
+    def to_arrow(self: Self) -> pyarrow.Table:
+        """Return the annotations as a `pyarrow.Table` (see `to_numpy`), the numeric columns are not copied."""
+        ...
+    def to_pandas(self: Self) -> pandas.DataFrame:
+        """Return the annotations as a `pandas.DataFrame` (see `to_numpy`)."""
+        ...
     def json(self: Self) -> str:
         """Return the dataset as a json string."""
         ...
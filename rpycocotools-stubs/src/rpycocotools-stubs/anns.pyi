@@ -1,5 +1,5 @@
 from collections.abc import Iterator, Sequence
-from typing import Generic, TypeAlias, TypeVar
+from typing import Any, Generic, TypeAlias, TypeVar
 
 from typing_extensions import Self
 
@@ -15,6 +15,8 @@ class Annotation(Generic[_TSegmentation]):
     area: float
     bbox: BBox
     iscrowd: int
+    extra: dict[str, Any]
+    """Fields of the annotation that are not part of the COCO format."""
 
     def __init__(
         self: Self,
@@ -35,10 +37,21 @@ _AnnotationAny: TypeAlias = (
     | Annotation[COCO_RLE]
 )
 
+class Frequency:
+    """Frequency group of a LVIS category."""
+    Rare: Frequency
+    Common: Frequency
+    Frequent: Frequency
+
 class Category:
     id: int
     name: str
     supercategory: str
+    frequency: Frequency | None
+    """LVIS only."""
+    synonyms: list[str] | None
+    """LVIS only."""
+    extra: dict[str, Any]
     def __init__(self: Self, id: int, name: str, supercategory: str) -> None: ...
 
 class BBox:
@@ -54,6 +67,11 @@ class Image:
     width: int
     height: int
     file_name: str
+    neg_category_ids: list[int] | None
+    """LVIS: categories verified to not be present in the image."""
+    not_exhaustive_category_ids: list[int] | None
+    """LVIS: categories present in the image, but not exhaustively annotated."""
+    extra: dict[str, Any]
     def __init__(self: Self, id: int, width: int, height: int, file_name: str) -> None: ...
 
 Polygons: TypeAlias = list[list[float]]
@@ -58,17 +58,26 @@ def encode(mask: npt.NDArray[np.uint8],
 @overload
 def encode(mask: npt.NDArray[np.uint8],
            target: Literal["polygons"],
+           tolerance: float = 0.0,
+           max_vertices: int | None = None,
+           smoothing: int = 0,
            ) -> Polygons:
     ...
 
 def encode(mask: npt.NDArray[np.uint8],
            target: Literal["polygons", "rle", "coco_rle", "polygons_rs"],
+           tolerance: float = 0.0,
+           max_vertices: int | None = None,
+           smoothing: int = 0,
            ) -> Polygons | RLE | COCO_RLE | PolygonsRS:
     """Encode/compress a mask into the desired format.
 
     Args:
         mask: The mask to encode, it should be a 2 dimensional array.
         target: The desired format for the encoded mask.
+        tolerance: Only used for polygons, tolerance (in pixels) of the Douglas–Peucker simplification.
+        max_vertices: Only used for polygons, maximum number of vertices of each polygon.
+        smoothing: Only used for polygons, number of smoothing iterations (gives sub-pixel vertices).
 
     Returns:
         The encoded mask.
@@ -37,12 +37,18 @@ def decode(encoded_mask: anns.RLE | anns.COCO_RLE | anns.PolygonsRS | anns.Polyg
 
 def encode(mask: npt.NDArray[np.uint8],
            target: Literal["rle", "coco_rle", "polygons", "polygon_rs"],
+           tolerance: float = 0.0,
+           max_vertices: None | int = None,
+           smoothing: int = 0,
            ) -> anns.RLE | anns.COCO_RLE | anns.PolygonsRS | anns.Polygons:
     """Decode an encoded mask.
 
     Args:
         mask: The mask to encode, it should be a 2 dimensional array.
         target: The desired format for the encoded mask.
+        tolerance: Only used for polygons, tolerance (in pixels) of the Douglas–Peucker simplification.
+        max_vertices: Only used for polygons, maximum number of vertices of each polygon.
+        smoothing: Only used for polygons, number of smoothing iterations (gives sub-pixel vertices).
 
     Returns:
         The encoded mask.
@@ -53,7 +59,7 @@ def encode(mask: npt.NDArray[np.uint8],
         case "coco_rle":
             encoded_mask = _mask.encode_to_coco_rle(mask)
         case "polygons":
-            encoded_mask = _mask.encode_to_polygons(mask)
+            encoded_mask = _mask.encode_to_polygons(mask, tolerance, max_vertices, smoothing)
         case _:  # "polygons_rs"
             encoded_mask = _mask.encode_to_polygons_rs(mask)
     return encoded_mask
@@ -2,16 +2,18 @@ use std::path::PathBuf;
 
 use cocotools::coco::object_detection;
 use cocotools::errors::CocoError;
+use cocotools::mask::conversions::encode_coco_rles;
 use cocotools::visualize::display;
+use cocotools::visualize::draw::DrawOption;
 use cocotools::COCO;
 use nshare::ToNdarray3;
 use numpy::IntoPyArray;
-use numpy::PyArray3;
+use numpy::{Element, PyArray1, PyArray3};
 use pyo3::exceptions::{PyKeyError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::PyUnicode;
+use pyo3::types::{PyDict, PyList, PyUnicode};
 
-use crate::errors::{PyLoadingError, PyMissingIdError};
+use crate::errors::{PyCacheError, PyLoadingError, PyMaskError, PyMissingIdError, PyMutationError};
 
 #[pyclass(name = "COCO", module = "rpycocotools")]
 #[derive(Debug)]
@@ -32,6 +34,21 @@ impl PyCOCO {
         Ok(Self(dataset))
     }
 
+    /// Load the dataset from a binary cache kept in `cache_dir`, rebuilt when the annotation file changes.
+    #[staticmethod]
+    fn load_cached(
+        annotations_path: &PyUnicode,
+        image_folder_path: &PyUnicode,
+        cache_dir: &PyUnicode,
+    ) -> PyResult<Self> {
+        let annotations_path = PathBuf::from(annotations_path.to_str()?);
+        let image_folder_path = PathBuf::from(image_folder_path.to_str()?);
+        let cache_dir = PathBuf::from(cache_dir.to_str()?);
+        let dataset = COCO::load_cached(annotations_path, image_folder_path, cache_dir)
+            .map_err(PyCacheError::from)?;
+        Ok(Self(dataset))
+    }
+
     fn __len__(&self) -> usize {
         self.0.get_imgs().len()
     }
@@ -104,6 +121,251 @@ impl PyCOCO {
             .collect()
     }
 
+    /// Return the ids of the annotations satisfying all the given conditions.
+    ///
+    /// Ids can be given as a single id or as a list of ids, and an empty list is not used as a condition.
+    /// The area must be strictly within `area_rng`, given as `[min, max]`.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if the ids or the area range are invalid.
+    #[pyo3(signature = (img_ids=None, cat_ids=None, area_rng=None, iscrowd=None))]
+    fn get_ann_ids(
+        &self,
+        img_ids: Option<&PyAny>,
+        cat_ids: Option<&PyAny>,
+        area_rng: Option<Vec<f64>>,
+        iscrowd: Option<u32>,
+    ) -> PyResult<Vec<u64>> {
+        let area_range = match area_rng.as_deref() {
+            None | Some([]) => None,
+            Some([min_area, max_area]) => Some((*min_area, *max_area)),
+            Some(_) => {
+                return Err(PyValueError::new_err(
+                    "The area range must contain exactly two values",
+                ))
+            }
+        };
+        Ok(self.0.get_ann_ids(
+            &extract_ids(img_ids)?,
+            &extract_ids(cat_ids)?,
+            area_range,
+            iscrowd.map(|iscrowd| iscrowd != 0),
+        ))
+    }
+
+    /// Return the ids of the categories satisfying all the given conditions.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if the names or ids are invalid.
+    #[pyo3(signature = (cat_nms=None, sup_nms=None, cat_ids=None))]
+    fn get_cat_ids(
+        &self,
+        cat_nms: Option<&PyAny>,
+        sup_nms: Option<&PyAny>,
+        cat_ids: Option<&PyAny>,
+    ) -> PyResult<Vec<u32>> {
+        let cat_nms: Vec<String> = extract_ids(cat_nms)?;
+        let sup_nms: Vec<String> = extract_ids(sup_nms)?;
+        Ok(self.0.get_cat_ids(
+            &cat_nms.iter().map(String::as_str).collect::<Vec<_>>(),
+            &sup_nms.iter().map(String::as_str).collect::<Vec<_>>(),
+            &extract_ids(cat_ids)?,
+        ))
+    }
+
+    /// Return the ids of the images containing at least one annotation of each of the given categories.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if the ids are invalid.
+    #[pyo3(signature = (img_ids=None, cat_ids=None))]
+    fn get_img_ids(&self, img_ids: Option<&PyAny>, cat_ids: Option<&PyAny>) -> PyResult<Vec<u64>> {
+        Ok(self
+            .0
+            .get_img_ids(&extract_ids(img_ids)?, &extract_ids(cat_ids)?))
+    }
+
+    /// Alias of `get_ann_ids` with the pycocotools arguments names.
+    #[allow(non_snake_case)]
+    #[pyo3(name = "getAnnIds", signature = (imgIds=None, catIds=None, areaRng=None, iscrowd=None))]
+    fn get_ann_ids_pycocotools(
+        &self,
+        imgIds: Option<&PyAny>,
+        catIds: Option<&PyAny>,
+        areaRng: Option<Vec<f64>>,
+        iscrowd: Option<u32>,
+    ) -> PyResult<Vec<u64>> {
+        self.get_ann_ids(imgIds, catIds, areaRng, iscrowd)
+    }
+
+    /// Alias of `get_cat_ids` with the pycocotools arguments names.
+    #[allow(non_snake_case)]
+    #[pyo3(name = "getCatIds", signature = (catNms=None, supNms=None, catIds=None))]
+    fn get_cat_ids_pycocotools(
+        &self,
+        catNms: Option<&PyAny>,
+        supNms: Option<&PyAny>,
+        catIds: Option<&PyAny>,
+    ) -> PyResult<Vec<u32>> {
+        self.get_cat_ids(catNms, supNms, catIds)
+    }
+
+    /// Alias of `get_img_ids` with the pycocotools arguments names.
+    #[allow(non_snake_case)]
+    #[pyo3(name = "getImgIds", signature = (imgIds=None, catIds=None))]
+    fn get_img_ids_pycocotools(
+        &self,
+        imgIds: Option<&PyAny>,
+        catIds: Option<&PyAny>,
+    ) -> PyResult<Vec<u64>> {
+        self.get_img_ids(imgIds, catIds)
+    }
+
+    fn next_img_id(&self) -> u64 {
+        self.0.next_img_id()
+    }
+
+    fn next_ann_id(&self) -> u64 {
+        self.0.next_ann_id()
+    }
+
+    fn next_cat_id(&self) -> u32 {
+        self.0.next_cat_id()
+    }
+
+    /// Add an image to the dataset.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is already an image with the same id in the dataset.
+    fn add_img(&mut self, img: object_detection::Image) -> PyResult<()> {
+        self.0.add_img(img).map_err(PyMutationError::from)?;
+        Ok(())
+    }
+
+    /// Replace the image with the same id and return the previous one.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    fn update_img(
+        &mut self,
+        py: Python<'_>,
+        img: object_detection::Image,
+    ) -> PyResult<Py<object_detection::Image>> {
+        Py::new(py, self.0.update_img(img).map_err(PyMissingIdError::from)?)
+    }
+
+    /// Remove the image and its annotations from the dataset, and return them.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no image with this id in the dataset.
+    fn remove_img(
+        &mut self,
+        py: Python<'_>,
+        img_id: u64,
+    ) -> PyResult<(
+        Py<object_detection::Image>,
+        Vec<Py<object_detection::Annotation>>,
+    )> {
+        let (img, anns) = self.0.remove_img(img_id).map_err(PyMissingIdError::from)?;
+        Ok((
+            Py::new(py, img)?,
+            anns.into_iter()
+                .map(|ann| Py::new(py, ann))
+                .collect::<PyResult<_>>()?,
+        ))
+    }
+
+    /// Add an annotation to the dataset.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is already an annotation with the same id in the dataset,
+    /// or if its image or category is not in the dataset.
+    fn add_ann(&mut self, ann: object_detection::Annotation) -> PyResult<()> {
+        self.0.add_ann(ann).map_err(PyMutationError::from)?;
+        Ok(())
+    }
+
+    /// Replace the annotation with the same id and return the previous one.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset,
+    /// or if the image or category of the new annotation is not in the dataset.
+    fn update_ann(
+        &mut self,
+        py: Python<'_>,
+        ann: object_detection::Annotation,
+    ) -> PyResult<Py<object_detection::Annotation>> {
+        Py::new(py, self.0.update_ann(ann).map_err(PyMutationError::from)?)
+    }
+
+    /// Remove the annotation from the dataset and return it.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no annotation with this id in the dataset.
+    fn remove_ann(
+        &mut self,
+        py: Python<'_>,
+        ann_id: u64,
+    ) -> PyResult<Py<object_detection::Annotation>> {
+        Py::new(
+            py,
+            self.0.remove_ann(ann_id).map_err(PyMissingIdError::from)?,
+        )
+    }
+
+    /// Add a category to the dataset.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is already a category with the same id in the dataset.
+    fn add_cat(&mut self, cat: object_detection::Category) -> PyResult<()> {
+        self.0.add_cat(cat).map_err(PyMutationError::from)?;
+        Ok(())
+    }
+
+    /// Replace the category with the same id and return the previous one.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    fn update_cat(
+        &mut self,
+        py: Python<'_>,
+        cat: object_detection::Category,
+    ) -> PyResult<Py<object_detection::Category>> {
+        Py::new(py, self.0.update_cat(cat).map_err(PyMissingIdError::from)?)
+    }
+
+    /// Remove the category and its annotations from the dataset, and return them.
+    ///
+    /// ## Errors
+    ///
+    /// Will return `Err` if there is no category with this id in the dataset.
+    fn remove_cat(
+        &mut self,
+        py: Python<'_>,
+        cat_id: u32,
+    ) -> PyResult<(
+        Py<object_detection::Category>,
+        Vec<Py<object_detection::Annotation>>,
+    )> {
+        let (cat, anns) = self.0.remove_cat(cat_id).map_err(PyMissingIdError::from)?;
+        Ok((
+            Py::new(py, cat)?,
+            anns.into_iter()
+                .map(|ann| Py::new(py, ann))
+                .collect::<PyResult<_>>()?,
+        ))
+    }
+
     /// Visualize an image and its annotations.
     ///
     /// ## Errors
@@ -112,11 +374,15 @@ impl PyCOCO {
     pub fn visualize_img(&self, img_id: u64) -> PyResult<()> {
         let img = self
             .0
-            .draw_img_anns(img_id, true)
+            .draw_img_anns(
+                img_id,
+                DrawOption::default().with_bbox(true).with_mask(true),
+            )
             .map_err(|err| match err {
                 CocoError::MissingId(err) => PyKeyError::new_err(err.to_string()),
                 CocoError::Mask(err) => PyValueError::new_err(err.to_string()),
                 CocoError::Loading(err) => PyValueError::new_err(err.to_string()),
+                CocoError::Mutation(err) => PyMutationError::from(err).into(),
             })?;
 
         let file_name = &self
@@ -143,11 +409,15 @@ impl PyCOCO {
     ) -> PyResult<&'a PyArray3<u8>> {
         let img = self
             .0
-            .draw_img_anns(img_id, draw_bboxes)
+            .draw_img_anns(
+                img_id,
+                DrawOption::default().with_bbox(draw_bboxes).with_mask(true),
+            )
             .map_err(|err| match err {
                 CocoError::MissingId(err) => PyKeyError::new_err(err.to_string()),
                 CocoError::Mask(err) => PyValueError::new_err(err.to_string()),
                 CocoError::Loading(err) => PyValueError::new_err(err.to_string()),
+                CocoError::Mutation(err) => PyMutationError::from(err).into(),
             })?;
 
         let img = img
@@ -157,6 +427,46 @@ impl PyCOCO {
         Ok(img)
     }
 
+    /// Return the annotations as a dict of columns, built in Rust without creating a Python object per annotation.
+    ///
+    /// The numeric columns are numpy arrays, and the segmentations are encoded as COCO RLE with their counts in
+    /// `segmentation_counts`.
+    fn to_numpy<'a>(&self, py: Python<'a>) -> PyResult<&'a PyDict> {
+        let anns = self.0.get_anns();
+        let rles = encode_coco_rles(anns.iter().map(|ann| &ann.segmentation).collect())
+            .map_err(PyMaskError::from)?;
+
+        let columns = PyDict::new(py);
+        columns.set_item("id", column(py, &anns, |ann| ann.id))?;
+        columns.set_item("image_id", column(py, &anns, |ann| ann.image_id))?;
+        columns.set_item("category_id", column(py, &anns, |ann| ann.category_id))?;
+        columns.set_item("area", column(py, &anns, |ann| ann.area))?;
+        columns.set_item("bbox_x", column(py, &anns, |ann| ann.bbox.left))?;
+        columns.set_item("bbox_y", column(py, &anns, |ann| ann.bbox.top))?;
+        columns.set_item("bbox_w", column(py, &anns, |ann| ann.bbox.width))?;
+        columns.set_item("bbox_h", column(py, &anns, |ann| ann.bbox.height))?;
+        columns.set_item("iscrowd", column(py, &anns, |ann| ann.iscrowd))?;
+        columns.set_item("segmentation_height", column(py, &rles, |rle| rle.size[0]))?;
+        columns.set_item("segmentation_width", column(py, &rles, |rle| rle.size[1]))?;
+        columns.set_item(
+            "segmentation_counts",
+            PyList::new(py, rles.iter().map(|rle| rle.counts.as_str())),
+        )?;
+        Ok(columns)
+    }
+
+    /// Return the annotations as a `pyarrow.Table` (see `to_numpy`), the numeric columns are not copied.
+    fn to_arrow<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        let columns = self.to_numpy(py)?;
+        py.import("pyarrow")?.call_method1("table", (columns,))
+    }
+
+    /// Return the annotations as a `pandas.DataFrame` (see `to_numpy`).
+    fn to_pandas<'a>(&self, py: Python<'a>) -> PyResult<&'a PyAny> {
+        let columns = self.to_numpy(py)?;
+        py.import("pandas")?.call_method1("DataFrame", (columns,))
+    }
+
     fn json(&self) -> PyResult<String> {
         self.0
             .json()
@@ -164,6 +474,22 @@ impl PyCOCO {
     }
 }
 
+/// Numpy array with the value of each item, the vector is moved to numpy without copying it.
+fn column<'a, T, U: Element>(py: Python<'a>, items: &[T], f: impl Fn(&T) -> U) -> &'a PyArray1<U> {
+    items.iter().map(f).collect::<Vec<_>>().into_pyarray(py)
+}
+
+/// Extract values given either as a single value or as a list of values, like in pycocotools.
+fn extract_ids<'a, T: FromPyObject<'a>>(ids: Option<&'a PyAny>) -> PyResult<Vec<T>> {
+    match ids {
+        None => Ok(Vec::new()),
+        Some(ids) => ids
+            .extract::<T>()
+            .map(|id| vec![id])
+            .or_else(|_| ids.extract()),
+    }
+}
+
 /// Construct a COCO dataset from its components and the image folder.
 ///
 /// # Errors
@@ -3,7 +3,9 @@
 use pyo3::exceptions::{PyKeyError, PyValueError};
 use pyo3::prelude::*;
 
-use crate::cocotools::errors::{LoadingError, MaskError, MissingIdError};
+use crate::cocotools::errors::{
+    CacheError, LoadingError, MaskError, MissingIdError, MutationError,
+};
 
 pub struct PyLoadingError(LoadingError);
 
@@ -19,6 +21,20 @@ impl From<PyLoadingError> for PyErr {
     }
 }
 
+pub struct PyCacheError(CacheError);
+
+impl From<CacheError> for PyCacheError {
+    fn from(error: CacheError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<PyCacheError> for PyErr {
+    fn from(error: PyCacheError) -> Self {
+        PyValueError::new_err(error.0.to_string())
+    }
+}
+
 pub struct PyMissingIdError(MissingIdError);
 
 impl From<MissingIdError> for PyMissingIdError {
@@ -46,3 +62,20 @@ impl From<PyMaskError> for PyErr {
         PyValueError::new_err(error.0.to_string())
     }
 }
+
+pub struct PyMutationError(MutationError);
+
+impl From<MutationError> for PyMutationError {
+    fn from(error: MutationError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<PyMutationError> for PyErr {
+    fn from(error: PyMutationError) -> Self {
+        match error.0 {
+            MutationError::MissingId(err) => PyKeyError::new_err(err.to_string()),
+            err => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
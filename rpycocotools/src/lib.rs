@@ -12,6 +12,7 @@ fn anns(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
     module.add_class::<cocotools::coco::object_detection::Annotation>()?;
     module.add_class::<cocotools::coco::object_detection::Bbox>()?;
     module.add_class::<cocotools::coco::object_detection::Category>()?;
+    module.add_class::<cocotools::coco::object_detection::Frequency>()?;
     module.add_class::<coco::PyPolygons>()?;
     module.add_class::<cocotools::coco::object_detection::PolygonsRS>()?;
     module.add_class::<cocotools::coco::object_detection::Rle>()?;
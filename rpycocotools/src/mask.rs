@@ -146,13 +146,24 @@ fn encode_to_coco_rle(
 }
 
 #[pyfunction]
+#[pyo3(signature = (uncompressed_mask, tolerance=0.0, max_vertices=None, smoothing=0))]
 #[allow(clippy::needless_pass_by_value)]
 fn encode_to_polygons(
     py: Python<'_>,
     uncompressed_mask: PyReadonlyArray2<u8>,
+    tolerance: f64,
+    max_vertices: Option<usize>,
+    smoothing: u32,
 ) -> PyResult<Py<PyPolygons>> {
     let uncompressed_mask = uncompressed_mask.to_owned_array();
-    let encoded_mask = PyPolygons(conversions::poly_from_mask(&uncompressed_mask));
+    let polygon_option = conversions::PolygonOption::default()
+        .tolerance(tolerance)
+        .max_vertices(max_vertices)
+        .smoothing(smoothing);
+    let encoded_mask = PyPolygons(conversions::poly_from_mask(
+        &uncompressed_mask,
+        polygon_option,
+    ));
     Py::new(py, encoded_mask)
 }
 
@@ -173,8 +184,9 @@ fn area_rle(rle: object_detection::Rle) -> u32 {
 }
 
 #[pyfunction]
-fn area_coco_rle(coco_rle: object_detection::CocoRle) -> u32 {
-    coco_rle.area()
+fn area_coco_rle(coco_rle: object_detection::CocoRle) -> PyResult<u32> {
+    let rle = object_detection::Rle::try_from(&coco_rle).map_err(PyMaskError::from)?;
+    Ok(rle.area())
 }
 
 #[pyfunction]
@@ -193,8 +205,8 @@ fn rle_to_bbox(rle: object_detection::Rle) -> object_detection::Bbox {
 }
 
 #[pyfunction]
-fn coco_rle_to_bbox(coco_rle: object_detection::CocoRle) -> object_detection::Bbox {
-    object_detection::Bbox::from(&coco_rle)
+fn coco_rle_to_bbox(coco_rle: object_detection::CocoRle) -> PyResult<object_detection::Bbox> {
+    Ok(object_detection::Bbox::try_from(&coco_rle).map_err(PyMaskError::from)?)
 }
 
 #[pyfunction]
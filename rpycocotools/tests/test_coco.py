@@ -1,3 +1,7 @@
+from pathlib import Path
+
+import numpy as np
+import pytest
 from hypothesis import given
 from hypothesis import strategies as st
 
@@ -21,6 +25,12 @@ def test_access_cat(coco_dataset: rpycocotools.COCO) -> None:
     assert str(cat) == "Category(id=2, name='bicycle', supercategory='vehicle')"
 
 
+def test_cat_without_supercategory() -> None:
+    cat = rpycocotools.anns.Category(id=1, name="person")
+    assert cat.supercategory is None
+    assert str(cat) == "Category(id=1, name='person', supercategory=None)"
+
+
 def test_access_anns(coco_dataset: rpycocotools.COCO) -> None:
     anns = coco_dataset.get_anns()
     assert len(anns) == 45
@@ -105,3 +115,64 @@ def test_dataset_from_components() -> None:
     ]
     coco_dataset = rpycocotools.anns.from_dataset(imgs, anns, cats, "a")
     assert coco_dataset.get_img_anns(1)[0] == expected_ann
+
+
+def test_get_ids(coco_dataset: rpycocotools.COCO) -> None:
+    assert coco_dataset.get_img_ids() == sorted(img.id for img in coco_dataset.get_imgs())
+    assert coco_dataset.getCatIds(catNms=["bicycle"]) == [2]
+    img_ids = coco_dataset.getImgIds(catIds=2)
+    assert img_ids
+    ann_ids = coco_dataset.getAnnIds(imgIds=img_ids, catIds=[2], iscrowd=None)
+    assert all(coco_dataset.get_ann(ann_id).category_id == 2 for ann_id in ann_ids)
+    assert coco_dataset.get_ann_ids(img_ids=480985) == sorted(ann.id for ann in coco_dataset.get_img_anns(480985))
+
+
+def test_mutations(coco_dataset: rpycocotools.COCO) -> None:
+    img_id = coco_dataset.next_img_id()
+    coco_dataset.add_img(rpycocotools.anns.Image(img_id, 640, 480, "new.jpg"))
+    ann = coco_dataset.get_ann(1348739)
+    ann.id = coco_dataset.next_ann_id()
+    ann.image_id = img_id
+    coco_dataset.add_ann(ann)
+    assert coco_dataset.get_img_ids(cat_ids=3)[-1] == img_id
+    img, anns = coco_dataset.remove_img(img_id)
+    assert img.id == img_id
+    assert [removed_ann.id for removed_ann in anns] == [ann.id]
+    assert coco_dataset.get_ann_ids(img_ids=img_id) == []
+
+
+def test_load_cached(tmp_path: Path) -> None:
+    annotations_path = "../data_samples/coco_25k/annotations.json"
+    image_folder = "../data_samples/coco_25k/images"
+    dataset = rpycocotools.COCO.load_cached(annotations_path, image_folder, str(tmp_path))
+    assert len(list(tmp_path.glob("*.cocobin"))) == 1
+    cached_dataset = rpycocotools.COCO.load_cached(annotations_path, image_folder, str(tmp_path))
+    assert cached_dataset.json() == dataset.json() == rpycocotools.COCO(annotations_path, image_folder).json()
+
+
+def test_to_numpy(coco_dataset: rpycocotools.COCO) -> None:
+    columns = coco_dataset.to_numpy()
+    anns = coco_dataset.get_anns()
+    assert all(len(column) == len(anns) for column in columns.values())
+    assert columns["id"].dtype == np.uint64
+    assert columns["id"].tolist() == [ann.id for ann in anns]
+    assert columns["bbox_w"].tolist() == [ann.bbox.width for ann in anns]
+    assert columns["iscrowd"].tolist() == [ann.iscrowd for ann in anns]
+    ann = anns[0]
+    rle = rpycocotools.mask.encode(rpycocotools.mask.decode(ann.segmentation), target="coco_rle")
+    assert columns["segmentation_counts"][0] == rle.counts
+    assert [columns["segmentation_height"][0], columns["segmentation_width"][0]] == rle.size
+
+
+def test_to_arrow(coco_dataset: rpycocotools.COCO) -> None:
+    pytest.importorskip("pyarrow")
+    table = coco_dataset.to_arrow()
+    assert table.num_rows == len(coco_dataset.get_anns())
+    assert table.column("category_id").to_pylist() == coco_dataset.to_numpy()["category_id"].tolist()
+
+
+def test_to_pandas(coco_dataset: rpycocotools.COCO) -> None:
+    pytest.importorskip("pandas")
+    dataframe = coco_dataset.to_pandas()
+    assert len(dataframe) == len(coco_dataset.get_anns())
+    assert dataframe["image_id"].tolist() == coco_dataset.to_numpy()["image_id"].tolist()